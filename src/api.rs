@@ -1,33 +1,229 @@
 use actix_web::middleware::Logger;
-use actix_web::{get, post, App, HttpResponse, HttpServer, Responder};
+use actix_web::{get, post, web, App, HttpResponse, HttpServer, Responder};
 use serde::Serialize;
 
 use crate::sim;
 use crate::sim::cash::Account;
+use crate::sim::compare::compare;
+use crate::sim::export::{write_frame, Format};
+use crate::sim::monte_carlo::{run_monte_carlo, MonteCarloResult};
+use crate::sim::report::render;
+use crate::sim::scenario::Scenario;
+use crate::sim::statement::statements;
+use crate::sim::terminal::Verbosity;
+use crate::sim::variance::Period;
+use crate::sim::validate::{check, validate_document, validate_str, ValidationError};
+use crate::Error;
 
 #[get("/")]
 async fn index() -> impl Responder {
     HttpResponse::Ok().body("Hello, world!")
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-struct ScenarioQuery {
+#[derive(Serialize)]
+struct ScenarioResponse {
     scenario: String,
+    results: sim::SimulationResult,
+    monte_carlo: Option<MonteCarloResult>,
 }
 
-#[derive(Debug, Serialize)]
-struct ScenarioResponse {
-    scenario: String,
-    // Add more fields as needed
+// Inputs that don't make sense are the client's fault, anything else is ours.
+fn error_response(e: Error) -> HttpResponse {
+    match e {
+        Error::Validation(errors) => HttpResponse::BadRequest().json(errors),
+        Error::Invalid(message) => HttpResponse::BadRequest().body(message),
+        e => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+// Simulations run on the blocking thread pool so that a long one doesn't hold up the
+// server's other requests.
+async fn blocking<T, F>(f: F) -> Result<T, HttpResponse>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    match web::block(f).await {
+        Ok(result) => result.map_err(error_response),
+        Err(e) => Err(HttpResponse::InternalServerError().body(e.to_string())),
+    }
 }
 
 #[post("/results")]
 async fn get_results(account: String) -> impl Responder {
-    let account: Account = serde_json::from_str(&account).unwrap();
-    let response = sim::run_simulation(account, None, false);
+    let account: Account = match serde_json::from_str(&account) {
+        Ok(a) => a,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(vec![ValidationError {
+                file: None,
+                line: Some(e.line()),
+                path: String::new(),
+                message: e.to_string(),
+            }])
+        }
+    };
+    let errors = check(&Scenario::from_account(account.clone(), None));
+    if !errors.is_empty() {
+        return HttpResponse::BadRequest().json(errors);
+    }
+    match blocking(move || sim::run_simulation(account, None, Verbosity::Quiet)).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(response) => response,
+    }
+}
+
+// Validates and runs a posted scenario with its variables overridden by the query.
+fn simulate(
+    query: &std::collections::BTreeMap<String, String>,
+    scenario: &str,
+) -> Result<(Scenario, sim::SimulationResult), Error> {
+    let overrides = query
+        .iter()
+        .map(|(k, v)| (k.clone(), sim::expr::parse_override(v)))
+        .collect();
+    let scenario = validate_str(scenario).map_err(Error::Validation)?;
+    let scenario = scenario.with_variables(&overrides)?;
+    let errors = check(&scenario);
+    if !errors.is_empty() {
+        return Err(Error::Validation(errors));
+    }
+    let results = scenario.run(Verbosity::Quiet)?;
+    Ok((scenario, results))
+}
+
+// Runs a posted scenario like `simulate`, along with its Monte Carlo paths if it has any.
+fn simulate_with_monte_carlo(
+    query: &std::collections::BTreeMap<String, String>,
+    scenario: &str,
+) -> Result<(Scenario, sim::SimulationResult, Option<MonteCarloResult>), Error> {
+    let (scenario, results) = simulate(query, scenario)?;
+    let monte_carlo = match scenario.monte_carlo.as_ref() {
+        Some(settings) => Some(run_monte_carlo(&scenario, settings)?),
+        None => None,
+    };
+    Ok((scenario, results, monte_carlo))
+}
+
+/// Runs a scenario. Query parameters override its variables, e.g. `/scenario?salary=130000`.
+#[post("/scenario")]
+async fn get_scenario(
+    query: web::Query<std::collections::BTreeMap<String, String>>,
+    scenario: String,
+) -> impl Responder {
+    let query = query.into_inner();
+    let (scenario, results, monte_carlo) =
+        match blocking(move || simulate_with_monte_carlo(&query, &scenario)).await {
+            Ok(r) => r,
+            Err(response) => return response,
+        };
+    let response = ScenarioResponse {
+        scenario: scenario.name.clone(),
+        results,
+        monte_carlo,
+    };
     HttpResponse::Ok().json(response)
 }
 
+/// Runs a scenario like `/scenario` and returns its statements for every `Month`, `Quarter`
+/// or `Year`, see `sim::statement`.
+#[post("/statements/{period}")]
+async fn get_statements(
+    period: web::Path<String>,
+    query: web::Query<std::collections::BTreeMap<String, String>>,
+    scenario: String,
+) -> impl Responder {
+    let period: Period = match period.parse() {
+        Ok(p) => p,
+        Err(e) => return error_response(e),
+    };
+    let query = query.into_inner();
+    let rows = blocking(move || {
+        let (_, results) = simulate(&query, &scenario)?;
+        statements(&results, period)
+    });
+    match rows.await {
+        Ok(rows) => HttpResponse::Ok().json(rows),
+        Err(response) => response,
+    }
+}
+
+/// Runs a scenario like `/scenario` and returns one table of its results as a file named by
+/// the path, e.g. `/export/payments.parquet`. See `sim::export` for the tables and formats.
+#[post("/export/{file}")]
+async fn get_export(
+    file: web::Path<String>,
+    query: web::Query<std::collections::BTreeMap<String, String>>,
+    scenario: String,
+) -> impl Responder {
+    let (table, format) = match file.split_once('.') {
+        Some((table, format)) => (table.to_string(), format),
+        None => return HttpResponse::NotFound().body(format!("No export named {}", file)),
+    };
+    let format: Format = match format.parse() {
+        Ok(f) => f,
+        Err(e) => return error_response(e),
+    };
+    let query = query.into_inner();
+    let body = blocking(move || {
+        let (_, results) = simulate(&query, &scenario)?;
+        let mut df = results.frame(&table)?;
+        let mut body = vec![];
+        write_frame(&mut df, &mut body, format)?;
+        Ok(body)
+    });
+    match body.await {
+        Ok(body) => HttpResponse::Ok().content_type(format.content_type()).body(body),
+        Err(response) => response,
+    }
+}
+
+/// Runs a scenario like `/scenario` and returns its report as a single HTML page, see
+/// `sim::report`.
+#[post("/report")]
+async fn get_report(
+    query: web::Query<std::collections::BTreeMap<String, String>>,
+    scenario: String,
+) -> impl Responder {
+    let query = query.into_inner();
+    let html = blocking(move || {
+        let (scenario, results, monte_carlo) = simulate_with_monte_carlo(&query, &scenario)?;
+        render(&scenario, &results, monte_carlo.as_ref())
+    });
+    match html.await {
+        Ok(html) => HttpResponse::Ok().content_type("text/html; charset=utf-8").body(html),
+        Err(response) => response,
+    }
+}
+
+#[post("/compare")]
+async fn get_comparison(scenarios: String) -> impl Responder {
+    let docs: Vec<serde_json::Value> = match serde_json::from_str(&scenarios) {
+        Ok(d) => d,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+    let mut scenarios = vec![];
+    let mut errors = vec![];
+    for (i, doc) in docs.into_iter().enumerate() {
+        match validate_document(doc) {
+            Ok(s) => scenarios.push(s),
+            Err(e) => errors.extend(e.into_iter().map(|mut e| {
+                e.path = format!("{}.{}", i, e.path).trim_end_matches('.').to_string();
+                e
+            })),
+        }
+    }
+    if !errors.is_empty() {
+        return HttpResponse::BadRequest().json(errors);
+    }
+    if scenarios.len() < 2 {
+        return HttpResponse::BadRequest().body("At least two scenarios are required");
+    }
+    match blocking(move || compare(&scenarios, None)).await {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(response) => response,
+    }
+}
+
 #[actix_web::main]
 pub async fn main() -> std::io::Result<()> {
     println!("Starting API server at http://localhost:8080/ ...");
@@ -39,6 +235,11 @@ pub async fn main() -> std::io::Result<()> {
             .wrap(Logger::default())
             .service(index)
             .service(get_results)
+            .service(get_export)
+            .service(get_statements)
+            .service(get_scenario)
+            .service(get_report)
+            .service(get_comparison)
     })
     .bind("127.0.0.1:8080")?
     .run()
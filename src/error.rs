@@ -0,0 +1,83 @@
+use crate::sim::validate::{self, ValidationError};
+
+/// Everything that can go wrong in the library.
+///
+/// Errors from the crates used for reading and writing files are wrapped as they are, so
+/// callers can still inspect them. Inputs that are read successfully but make no sense are
+/// reported as `Validation` (with every problem found) or, when found during a simulation,
+/// as `Invalid`.
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+    Json(serde_json::Error),
+    Polars(polars::error::PolarsError),
+    Excel(xlsxwriter::XlsxError),
+    Validation(Vec<ValidationError>),
+    Invalid(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Yaml(e) => write!(f, "{}", e),
+            Error::Json(e) => write!(f, "{}", e),
+            Error::Polars(e) => write!(f, "{}", e),
+            Error::Excel(e) => write!(f, "Excel export failed: {}", e),
+            Error::Validation(errors) => write!(f, "{}", validate::describe(errors)),
+            Error::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Yaml(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::Polars(e) => Some(e),
+            Error::Excel(e) => Some(e),
+            Error::Validation(_) | Error::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(e: serde_yaml::Error) -> Error {
+        Error::Yaml(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Json(e)
+    }
+}
+
+impl From<polars::error::PolarsError> for Error {
+    fn from(e: polars::error::PolarsError) -> Error {
+        Error::Polars(e)
+    }
+}
+
+impl From<xlsxwriter::XlsxError> for Error {
+    fn from(e: xlsxwriter::XlsxError) -> Error {
+        Error::Excel(e)
+    }
+}
+
+impl From<Vec<ValidationError>> for Error {
+    fn from(errors: Vec<ValidationError>) -> Error {
+        Error::Validation(errors)
+    }
+}
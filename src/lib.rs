@@ -1,4 +1,7 @@
 pub mod sim;
-mod api;
+// a sketch of storing results in Postgres that nothing calls yet
+#[allow(dead_code)]
 mod database;
+mod error;
 
+pub use error::{Error, Result};
@@ -1,4 +1,4 @@
-use budget::sim;
+use budget::{sim, Error};
 mod api;
 use std::process::exit;
 
@@ -10,6 +10,10 @@ fn generate_json_schemas() {
         (schema_for!(sim::cash::Payment), ".payment.json"),
         (schema_for!(sim::portfolio::Asset), ".asset.json"),
         (schema_for!(sim::portfolio::Portfolio), ".portfolio.json"),
+        (schema_for!(sim::scenario::Scenario), ".scenario.json"),
+        (schema_for!(sim::ledger::Ledger), ".ledger.json"),
+        (schema_for!(sim::import::CsvMapping), ".csv_mapping.json"),
+        (schema_for!(sim::journal::JournalMapping), ".journal_mapping.json"),
     ];
 
     for obj in schematize_objs {
@@ -19,6 +23,76 @@ fn generate_json_schemas() {
     }
 }
 
+/// Reads and validates a scenario file, an Excel template of one, or a single account config
+/// wrapped in a scenario named after the file. `portfolio` is only attached to account
+/// configs; scenario files declare their own.
+fn read_scenario(
+    file: &str,
+    portfolio: Option<sim::portfolio::Portfolio>,
+) -> Result<sim::scenario::Scenario, Vec<sim::validate::ValidationError>> {
+    let path = std::path::Path::new(file);
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    if ["xlsx", "xlsm", "xls", "ods"].contains(&extension.to_lowercase().as_str()) {
+        return sim::template::read_template(path);
+    }
+    let doc: Option<serde_yaml::Value> = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|config| serde_yaml::from_str(&config).ok());
+    let is_scenario = doc
+        .map(|d| d.get("accounts").is_some() || d.get(sim::inherit::EXTENDS).is_some())
+        .unwrap_or(false);
+    if is_scenario {
+        return sim::validate::validate_file(path);
+    }
+
+    let mut scenario = sim::validate::validate_account_file(path, portfolio)?;
+    if let Some(stem) = path.file_stem() {
+        scenario.name = stem.to_string_lossy().to_string();
+    }
+    Ok(scenario)
+}
+
+/// Reads a CSV column mapping for `--import` and `--detect-recurring`, exiting on errors.
+fn read_mapping(file: &str) -> sim::import::CsvMapping {
+    match std::fs::read_to_string(file)
+        .map_err(|e| e.to_string())
+        .and_then(|c| serde_yaml::from_str(&c).map_err(|e| e.to_string()))
+    {
+        Ok(mapping) => mapping,
+        Err(e) => {
+            println!("{}: {}", file, e);
+            exit(1)
+        }
+    }
+}
+
+/// Prints every validation error and exits.
+fn exit_with_errors(errors: &[sim::validate::ValidationError]) -> ! {
+    for e in errors {
+        println!("{}", e);
+    }
+    exit(1)
+}
+
+fn exit_with_error(e: Error) -> ! {
+    match e {
+        Error::Validation(errors) => exit_with_errors(&errors),
+        e => {
+            println!("{}", e);
+            exit(1)
+        }
+    }
+}
+
+/// The value after `flag`, exiting with a usage error when nothing follows it.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    let i = args.iter().position(|s| s == flag)?;
+    match args.get(i + 1) {
+        Some(value) => Some(value),
+        None => exit_with_error(Error::Invalid(format!("{} needs a value", flag))),
+    }
+}
+
 fn main() {
     // parse command line args
     let args: Vec<String> = std::env::args().collect();
@@ -33,57 +107,670 @@ fn main() {
     // flag to generate json schema
     let gen_schema = args.contains(&String::from("--gen-schema"));
 
-    // run simulation with provided config
+    // run simulation with provided scenario, or a single account config
     let run_sim = args.contains(&String::from("--run-sim"));
-    let config_file = args.iter().position(|s| s == "--config");
-    let mut config = String::new();
+    let scenario_file = flag_value(&args, "--scenario");
+    let config_file = flag_value(&args, "--config");
+
+    // Print every simulated day with --verbose, or nothing but the requested outputs with
+    // --quiet, instead of the summary table and chart
+    let verbosity = if args.iter().any(|s| s == "--verbose" || s == "-v") {
+        sim::terminal::Verbosity::Daily
+    } else if args.iter().any(|s| s == "--quiet" || s == "-q") {
+        sim::terminal::Verbosity::Quiet
+    } else {
+        sim::terminal::Verbosity::Summary
+    };
+
+    // Only check the scenario, reporting every problem found
+    let validate = args.contains(&String::from("--validate"));
+
+    // Print the scenario after resolving `extends` and defaults
+    let print_scenario = args.contains(&String::from("--print-scenario"));
+
+    // Write the scenario as an Excel template that `--scenario` reads back
+    let template_file = flag_value(&args, "--excel-template");
 
     // Optional Portfolio conifguration
-    let portfolio_file = args.iter().position(|s| s == "--portfolio");
+    let portfolio_file = flag_value(&args, "--portfolio");
     let mut portfolio: Option<sim::portfolio::Portfolio> = None;
 
-    // let num_samples_arg = args.iter().position(|s| s == "--num-samples");
-    // let mut num_samples: i64 = 1;
+    // Variable overrides, `--set name=value`, may be repeated
+    let set_args: Vec<usize> = args
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| *s == "--set")
+        .map(|(i, _)| i)
+        .collect();
+
+    // Number of Monte Carlo paths, overriding the scenario's settings
+    let num_samples_arg = args.iter().position(|s| s == "--num-samples");
+
+    // Sensitivity analysis, perturbing each input by the given percentage
+    let sensitivity_arg = args.iter().position(|s| s == "--sensitivity");
+    let metrics_arg = flag_value(&args, "--metrics");
+
+    // Parameter sweep over the variants described in a sweep spec file
+    let sweep_file = flag_value(&args, "--sweep");
+    let output_file = flag_value(&args, "--output");
+
+    // Compare two or more scenarios over the horizon of the first
+    let compare_arg = args.iter().position(|s| s == "--compare");
+
+    // Import a bank export into the ledger of one of the scenario's accounts
+    let import_file = flag_value(&args, "--import");
+    let ledger_file = flag_value(&args, "--ledger");
+    let account_arg = flag_value(&args, "--account");
+    let mapping_file = flag_value(&args, "--mapping");
+
+    // Draft an account from the recurring cash flows in a bank export
+    let detect_file = flag_value(&args, "--detect-recurring");
+
+    // Compare a ledger's actual transactions with the planned payments of its account
+    let variance_file = flag_value(&args, "--variance");
+    let period_arg = args.iter().position(|s| s == "--period");
 
-    // if num_samples_arg.is_some() {
-    //     let num_samples_arg = num_samples_arg.unwrap();
-    //     let num_samples_str = &args[num_samples_arg + 1];
-    //     num_samples = num_samples_str.parse::<i64>().unwrap_or(1);
-    // }
+    // Re-anchor the scenario on balances observed on a date, `--actual name=balance` may be
+    // repeated or taken from the account's `--ledger`
+    let reforecast_arg = args.iter().position(|s| s == "--reforecast");
+    let actual_args: Vec<usize> = args
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| *s == "--actual")
+        .map(|(i, _)| i)
+        .collect();
+
+    // Write the simulated payments as a ledger or beancount journal, naming journal accounts
+    // with an optional mapping
+    let journal_file = flag_value(&args, "--journal");
+    let journal_mapping_file = flag_value(&args, "--journal-mapping");
+
+    // Payment totals by category or tag in each `--period`
+    let rollup_arg = args.iter().position(|s| s == "--rollup");
+
+    // Opening and closing balance, inflows, outflows, taxes and growth of every account in
+    // each `Month`, `Quarter` or `Year`
+    let statements_arg = args.iter().position(|s| s == "--statements");
+
+    // Write the balances, payments, consolidated balances and account summaries as CSV,
+    // Parquet or Arrow files into a directory
+    let export_dir = flag_value(&args, "--export");
+    let format_arg = args.iter().position(|s| s == "--format");
 
     // Output to excel file
-    let excel = args.contains(&String::from("--excel"));
-    let excel_file = args.iter().position(|s| s == "--excel");
+    let excel_file = flag_value(&args, "--excel");
+
+    // Write the excel file as an editable assumptions sheet and a forecast of formulas
+    let formulas = args.contains(&String::from("--formulas"));
+
+    // Write a single HTML page with summary metrics, charts and the scenario's assumptions
+    let report_file = flag_value(&args, "--report");
+
+    if let Some(portfolio_file) = portfolio_file {
+        let portfolio_config = match std::fs::read_to_string(portfolio_file) {
+            Ok(c) => c,
+            Err(e) => {
+                println!("{}: {}", portfolio_file, e);
+                exit(1)
+            }
+        };
+        match serde_yaml::from_str(&portfolio_config) {
+            Ok(p) => portfolio = Some(p),
+            Err(e) => {
+                println!("{}: {}", portfolio_file, e);
+                exit(1)
+            }
+        }
+    }
 
-    if config_file.is_some() {
-        let config_file = config_file.unwrap();
-        let config_file = &args[config_file + 1];
-        config = std::fs::read_to_string(config_file).unwrap();
+    let scenario_arg = scenario_file.or(config_file);
+    let mut scenario: Option<sim::scenario::Scenario> = None;
+    if let Some(scenario_arg) = scenario_arg {
+        match read_scenario(scenario_arg, portfolio.clone()) {
+            Ok(s) => scenario = Some(s),
+            Err(errors) => exit_with_errors(&errors),
+        }
     }
 
-    if portfolio_file.is_some() {
-        let portfolio_file = portfolio_file.unwrap();
-        let portfolio_file = &args[portfolio_file + 1];
-        let portfolio_config = std::fs::read_to_string(portfolio_file).unwrap();
-        portfolio = Some(serde_yaml::from_str(&portfolio_config).unwrap());
+    if validate {
+        match scenario_arg {
+            Some(scenario_arg) => println!("{} is valid", scenario_arg),
+            None => {
+                println!("--validate requires --scenario <scenario_file> or --config <config_file>");
+                exit(1)
+            }
+        }
+    }
+
+    if !set_args.is_empty() {
+        if scenario.is_none() {
+            println!("--set requires --scenario <scenario_file>");
+            exit(1)
+        }
+        let mut overrides = std::collections::BTreeMap::new();
+        for i in set_args {
+            match args.get(i + 1).and_then(|a| a.split_once('=')) {
+                Some((name, value)) => {
+                    overrides.insert(name.to_string(), sim::expr::parse_override(value));
+                }
+                None => {
+                    println!("--set requires --set <name>=<value>");
+                    exit(1)
+                }
+            }
+        }
+        match scenario.as_ref().unwrap().with_variables(&overrides) {
+            Ok(s) => {
+                let errors = sim::validate::check(&s);
+                if !errors.is_empty() {
+                    exit_with_errors(&errors)
+                }
+                scenario = Some(s)
+            }
+            Err(e) => exit_with_error(e),
+        }
+    }
+
+    if let Some(reforecast_arg) = reforecast_arg {
+        if scenario.is_none() {
+            println!("--reforecast requires --scenario <scenario_file> or --config <config_file>");
+            exit(1)
+        }
+        let as_of = match args.get(reforecast_arg + 1).map(|d| d.parse()) {
+            Some(Ok(as_of)) => as_of,
+            _ => {
+                println!("--reforecast requires --reforecast <YYYY-MM-DD>");
+                exit(1)
+            }
+        };
+        let s = scenario.as_mut().unwrap();
+        let mut balances = std::collections::BTreeMap::new();
+        if let (Some(ledger_file), None) = (ledger_file, import_file) {
+            let path = std::path::Path::new(ledger_file);
+            let ledger: sim::ledger::Ledger = std::fs::read_to_string(path)
+                .map_err(Error::from)
+                .and_then(|c| serde_yaml::from_str(&c).map_err(Error::from))
+                .unwrap_or_else(|e| exit_with_error(e));
+            if let Some(account) = s.accounts.iter().find(|a| a.name == ledger.account) {
+                let balance = ledger
+                    .balance_at(account, as_of)
+                    .unwrap_or_else(|e| exit_with_error(e));
+                balances.insert(ledger.account.clone(), balance);
+            }
+        }
+        for i in actual_args {
+            let actual = args.get(i + 1).and_then(|a| a.split_once('='));
+            match actual.map(|(name, balance)| (name, balance.parse::<sim::money::Money>())) {
+                Some((name, Ok(balance))) => {
+                    balances.insert(name.to_string(), balance);
+                }
+                _ => {
+                    println!("--actual requires --actual <account_name>=<balance>");
+                    exit(1)
+                }
+            }
+        }
+        s.reforecast = Some(sim::reforecast::Reforecast { as_of, balances });
+        let errors = sim::validate::check(s);
+        if !errors.is_empty() {
+            exit_with_errors(&errors)
+        }
+    }
+
+    let journal_mapping: sim::journal::JournalMapping = match journal_mapping_file {
+        Some(f) => std::fs::read_to_string(f)
+            .map_err(Error::from)
+            .and_then(|c| serde_yaml::from_str(&c).map_err(Error::from))
+            .unwrap_or_else(|e| {
+                println!("{}: {}", f, e);
+                exit(1)
+            }),
+        None => Default::default(),
+    };
+
+    if print_scenario {
+        if scenario.is_none() {
+            println!("--print-scenario requires --scenario <scenario_file> or --config <config_file>");
+            exit(1)
+        }
+        print!("{}", serde_yaml::to_string(scenario.as_ref().unwrap()).unwrap());
+    }
+
+    if let Some(file) = template_file {
+        if scenario.is_none() {
+            println!("--excel-template requires --excel-template <excel_file> --scenario <scenario_file>");
+            exit(1)
+        }
+        sim::template::write_template(scenario.as_ref().unwrap(), file)
+            .unwrap_or_else(|e| exit_with_error(e));
+        println!("Wrote {}", file);
+    }
+
+    if let (Some(num_samples_arg), Some(scenario)) = (num_samples_arg, scenario.as_mut()) {
+        let num_samples = args
+            .get(num_samples_arg + 1)
+            .and_then(|s| s.parse::<usize>().ok());
+        if num_samples.is_none() {
+            println!("--num-samples requires --num-samples <samples>");
+            exit(1)
+        }
+        let settings = scenario
+            .monte_carlo
+            .get_or_insert_with(|| sim::monte_carlo::MonteCarlo::new(0));
+        settings.samples = num_samples.unwrap();
     }
 
     if run_sim {
-        if config_file.is_none() {
-            println!("--run-sim requires --config <config_file>");
+        if scenario.is_none() {
+            println!("--run-sim requires --scenario <scenario_file> or --config <config_file>");
             exit(1)
         }
-        let account: sim::cash::Account = serde_yaml::from_str(&config).unwrap();
-        let results = sim::run_simulation(account, portfolio, true);
+        let scenario = scenario.as_ref().unwrap();
+        let results = scenario.run(verbosity).unwrap_or_else(|e| exit_with_error(e));
+
+        if !scenario.fx.is_empty() {
+            if let Some(c) = results.consolidated.last() {
+                println!(
+                    "Consolidated balance on {}, {}, FX gain/loss, {}",
+                    c.date, c.balance, c.fx_gain_loss
+                );
+            }
+        }
 
-        if excel {
-            if excel_file.is_none() {
-                println!("--excel requires --excel <excel_file>");
+        for r in &results.reconciliations {
+            println!(
+                "Reconciled {} on {}, projected {}, actual {}, difference {}",
+                r.account_name, r.date, r.projected, r.actual, r.difference
+            );
+        }
+
+        let monte_carlo = scenario.monte_carlo.as_ref().map(|settings| {
+            sim::monte_carlo::run_monte_carlo(scenario, settings)
+                .unwrap_or_else(|e| exit_with_error(e))
+        });
+        if let Some(mc) = &monte_carlo {
+            println!("--- Monte Carlo ({} paths) ---", mc.samples);
+            let mut ending = mc.ending_balances.clone();
+            ending.sort_by(|a, b| a.total_cmp(b));
+            for q in &mc.percentiles {
+                println!(
+                    "P{} ending balance, {}",
+                    q,
+                    sim::monte_carlo::percentile(&ending, *q)
+                );
+            }
+            println!("Paths never below zero, {:.1}%", mc.success_rate() * 100.0);
+        }
+
+        if let Some(rollup_arg) = rollup_arg {
+            let key: sim::rollup::RollupKey = match args.get(rollup_arg + 1) {
+                Some(k) if !k.starts_with("--") => k.parse().unwrap_or_else(|e| exit_with_error(e)),
+                _ => Default::default(),
+            };
+            let period: sim::variance::Period = match period_arg.and_then(|p| args.get(p + 1)) {
+                Some(p) => p.parse().unwrap_or_else(|e| exit_with_error(e)),
+                None => Default::default(),
+            };
+            let rows = sim::rollup::rollup(&results.payments, key, period)
+                .unwrap_or_else(|e| exit_with_error(e));
+            for r in rows {
+                println!(
+                    "{}, {}{}, {}, {} payments",
+                    r.period,
+                    "  ".repeat(r.depth),
+                    r.key,
+                    r.total,
+                    r.payments
+                );
+            }
+        }
+
+        if let Some(statements_arg) = statements_arg {
+            let period: sim::variance::Period = match args.get(statements_arg + 1) {
+                Some(p) if !p.starts_with("--") => p.parse().unwrap_or_else(|e| exit_with_error(e)),
+                _ => Default::default(),
+            };
+            let rows = sim::statement::statements(&results, period).unwrap_or_else(|e| exit_with_error(e));
+            for r in rows {
+                let rate = r
+                    .savings_rate
+                    .map(|s| format!(", saved {:.1}%", s * 100.0))
+                    .unwrap_or_default();
+                println!(
+                    "{}, {}, opening {}, inflows {}, outflows {}, taxes {}, growth {}, transfers {}, closing {}{}",
+                    r.period,
+                    r.account_name,
+                    r.opening,
+                    r.inflows,
+                    r.outflows,
+                    r.taxes,
+                    r.growth,
+                    r.transfers,
+                    r.closing,
+                    rate
+                );
+            }
+        }
+
+        if let Some(journal_file) = journal_file {
+            let path = std::path::Path::new(journal_file);
+            let dialect =
+                sim::journal::Dialect::from_path(path).unwrap_or(sim::journal::Dialect::Ledger);
+            let journal = sim::journal::write_journal(&results.payments, &journal_mapping, dialect);
+            std::fs::write(path, journal).unwrap_or_else(|e| exit_with_error(e.into()));
+        }
+
+        if let Some(export_dir) = export_dir {
+            let format: sim::export::Format = match format_arg.and_then(|f| args.get(f + 1)) {
+                Some(f) => f.parse().unwrap_or_else(|e| exit_with_error(e)),
+                None => sim::export::Format::Csv,
+            };
+            let dir = std::path::Path::new(export_dir);
+            let files = sim::export::export(&results, dir, format).unwrap_or_else(|e| exit_with_error(e));
+            for f in files {
+                println!("Wrote {}", f.display());
+            }
+        }
+
+        if let Some(report_file) = report_file {
+            sim::report::write_report(scenario, &results, monte_carlo.as_ref(), report_file)
+                .unwrap_or_else(|e| exit_with_error(e));
+            println!("Wrote {}", report_file);
+        }
+
+        if let Some(excel_file) = excel_file {
+            let written = match formulas {
+                true => sim::excel::write_formulas(scenario, excel_file),
+                false => sim::excel::write_sim(results, monte_carlo.as_ref(), excel_file),
+            };
+            if let Err(e) = written {
+                exit_with_error(e)
+            }
+        }
+    }
+
+    if let Some(sensitivity_arg) = sensitivity_arg {
+        if scenario.is_none() {
+            println!("--sensitivity requires --scenario <scenario_file> or --config <config_file>");
+            exit(1)
+        }
+        let percent = args
+            .get(sensitivity_arg + 1)
+            .and_then(|s| s.parse::<f64>().ok());
+        if percent.is_none() {
+            println!("--sensitivity requires --sensitivity <percent>");
+            exit(1)
+        }
+
+        let mut metrics = sim::sensitivity::Metric::all();
+        if let Some(metrics_arg) = metrics_arg {
+            let parsed: Result<Vec<sim::sensitivity::Metric>, String> = metrics_arg
+                .split(',')
+                .map(|m| m.parse())
+                .collect();
+            match parsed {
+                Ok(m) => metrics = m,
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1)
+                }
+            }
+        }
+
+        let rows = sim::sensitivity::sensitivity(
+            scenario.as_ref().unwrap(),
+            percent.unwrap() / 100.0,
+            &metrics,
+        )
+        .unwrap_or_else(|e| exit_with_error(e));
+        sim::sensitivity::print_tornado(&rows);
+    }
+
+    if let Some(sweep_file) = sweep_file {
+        if scenario.is_none() {
+            println!("--sweep requires --scenario <scenario_file> or --config <config_file>");
+            exit(1)
+        }
+        let spec: sim::sweep::SweepSpec = match std::fs::read_to_string(sweep_file)
+            .map_err(|e| e.to_string())
+            .and_then(|c| serde_yaml::from_str(&c).map_err(|e| e.to_string()))
+        {
+            Ok(spec) => spec,
+            Err(e) => {
+                println!("{}: {}", sweep_file, e);
                 exit(1)
             }
-            let excel_file = excel_file.unwrap();
-            let excel_file = &args[excel_file + 1];
-            sim::excel::write_sim(results, excel_file);
+        };
+
+        let mut df = sim::sweep::run_sweep(&spec, scenario.as_ref().unwrap())
+            .unwrap_or_else(|e| exit_with_error(e));
+        println!("{}", df);
+
+        if let Some(output_file) = output_file {
+            if let Err(e) = sim::sweep::write_csv(&mut df, output_file) {
+                exit_with_error(e)
+            }
+        }
+    }
+
+    if let Some(compare_arg) = compare_arg {
+        let files: Vec<&String> = args[compare_arg + 1..]
+            .iter()
+            .take_while(|a| !a.starts_with("--"))
+            .collect();
+        if files.len() < 2 {
+            println!("--compare requires at least two scenario or config files");
+            exit(1)
+        }
+
+        let mut scenarios: Vec<sim::scenario::Scenario> = vec![];
+        for f in files {
+            match read_scenario(f, portfolio.clone()) {
+                Ok(s) => scenarios.push(s),
+                Err(errors) => exit_with_errors(&errors),
+            }
+        }
+        let comparison =
+            sim::compare::compare(&scenarios, None).unwrap_or_else(|e| exit_with_error(e));
+
+        let baseline = &comparison.scenarios[0];
+        for (i, name) in comparison.scenarios.iter().enumerate().skip(1) {
+            let last = comparison.balances.last();
+            println!(
+                "{} vs {}: ending balance difference {}",
+                name,
+                baseline,
+                last.map(|b| b.differences[i]).unwrap_or(0.0)
+            );
+        }
+        for c in &comparison.crossovers {
+            println!("{} moves {:?} {} on {}", c.scenario, c.direction, baseline, c.date);
+        }
+
+        if let Some(output_file) = output_file {
+            let json = serde_json::to_string_pretty(&comparison)
+                .unwrap_or_else(|e| exit_with_error(e.into()));
+            std::fs::write(output_file, json)
+                .unwrap_or_else(|e| exit_with_error(e.into()));
+        }
+        if let Some(excel_file) = excel_file {
+            if let Err(e) = sim::excel::write_comparison(&comparison, excel_file) {
+                exit_with_error(e)
+            }
+        }
+    }
+
+    if let Some(import_file) = import_file {
+        if scenario.is_none() || ledger_file.is_none() {
+            println!(
+                "--import requires --scenario <scenario_file> or --config <config_file> and --ledger <ledger_file>"
+            );
+            exit(1)
+        }
+        let accounts = &scenario.as_ref().unwrap().accounts;
+        let account = match account_arg {
+            Some(name) => accounts.iter().find(|a| a.name == *name),
+            None if accounts.len() == 1 => accounts.first(),
+            None => {
+                println!("--import requires --account <account_name> for scenarios with several accounts");
+                exit(1)
+            }
+        };
+        let account = account.unwrap_or_else(|| {
+            println!("No account named {}", account_arg.unwrap());
+            exit(1)
+        });
+
+        let mapping = mapping_file.map(|m| read_mapping(m));
+        let path = std::path::Path::new(import_file);
+        let transactions = match sim::journal::Dialect::from_path(path) {
+            Some(_) => {
+                sim::journal::read_journal_file(path, &journal_mapping.account(&account.name))
+            }
+            None => sim::import::import_file(
+                path,
+                None,
+                mapping.as_ref(),
+                account.balance.currency(),
+            ),
+        }
+        .unwrap_or_else(|e| exit_with_error(e));
+
+        let ledger_path = std::path::Path::new(ledger_file.unwrap());
+        let mut ledger =
+            sim::ledger::Ledger::open(ledger_path, account).unwrap_or_else(|e| exit_with_error(e));
+        let summary = ledger
+            .import(transactions)
+            .unwrap_or_else(|e| exit_with_error(e));
+        ledger.save(ledger_path).unwrap_or_else(|e| exit_with_error(e));
+        println!(
+            "Imported {} transactions into {}, {} duplicates skipped",
+            summary.added,
+            ledger_path.display(),
+            summary.duplicates
+        );
+    }
+
+    if let Some(detect_file) = detect_file {
+        let path = std::path::Path::new(detect_file);
+        let mapping = mapping_file.map(|m| read_mapping(m));
+        let transactions =
+            sim::import::import_file(path, None, mapping.as_ref(), Default::default())
+                .unwrap_or_else(|e| exit_with_error(e));
+        let recurrences =
+            sim::recurring::detect(&transactions, &sim::recurring::Detection::default());
+        let end_of_history = transactions.iter().map(|t| t.date).max().unwrap_or_else(|| {
+            println!("{} has no transactions", path.display());
+            exit(1)
+        });
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let account = sim::recurring::draft_account(&name, &recurrences, end_of_history)
+            .unwrap_or_else(|e| exit_with_error(e));
+        let yaml = sim::recurring::draft_yaml(&account, &recurrences)
+            .unwrap_or_else(|e| exit_with_error(e));
+
+        match output_file {
+            Some(output_file) => {
+                for r in &recurrences {
+                    println!(
+                        "{:.2} {} {} {}{}",
+                        r.confidence,
+                        r.payee,
+                        r.amount,
+                        r.periodicity,
+                        if r.active { "" } else { " (ended)" }
+                    );
+                }
+                std::fs::write(output_file, yaml).unwrap_or_else(|e| {
+                    println!("{}: {}", output_file, e);
+                    exit(1)
+                });
+            }
+            None => print!("{}", yaml),
+        }
+    }
+
+    if let Some(variance_file) = variance_file {
+        if scenario.is_none() {
+            println!("--variance requires --scenario <scenario_file> or --config <config_file>");
+            exit(1)
+        }
+        let period: sim::variance::Period = match period_arg.and_then(|p| args.get(p + 1)) {
+            Some(p) => p.parse().unwrap_or_else(|e| exit_with_error(e)),
+            None => Default::default(),
+        };
+        let ledger_path = std::path::Path::new(variance_file);
+        let ledger: sim::ledger::Ledger = std::fs::read_to_string(ledger_path)
+            .map_err(Error::from)
+            .and_then(|c| serde_yaml::from_str(&c).map_err(Error::from))
+            .unwrap_or_else(|e| {
+                println!("{}: {}", ledger_path.display(), e);
+                exit(1)
+            });
+        let scenario = scenario.as_ref().unwrap();
+        let mut account = match scenario.accounts.iter().find(|a| a.name == ledger.account) {
+            Some(a) => a.clone(),
+            None => {
+                println!(
+                    "{} is the ledger of account {}, which is not in the scenario",
+                    ledger_path.display(),
+                    ledger.account
+                );
+                exit(1)
+            }
+        };
+
+        // planned payments over the whole periods the ledger covers
+        let (first, last) = match (ledger.transactions.first(), ledger.transactions.last()) {
+            (Some(first), Some(last)) => (
+                period.start(first.date),
+                period.next(period.start(last.date)),
+            ),
+            _ => {
+                println!("{} has no transactions", ledger_path.display());
+                exit(1)
+            }
+        };
+        let start_date = first.max(account.start_date).max(scenario.start_date);
+        let end_date = last.min(account.end_date).min(scenario.end_date);
+        let payments: sim::columns::PaymentTable = match end_date.pred_opt() {
+            Some(end_date) if start_date <= end_date => account
+                .payments(start_date, end_date)
+                .unwrap_or_else(|e| exit_with_error(e))
+                .into_iter()
+                .collect(),
+            _ => Default::default(),
+        };
+        let report = sim::variance::budget_vs_actual(&payments, &ledger.transactions, period)
+            .unwrap_or_else(|e| exit_with_error(e));
+
+        for r in &report.rows {
+            println!(
+                "{}, {}, planned {}, actual {}, variance {}, cumulative {}",
+                r.category, r.period, r.planned, r.actual, r.variance, r.cumulative_variance
+            );
+        }
+        for t in &report.unmatched {
+            println!("Unmatched: {}, {}, {}", t.date, t.payee, t.amount);
+        }
+
+        if let Some(output_file) = output_file {
+            let written = if output_file.ends_with(".csv") {
+                report.write_csv(output_file)
+            } else {
+                serde_json::to_string_pretty(&report)
+                    .map_err(Error::from)
+                    .and_then(|json| std::fs::write(output_file, json).map_err(Error::from))
+            };
+            written.unwrap_or_else(|e| exit_with_error(e));
+        }
+        if let Some(excel_file) = excel_file {
+            if let Err(e) = sim::excel::write_variance(&report, excel_file) {
+                exit_with_error(e)
+            }
         }
     }
 
@@ -3,6 +3,9 @@ use memoize::memoize;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use super::money::{Money, Rounding};
+use crate::{Error, Result};
+
 #[allow(unused_imports)]
 use std::hash::{Hash, Hasher};
 
@@ -29,6 +32,10 @@ pub enum Frequency {
     MonthEnd,
     SemiMonthly,
     Annually,
+    /// Every seven days from the start date.
+    Weekly,
+    /// Every fourteen days from the start date, such as biweekly pay.
+    BiWeekly,
 }
 
 impl Frequency {
@@ -39,18 +46,27 @@ impl Frequency {
             Frequency::MonthEnd => 1.0 / 12.0,
             Frequency::SemiMonthly => 1.0 / 24.0,
             Frequency::Annually => 1.0,
+            Frequency::Weekly => 7.0 / 365.25,
+            Frequency::BiWeekly => 14.0 / 365.25,
         }
     }
 
-    pub fn matches(&self, d: &chrono::NaiveDate, start_date: &Option<chrono::NaiveDate>, end_date: &Option<chrono::NaiveDate>) -> bool {
+    /// Annual, weekly and biweekly recurrences count from their start date, so they need one.
+    pub fn needs_start_date(&self) -> bool {
+        matches!(self, Frequency::Annually | Frequency::Weekly | Frequency::BiWeekly)
+    }
+
+    /// Whether a recurrence falls on `d`. Annual recurrences repeat on the anniversary of
+    /// `start_date` and weekly ones every 7 or 14 days from it, so they fail without one.
+    pub fn matches(&self, d: &chrono::NaiveDate, start_date: &Option<chrono::NaiveDate>, end_date: &Option<chrono::NaiveDate>) -> Result<bool> {
         if let Some(start_date) = start_date {
             if start_date > d {
-                return false;
+                return Ok(false);
             }
         }
         if let Some(end_date) = end_date {
             if end_date < d {
-                return false;
+                return Ok(false);
             }
         }
 
@@ -58,65 +74,134 @@ impl Frequency {
             Frequency::Once => {
                 if let Some(start_date) = start_date {
                     if start_date != d {
-                        return false;
+                        return Ok(false);
                     }
                 }
             }
             Frequency::MonthStart => {
                 if d.day() != 1 {
-                    return false;
+                    return Ok(false);
                 }
             }
             Frequency::MonthEnd => {
                 let last_day_of_month = DAYS_IN_MONTH[(d.month() as usize) - 1];
                 if d.day() != last_day_of_month {
-                    return false;
+                    return Ok(false);
                 }
             }
             Frequency::SemiMonthly => {
                 let last_day_of_month = DAYS_IN_MONTH[(d.month() as usize) - 1];
                 if d.day() != last_day_of_month && d.day() != 15 {
-                    return false;
+                    return Ok(false);
                 }
             }
             Frequency::Annually => {
-                let sd = start_date.unwrap();
+                let sd = start_date.ok_or_else(|| {
+                    Error::Invalid("Annual recurrences need a start date".to_string())
+                })?;
                 if d.month() != sd.month() || d.day() != sd.day() {
-                    return false;
+                    return Ok(false);
+                }
+            }
+            Frequency::Weekly | Frequency::BiWeekly => {
+                let sd = start_date.ok_or_else(|| {
+                    Error::Invalid(format!("{:?} recurrences need a start date", self))
+                })?;
+                let days = if *self == Frequency::Weekly { 7 } else { 14 };
+                if (*d - sd).num_days() % days != 0 {
+                    return Ok(false);
                 }
             }
         }
 
-        true
+        Ok(true)
     }
 }
 
 
+/// What a payment is for, so results can be grouped without relying on names.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq, Hash,
+)]
+pub enum PaymentKind {
+    /// A payment of the cash flow itself.
+    #[default]
+    Principal,
+    /// Tax on a payment of the cash flow, at its `tax_rate`.
+    Tax,
+    /// Returns of a portfolio the account is invested in.
+    Interest,
+    /// Money moved between two accounts.
+    Transfer,
+}
+
+impl std::fmt::Display for PaymentKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct Payment {
     pub cash_flow: CashFlow,
     pub date: NaiveDate,
-    pub amount: f64,
+    pub amount: Money,
+    /// Name of the account paying or receiving, for payments made by an `Account`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// Id of that account, see [`Account::id`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+    /// Id of the cash flow the payment was made by, shared by its tax payments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flow_id: Option<String>,
+    #[serde(default)]
+    pub kind: PaymentKind,
 }
 
 impl Payment {
-    pub fn new(date: NaiveDate, amount: f64, cash_flow: CashFlow) -> Payment {
+    pub fn new(date: NaiveDate, amount: Money, cash_flow: CashFlow) -> Payment {
         Payment {
             date,
             amount,
+            flow_id: cash_flow.id.clone(),
             cash_flow,
+            account: None,
+            account_id: None,
+            kind: PaymentKind::Principal,
         }
     }
+
+    /// The category of the cash flow the payment was made by, see [`CashFlow::category`].
+    pub fn category(&self) -> &str {
+        self.cash_flow.category()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.cash_flow.tags
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct CashFlow {
+    /// Stable identifier that payments refer to the flow by. Flows of an account without one
+    /// are given one by [`Account::assign_ids`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub name: Option<String>,
-    pub amount: f64,
+    pub amount: Money,
     pub frequency: Frequency,
     pub start_date: Option<chrono::NaiveDate>,
     pub end_date: Option<chrono::NaiveDate>,
     pub tax_rate: f64,
+    /// Budget category the flow is reported under, by default its name. Subcategories
+    /// follow their parent after a colon, as in `Housing:Rent`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// Free-form labels, such as `fixed` or `discretionary`, for grouping flows across
+    /// categories.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
 }
 
 impl CashFlow {
@@ -125,19 +210,22 @@ impl CashFlow {
     // frequency is optional with default value of "once"
     pub fn new(
         name: Option<String>,
-        amount: f64,
+        amount: Money,
         frequency: Option<Frequency>,
         start_date: Option<chrono::NaiveDate>,
         end_date: Option<chrono::NaiveDate>,
         tax_rate: Option<f64>,
     ) -> CashFlow {
         CashFlow {
+            id: None,
             name,
             amount,
             frequency: frequency.unwrap_or(Frequency::Once),
             start_date,
             end_date,
             tax_rate: tax_rate.unwrap_or(0.0),
+            category: None,
+            tags: vec![],
         }
     }
 
@@ -145,122 +233,228 @@ impl CashFlow {
         self.name = Some(name);
     }
 
+    /// The budget category, or the name for uncategorized flows.
+    pub fn category(&self) -> &str {
+        self.category
+            .as_deref()
+            .or(self.name.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Fails if the cash flow cannot produce payments: annual and weekly flows need a start
+    /// date to recur from.
+    pub fn check(&self) -> Result<()> {
+        if self.frequency.needs_start_date() && self.start_date.is_none() {
+            return Err(Error::Invalid(format!(
+                "Cash flow {} is {:?} but has no start date",
+                self.name.as_deref().unwrap_or("(unnamed)"),
+                self.frequency
+            )));
+        }
+        Ok(())
+    }
+
+    /// Payments (or, with `tax_payments`, the taxes on them) between the two dates, each
+    /// rounded to the currency's minor unit. Taxes are paid by the same cash flow, with the
+    /// kind [`PaymentKind::Tax`].
     pub fn payments(
         &mut self,
         start_date: chrono::NaiveDate,
         end_date: chrono::NaiveDate,
         tax_payments: bool,
-    ) -> Vec<Payment> {
+        rounding: Rounding,
+    ) -> Result<Vec<Payment>> {
         // returns a vec of payments
-        let mut d = start_date.pred_opt().unwrap();
+        self.check()?;
+        let out_of_range = || Error::Invalid(format!("{} is out of range", start_date));
+        let mut d = start_date.pred_opt().ok_or_else(out_of_range)?;
         let mut payments: Vec<Payment> = vec![];
 
         // If tax payments have been requests, but the tax rate is 0, return an empty vec
         if tax_payments && self.tax_rate == 0.0 {
-            return payments;
+            return Ok(payments);
         }
 
         while d < end_date {
-            d = d.succ_opt().unwrap();
+            d = d.succ_opt().ok_or_else(out_of_range)?;
 
-            if self.frequency.matches(&d, &self.start_date, &self.end_date) {
+            if self.frequency.matches(&d, &self.start_date, &self.end_date)? {
                 let mut p = Payment::new(
                     d,
                     if tax_payments {
-                        self.amount * -self.tax_rate
+                        self.amount.scale(-self.tax_rate)?.round(rounding)
                     } else {
-                        self.amount
+                        self.amount.round(rounding)
                     },
                     self.clone(),
                 );
     
                 if tax_payments {
-                    p.cash_flow
-                        .set_name(format!("{} Tax", self.name.clone().unwrap()));
+                    p.kind = PaymentKind::Tax;
                 }
     
                 payments.push(p.clone());
             }
         }
-        payments
+        Ok(payments)
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, JsonSchema, Clone)]
 pub struct Account {
+    /// Stable identifier for joining results, by default generated from the name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub name: String,
-    pub balance: f64,
+    /// Opening balance. Its currency is the account's currency, which every cash flow must
+    /// share.
+    pub balance: Money,
     pub cash_flows: Vec<CashFlow>,
     pub start_date: chrono::NaiveDate,
     pub end_date: chrono::NaiveDate,
+    /// How payments are rounded to the currency's minor unit.
+    #[serde(default)]
+    pub rounding: Rounding,
 }
 
 impl Account {
     pub fn new(
         name: String,
-        balance: f64,
+        balance: Money,
         cash_flows: Vec<CashFlow>,
         start_date: chrono::NaiveDate,
         end_date: chrono::NaiveDate,
     ) -> Account {
         Account {
+            id: None,
             name,
             balance,
             cash_flows,
             start_date,
             end_date,
+            rounding: Rounding::default(),
         }
     }
 
+    /// Fails if a cash flow cannot produce payments or is in another currency than the
+    /// account.
+    pub fn check(&self) -> Result<()> {
+        for cash_flow in &self.cash_flows {
+            cash_flow.check()?;
+            if cash_flow.amount.currency() != self.balance.currency() {
+                return Err(Error::Invalid(format!(
+                    "Cash flow {} is in {} but account {} is in {}",
+                    cash_flow.name.as_deref().unwrap_or("(unnamed)"),
+                    cash_flow.amount.currency(),
+                    self.name,
+                    self.balance.currency()
+                )));
+            }
+        }
+        Ok(())
+    }
+
     pub fn add_cash_flow(&mut self, cash_flow: CashFlow) {
         self.cash_flows.push(cash_flow);
     }
 
+    /// The account's `id`, or one generated from its name, e.g. `joint-checking` for
+    /// `Joint Checking`.
+    pub fn id(&self) -> String {
+        match &self.id {
+            Some(id) => id.clone(),
+            None => match generated_id(&self.name) {
+                id if id.is_empty() => "account".to_string(),
+                id => id,
+            },
+        }
+    }
+
+    /// Gives every cash flow without an `id` one made of the account's id and the flow's
+    /// name, e.g. `checking.rent`, or its position for unnamed flows. An id already taken
+    /// gets a `-2`, `-3`, ... suffix.
+    pub fn assign_ids(&mut self) {
+        if self.cash_flows.iter().all(|f| f.id.is_some()) {
+            return;
+        }
+        let account = self.id();
+        let mut taken: std::collections::HashSet<String> =
+            self.cash_flows.iter().filter_map(|f| f.id.clone()).collect();
+        for (i, flow) in self.cash_flows.iter_mut().enumerate() {
+            if flow.id.is_some() {
+                continue;
+            }
+            let name = match flow.name.as_deref().map(generated_id) {
+                Some(name) if !name.is_empty() => name,
+                _ => (i + 1).to_string(),
+            };
+            let base = format!("{}.{}", account, name);
+            let mut id = base.clone();
+            let mut n = 1;
+            while taken.contains(&id) {
+                n += 1;
+                id = format!("{}-{}", base, n);
+            }
+            taken.insert(id.clone());
+            flow.id = Some(id);
+        }
+    }
+
+    // marks payments as made by this account
+    fn attribute(&self, payments: &mut [Payment]) {
+        let id = self.id();
+        for p in payments {
+            p.account = Some(self.name.clone());
+            p.account_id = Some(id.clone());
+        }
+    }
+
     pub fn payments(
         &mut self,
         start_date: chrono::NaiveDate,
         end_date: chrono::NaiveDate,
-    ) -> Vec<Payment> {
+    ) -> Result<Vec<Payment>> {
+        self.assign_ids();
         let mut payments: Vec<Payment> = vec![];
         for cash_flow in &mut self.cash_flows {
-            payments.append(&mut cash_flow.payments(start_date, end_date, false));
-            payments.append(&mut cash_flow.payments(start_date, end_date, true));
+            payments.append(&mut cash_flow.payments(start_date, end_date, false, self.rounding)?);
+            payments.append(&mut cash_flow.payments(start_date, end_date, true, self.rounding)?);
         }
+        self.attribute(&mut payments);
         // sort by date
-        payments.sort_by(|a, b| a.date.cmp(&b.date));
-        payments
+        payments.sort_by_key(|p| p.date);
+        Ok(payments)
     }
 
-    pub fn balance_at(&mut self, date: chrono::NaiveDate) -> f64 {
+    pub fn balance_at(&mut self, date: chrono::NaiveDate) -> Result<Money> {
         let mut balance = self.balance;
         for cash_flow in &mut self.cash_flows {
-            let payments = cash_flow.payments(self.start_date, date, false);
-            let taxes = cash_flow.payments(self.start_date, date, true);
+            let payments = cash_flow.payments(self.start_date, date, false, self.rounding)?;
+            let taxes = cash_flow.payments(self.start_date, date, true, self.rounding)?;
             for payment in payments.into_iter().chain(taxes) {
-                balance += payment.amount;
+                balance = balance.checked_add(payment.amount)?;
             }
         }
-        balance
+        Ok(balance)
     }
 
-    pub fn flows_at(&mut self, date: chrono::NaiveDate) -> Vec<Payment> {
+    pub fn flows_at(&mut self, date: chrono::NaiveDate) -> Result<Vec<Payment>> {
         // Returns a vec of Payment objects corresponding to all flows on this date
+        self.assign_ids();
         let mut flows: Vec<Payment> = vec![];
         for cash_flow in &mut self.cash_flows {
-            let payments = &mut cash_flow.payments(date, date, false);
-            let taxes = &mut cash_flow.payments(date, date, true);
-            for payment in payments.iter_mut().chain(taxes) {
-                flows.push(payment.clone());
-            }
+            flows.append(&mut cash_flow.payments(date, date, false, self.rounding)?);
+            flows.append(&mut cash_flow.payments(date, date, true, self.rounding)?);
         }
-        flows.sort_by(|a, b| a.date.cmp(&b.date));
-        flows
+        self.attribute(&mut flows);
+        flows.sort_by_key(|p| p.date);
+        Ok(flows)
     }
 }
 
 impl std::cmp::PartialEq for Account {
     fn eq(&self, other: &Self) -> bool {
-        self.name == other.name
+        self.id() == other.id()
     }
 }
 
@@ -268,19 +462,30 @@ impl std::cmp::Eq for Account {}
 
 impl std::hash::Hash for Account {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.name.hash(state);
+        self.id().hash(state);
     }
 }
 
+/// The lowercase letters and digits of `name`, with every other run of characters replaced
+/// by a dash, e.g. `acme-payroll-2` for `Acme Payroll #2`.
+pub fn generated_id(name: &str) -> String {
+    let words: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect();
+    words.join("-")
+}
+
 #[test]
 fn test_account_hash() {
-    // test that the account hash is based on the name
+    // test that the account hash is based on the id, generated from the name
     let account1 = Account::new(
         "Test Account".to_string(),
-        0.0,
+        Money::try_from(0.0).unwrap(),
         vec![CashFlow::new(
             Some("Test Cash Flow".to_string()),
-            100.0,
+            Money::try_from(100.0).unwrap(),
             Some(Frequency::MonthStart),
             None,
             None,
@@ -292,10 +497,10 @@ fn test_account_hash() {
 
     let account2 = Account::new(
         "Test Account 2".to_string(),
-        0.0,
+        Money::try_from(0.0).unwrap(),
         vec![CashFlow::new(
             Some("Test Cash Flow".to_string()),
-            100.0,
+            Money::try_from(100.0).unwrap(),
             Some(Frequency::MonthStart),
             None,
             None,
@@ -316,25 +521,35 @@ fn test_account_hash() {
     assert_ne!(hash1, hash2);
 }
 
+/// Sum of an account's cash flows from its start date up to and including `date`.
+pub fn get_account_balance_at(account: Account, date: chrono::NaiveDate) -> Result<Money> {
+    // errors are found before the memoized recursion, which can then assume valid flows
+    account.check()?;
+    account_balance_at(account, date).map_err(Error::Invalid)
+}
+
+// the error is a message rather than an `Error`, which can't be cloned out of the cache
 #[memoize]
-pub fn get_account_balance_at(account: Account, date: chrono::NaiveDate) -> f64 {
+fn account_balance_at(account: Account, date: chrono::NaiveDate) -> Result<Money, String> {
     let mut a = account.clone();
-    let f = a.flows_at(date);
-    let mut b: f64 = 0.0;
+    let f = a.flows_at(date).unwrap_or_default();
+    let mut b = Money::zero(a.balance.currency());
     if date > a.start_date {
-        b = get_account_balance_at(a, date - chrono::Duration::days(1));
+        b = account_balance_at(a, date - chrono::Duration::days(1))?;
     }
-    b + f.iter().fold(0.0, |acc, x| acc + x.amount)
+    f.iter()
+        .try_fold(b, |acc, x| acc.checked_add(x.amount))
+        .map_err(|e| e.to_string())
 }
 
 #[test]
 fn test_get_account_balance_at() {
     let account = Account::new(
         "Test Account".to_string(),
-        0.0,
+        Money::try_from(0.0).unwrap(),
         vec![CashFlow::new(
             Some("Test Cash Flow".to_string()),
-            100.0,
+            Money::try_from(100.0).unwrap(),
             Some(Frequency::MonthStart),
             None,
             None,
@@ -346,46 +561,157 @@ fn test_get_account_balance_at() {
     let balance = get_account_balance_at(
         account.clone(),
         NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
-    );
-    assert_eq!(balance, 100.0);
+    )
+    .unwrap();
+    assert_eq!(balance, Money::try_from(100.0).unwrap());
     let balance = get_account_balance_at(
         account.clone(),
         NaiveDate::from_ymd_opt(2020, 1, 2).unwrap(),
-    );
-    assert_eq!(balance, 100.0);
+    )
+    .unwrap();
+    assert_eq!(balance, Money::try_from(100.0).unwrap());
     let balance = get_account_balance_at(
         account.clone(),
         NaiveDate::from_ymd_opt(2020, 1, 31).unwrap(),
-    );
-    assert_eq!(balance, 100.0);
+    )
+    .unwrap();
+    assert_eq!(balance, Money::try_from(100.0).unwrap());
     let balance = get_account_balance_at(
         account.clone(),
         NaiveDate::from_ymd_opt(2020, 2, 1).unwrap(),
-    );
-    assert_eq!(balance, 200.0);
+    )
+    .unwrap();
+    assert_eq!(balance, Money::try_from(200.0).unwrap());
     let balance = get_account_balance_at(
         account.clone(),
         NaiveDate::from_ymd_opt(2020, 2, 2).unwrap(),
-    );
-    assert_eq!(balance, 200.0);
+    )
+    .unwrap();
+    assert_eq!(balance, Money::try_from(200.0).unwrap());
     let balance = get_account_balance_at(
         account.clone(),
         NaiveDate::from_ymd_opt(2020, 2, 29).unwrap(),
-    );
-    assert_eq!(balance, 200.0);
+    )
+    .unwrap();
+    assert_eq!(balance, Money::try_from(200.0).unwrap());
     let balance = get_account_balance_at(
         account.clone(),
         NaiveDate::from_ymd_opt(2020, 3, 1).unwrap(),
-    );
-    assert_eq!(balance, 300.0);
+    )
+    .unwrap();
+    assert_eq!(balance, Money::try_from(300.0).unwrap());
     let balance = get_account_balance_at(
         account.clone(),
         NaiveDate::from_ymd_opt(2020, 3, 2).unwrap(),
-    );
-    assert_eq!(balance, 300.0);
+    )
+    .unwrap();
+    assert_eq!(balance, Money::try_from(300.0).unwrap());
     let balance = get_account_balance_at(
         account.clone(),
         NaiveDate::from_ymd_opt(2020, 3, 31).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(balance, Money::try_from(300.0).unwrap());
+}
+
+#[test]
+fn test_invalid_cash_flow_is_an_error() {
+    let account = Account::new(
+        "Test Account".to_string(),
+        Money::try_from(0.0).unwrap(),
+        vec![CashFlow::new(
+            Some("Bonus".to_string()),
+            Money::try_from(100.0).unwrap(),
+            Some(Frequency::Annually),
+            None,
+            None,
+            None,
+        )],
+        NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+        NaiveDate::from_ymd_opt(2020, 12, 31).unwrap(),
+    );
+    let date = NaiveDate::from_ymd_opt(2020, 6, 1).unwrap();
+    match get_account_balance_at(account, date) {
+        Err(crate::Error::Invalid(message)) => assert!(message.contains("start date")),
+        _ => panic!("expected an invalid input error"),
+    }
+}
+
+#[test]
+fn test_biweekly_cash_flow() {
+    let mut cash_flow = CashFlow::new(
+        Some("Payroll".to_string()),
+        Money::try_from(2000.0).unwrap(),
+        Some(Frequency::BiWeekly),
+        Some(NaiveDate::from_ymd_opt(2019, 12, 27).unwrap()),
+        None,
+        None,
+    );
+    let payments = cash_flow
+        .payments(
+            NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2020, 3, 1).unwrap(),
+            false,
+            Rounding::HalfUp,
+        )
+        .unwrap();
+    let days: Vec<String> = payments.iter().map(|p| p.date.to_string()).collect();
+    assert_eq!(
+        days,
+        vec!["2020-01-10", "2020-01-24", "2020-02-07", "2020-02-21"]
+    );
+}
+
+#[test]
+fn test_assign_ids() {
+    let flow = |name: Option<&str>, tax_rate| {
+        CashFlow::new(
+            name.map(|n| n.to_string()),
+            Money::try_from(100.0).unwrap(),
+            Some(Frequency::MonthStart),
+            None,
+            None,
+            Some(tax_rate),
+        )
+    };
+    let mut pinned = flow(Some("Rent"), 0.0);
+    pinned.id = Some("checking.salary".to_string());
+    let mut account = Account::new(
+        "Joint Checking".to_string(),
+        Money::try_from(0.0).unwrap(),
+        vec![pinned, flow(Some("Salary"), 0.25), flow(None, 0.1)],
+        NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+        NaiveDate::from_ymd_opt(2020, 12, 31).unwrap(),
+    );
+    account.id = Some("checking".to_string());
+    account.assign_ids();
+    let ids: Vec<&str> = account
+        .cash_flows
+        .iter()
+        .map(|f| f.id.as_deref().unwrap())
+        .collect();
+    assert_eq!(ids, vec!["checking.salary", "checking.salary-2", "checking.3"]);
+
+    let payments = account
+        .payments(
+            NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2020, 1, 31).unwrap(),
+        )
+        .unwrap();
+    let salary: Vec<(PaymentKind, f64)> = payments
+        .iter()
+        .filter(|p| p.flow_id.as_deref() == Some("checking.salary-2"))
+        .map(|p| (p.kind, p.amount.to_f64()))
+        .collect();
+    assert_eq!(
+        salary,
+        vec![(PaymentKind::Principal, 100.0), (PaymentKind::Tax, -25.0)]
     );
-    assert_eq!(balance, 300.0);
+    // unnamed flows are taxed too, their taxes identified by the flow's id
+    assert!(payments
+        .iter()
+        .any(|p| p.kind == PaymentKind::Tax && p.flow_id.as_deref() == Some("checking.3")));
+    assert!(payments
+        .iter()
+        .all(|p| p.account_id.as_deref() == Some("checking")));
 }
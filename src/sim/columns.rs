@@ -0,0 +1,466 @@
+use chrono::NaiveDate;
+use polars::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::cash::{CashFlow, Payment, PaymentKind};
+use super::money::Money;
+use crate::Result;
+
+/// An account that balances or payments refer to, stored once per table.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AccountInfo {
+    pub id: String,
+    pub name: String,
+}
+
+/// The cash flow behind every payment of one kind made by it, stored once per table. Tax
+/// payments have their own entry, holding the flow as it is named and categorized for them.
+#[derive(Serialize, Clone, Debug)]
+pub struct FlowInfo {
+    pub id: Option<String>,
+    pub kind: PaymentKind,
+    pub cash_flow: CashFlow,
+}
+
+/// Payments stored as one column per field, with the accounts and cash flows they were made
+/// by in lookup tables that the `account` and `flow` columns index into.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct PaymentTable {
+    pub accounts: Vec<AccountInfo>,
+    pub flows: Vec<FlowInfo>,
+    pub date: Vec<NaiveDate>,
+    pub account: Vec<Option<u32>>,
+    pub flow: Vec<u32>,
+    pub kind: Vec<PaymentKind>,
+    pub amount: Vec<Money>,
+    #[serde(skip)]
+    account_index: HashMap<String, u32>,
+    #[serde(skip)]
+    flow_index: HashMap<(String, PaymentKind), u32>,
+}
+
+/// One payment of a [`PaymentTable`].
+#[derive(Clone, Copy, Debug)]
+pub struct PaymentRow<'a> {
+    pub date: NaiveDate,
+    pub amount: Money,
+    pub kind: PaymentKind,
+    pub account: Option<&'a AccountInfo>,
+    pub flow: &'a FlowInfo,
+}
+
+impl<'a> PaymentRow<'a> {
+    pub fn cash_flow(&self) -> &'a CashFlow {
+        &self.flow.cash_flow
+    }
+
+    /// The name of the cash flow, or an empty string for unnamed flows.
+    pub fn name(&self) -> &'a str {
+        self.flow.cash_flow.name.as_deref().unwrap_or_default()
+    }
+
+    /// See [`CashFlow::category`].
+    pub fn category(&self) -> &'a str {
+        self.flow.cash_flow.category()
+    }
+
+    pub fn tags(&self) -> &'a [String] {
+        &self.flow.cash_flow.tags
+    }
+
+    pub fn flow_id(&self) -> Option<&'a str> {
+        self.flow.id.as_deref()
+    }
+
+    pub fn account_name(&self) -> Option<&'a str> {
+        self.account.map(|a| a.name.as_str())
+    }
+
+    pub fn account_id(&self) -> Option<&'a str> {
+        self.account.map(|a| a.id.as_str())
+    }
+
+    pub fn to_payment(&self) -> Payment {
+        let mut payment = Payment::new(self.date, self.amount, self.flow.cash_flow.clone());
+        payment.flow_id = self.flow.id.clone();
+        payment.kind = self.kind;
+        payment.account = self.account.map(|a| a.name.clone());
+        payment.account_id = self.account.map(|a| a.id.clone());
+        payment
+    }
+}
+
+fn intern_account(
+    accounts: &mut Vec<AccountInfo>,
+    index: &mut HashMap<String, u32>,
+    id: &str,
+    name: &str,
+) -> u32 {
+    *index.entry(id.to_string()).or_insert_with(|| {
+        accounts.push(AccountInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+        });
+        accounts.len() as u32 - 1
+    })
+}
+
+impl PaymentTable {
+    pub fn new() -> PaymentTable {
+        PaymentTable::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.date.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.date.is_empty()
+    }
+
+    /// Appends a payment. Payments with a `flow_id` share the cash flow of earlier payments
+    /// of the same flow and kind.
+    pub fn push(&mut self, payment: &Payment) {
+        let account = payment.account_id.as_deref().map(|id| {
+            let name = payment.account.as_deref().unwrap_or(id);
+            intern_account(&mut self.accounts, &mut self.account_index, id, name)
+        });
+        self.push_flow(
+            payment.date,
+            payment.amount,
+            account,
+            payment.flow_id.as_deref(),
+            payment.kind,
+            &payment.cash_flow,
+        );
+    }
+
+    fn push_flow(
+        &mut self,
+        date: NaiveDate,
+        amount: Money,
+        account: Option<u32>,
+        flow_id: Option<&str>,
+        kind: PaymentKind,
+        cash_flow: &CashFlow,
+    ) {
+        let flows = &mut self.flows;
+        let mut add = || {
+            flows.push(FlowInfo {
+                id: flow_id.map(|id| id.to_string()),
+                kind,
+                cash_flow: cash_flow.clone(),
+            });
+            flows.len() as u32 - 1
+        };
+        let flow = match flow_id {
+            Some(id) => *self
+                .flow_index
+                .entry((id.to_string(), kind))
+                .or_insert_with(add),
+            // without an id there is nothing to tell flows apart by
+            None => add(),
+        };
+        self.date.push(date);
+        self.account.push(account);
+        self.flow.push(flow);
+        self.kind.push(kind);
+        self.amount.push(amount);
+    }
+
+    /// Appends every payment of another table.
+    pub fn append(&mut self, other: &PaymentTable) {
+        for p in other.iter() {
+            let account = p.account.map(|a| {
+                intern_account(&mut self.accounts, &mut self.account_index, &a.id, &a.name)
+            });
+            self.push_flow(
+                p.date,
+                p.amount,
+                account,
+                p.flow_id(),
+                p.kind,
+                &p.flow.cash_flow,
+            );
+        }
+    }
+
+    pub fn get(&self, i: usize) -> PaymentRow<'_> {
+        PaymentRow {
+            date: self.date[i],
+            amount: self.amount[i],
+            kind: self.kind[i],
+            account: self.account[i].map(|a| &self.accounts[a as usize]),
+            flow: &self.flows[self.flow[i] as usize],
+        }
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = PaymentRow<'_>> + '_ {
+        (0..self.len()).map(move |i| self.get(i))
+    }
+
+    /// The payments as rows, each with its own copy of the cash flow.
+    pub fn to_payments(&self) -> Vec<Payment> {
+        self.iter().map(|p| p.to_payment()).collect()
+    }
+
+    /// A frame with `date`, `account_id`, `account`, `flow_id`, `cash_flow`, `category`,
+    /// `kind`, `amount` and `currency` columns, one row per payment.
+    pub fn data_frame(&self) -> Result<DataFrame> {
+        let text = |f: &dyn Fn(PaymentRow) -> Option<String>| -> Vec<Option<String>> {
+            self.iter().map(f).collect()
+        };
+        Ok(DataFrame::new(vec![
+            Series::new("date", &self.date),
+            Series::new("account_id", text(&|p| p.account_id().map(String::from))),
+            Series::new("account", text(&|p| p.account_name().map(String::from))),
+            Series::new("flow_id", text(&|p| p.flow_id().map(String::from))),
+            Series::new("cash_flow", text(&|p| Some(p.name().to_string()))),
+            Series::new("category", text(&|p| Some(p.category().to_string()))),
+            Series::new("kind", text(&|p| Some(p.kind.to_string()))),
+            Series::new(
+                "amount",
+                self.amount.iter().map(|a| a.to_f64()).collect::<Vec<f64>>(),
+            ),
+            Series::new(
+                "currency",
+                self.amount
+                    .iter()
+                    .map(|a| a.currency().code().to_string())
+                    .collect::<Vec<String>>(),
+            ),
+        ])?)
+    }
+}
+
+impl FromIterator<Payment> for PaymentTable {
+    fn from_iter<I: IntoIterator<Item = Payment>>(payments: I) -> PaymentTable {
+        let mut table = PaymentTable::new();
+        for p in payments {
+            table.push(&p);
+        }
+        table
+    }
+}
+
+/// Account balances stored as one column per field, with the accounts in a lookup table
+/// that the `account` column indexes into.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct BalanceTable {
+    pub accounts: Vec<AccountInfo>,
+    pub date: Vec<NaiveDate>,
+    pub account: Vec<u32>,
+    pub balance: Vec<Money>,
+    /// The balance in the scenario's reporting currency.
+    pub converted: Vec<Money>,
+    #[serde(skip)]
+    account_index: HashMap<String, u32>,
+}
+
+/// One balance of a [`BalanceTable`].
+#[derive(Clone, Copy, Debug)]
+pub struct BalanceRow<'a> {
+    pub date: NaiveDate,
+    pub account: &'a AccountInfo,
+    pub balance: Money,
+    pub converted: Money,
+}
+
+impl BalanceTable {
+    pub fn new() -> BalanceTable {
+        BalanceTable::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.date.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.date.is_empty()
+    }
+
+    /// Appends the balance of an account, by id and name, in its own currency and the
+    /// reporting currency.
+    pub fn push(
+        &mut self,
+        date: NaiveDate,
+        id: &str,
+        name: &str,
+        balance: Money,
+        converted: Money,
+    ) {
+        let account = intern_account(&mut self.accounts, &mut self.account_index, id, name);
+        self.date.push(date);
+        self.account.push(account);
+        self.balance.push(balance);
+        self.converted.push(converted);
+    }
+
+    /// Appends every balance of another table.
+    pub fn append(&mut self, other: &BalanceTable) {
+        for b in other.iter() {
+            self.push(
+                b.date,
+                &b.account.id,
+                &b.account.name,
+                b.balance,
+                b.converted,
+            );
+        }
+    }
+
+    pub fn get(&self, i: usize) -> BalanceRow<'_> {
+        BalanceRow {
+            date: self.date[i],
+            account: &self.accounts[self.account[i] as usize],
+            balance: self.balance[i],
+            converted: self.converted[i],
+        }
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = BalanceRow<'_>> + '_ {
+        (0..self.len()).map(move |i| self.get(i))
+    }
+
+    pub fn last(&self) -> Option<BalanceRow<'_>> {
+        self.len().checked_sub(1).map(|i| self.get(i))
+    }
+
+    /// A frame with `date`, `account_id`, `account`, `balance`, `currency` and `converted`
+    /// columns, one row per balance.
+    pub fn data_frame(&self) -> Result<DataFrame> {
+        let accounts = |f: &dyn Fn(&AccountInfo) -> String| -> Vec<String> {
+            self.account
+                .iter()
+                .map(|a| f(&self.accounts[*a as usize]))
+                .collect()
+        };
+        let f64s = |m: &[Money]| -> Vec<f64> { m.iter().map(|m| m.to_f64()).collect() };
+        Ok(DataFrame::new(vec![
+            Series::new("date", &self.date),
+            Series::new("account_id", accounts(&|a| a.id.clone())),
+            Series::new("account", accounts(&|a| a.name.clone())),
+            Series::new("balance", f64s(&self.balance)),
+            Series::new(
+                "currency",
+                self.balance
+                    .iter()
+                    .map(|b| b.currency().code().to_string())
+                    .collect::<Vec<String>>(),
+            ),
+            Series::new("converted", f64s(&self.converted)),
+        ])?)
+    }
+}
+
+// The serialized shape of a payment and a balance before results were stored as columns,
+// borrowing from the tables instead of copying every cash flow.
+#[derive(Serialize)]
+pub(crate) struct PaymentRecord<'a> {
+    cash_flow: &'a CashFlow,
+    date: NaiveDate,
+    amount: Money,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flow_id: Option<&'a str>,
+    kind: PaymentKind,
+}
+
+impl<'a> From<PaymentRow<'a>> for PaymentRecord<'a> {
+    fn from(p: PaymentRow<'a>) -> PaymentRecord<'a> {
+        PaymentRecord {
+            cash_flow: p.cash_flow(),
+            date: p.date,
+            amount: p.amount,
+            account: p.account_name(),
+            account_id: p.account_id(),
+            flow_id: p.flow_id(),
+            kind: p.kind,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct BalanceRecord<'a> {
+    date: NaiveDate,
+    account_name: &'a str,
+    account_id: &'a str,
+    balance: Money,
+    converted: Money,
+}
+
+impl<'a> From<BalanceRow<'a>> for BalanceRecord<'a> {
+    fn from(b: BalanceRow<'a>) -> BalanceRecord<'a> {
+        BalanceRecord {
+            date: b.date,
+            account_name: &b.account.name,
+            account_id: &b.account.id,
+            balance: b.balance,
+            converted: b.converted,
+        }
+    }
+}
+
+#[cfg(test)]
+mod columns_tests {
+    use super::*;
+    use crate::sim::cash::{Account, Frequency};
+
+    fn payments() -> Vec<Payment> {
+        let date = |m, d| NaiveDate::from_ymd_opt(2020, m, d).unwrap();
+        let flow = |name: &str, amount: f64, tax_rate| {
+            CashFlow::new(
+                Some(name.to_string()),
+                Money::try_from(amount).unwrap(),
+                Some(Frequency::MonthStart),
+                None,
+                None,
+                Some(tax_rate),
+            )
+        };
+        let mut account = Account::new(
+            "Checking".to_string(),
+            Money::try_from(0.0).unwrap(),
+            vec![flow("Salary", 3000.0, 0.2), flow("Rent", -1500.0, 0.0)],
+            date(1, 1),
+            date(12, 31),
+        );
+        account.payments(date(1, 1), date(6, 30)).unwrap()
+    }
+
+    #[test]
+    fn test_flows_are_stored_once() {
+        let payments = payments();
+        let table: PaymentTable = payments.iter().cloned().collect();
+        assert_eq!(table.len(), 18);
+        assert_eq!(table.accounts.len(), 1);
+        // salary, its tax and rent
+        assert_eq!(table.flows.len(), 3);
+
+        let rows = table.to_payments();
+        for (i, p) in payments.iter().enumerate() {
+            let expected = serde_json::to_value(p).unwrap();
+            assert_eq!(serde_json::to_value(&rows[i]).unwrap(), expected);
+            let record = PaymentRecord::from(table.get(i));
+            assert_eq!(serde_json::to_value(record).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_append_and_data_frame() {
+        let table: PaymentTable = payments().into_iter().collect();
+        let mut both = table.clone();
+        both.append(&table);
+        assert_eq!(both.len(), 36);
+        assert_eq!(both.flows.len(), 3);
+
+        let df = both.data_frame().unwrap();
+        assert_eq!(df.shape(), (36, 9));
+        let total: f64 = df.column("amount").unwrap().f64().unwrap().sum().unwrap();
+        assert_eq!(total, 2.0 * 6.0 * (3000.0 - 600.0 - 1500.0));
+    }
+}
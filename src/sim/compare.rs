@@ -0,0 +1,356 @@
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::cash::PaymentKind;
+use super::params::document;
+use super::scenario::Scenario;
+use super::terminal::Verbosity;
+use super::SimulationResult;
+use crate::{Error, Result};
+
+/// Balances of every scenario on one date, and each scenario's difference from the baseline.
+#[derive(Serialize, Clone, Debug)]
+pub struct BalanceDifference {
+    pub date: chrono::NaiveDate,
+    pub balances: Vec<f64>,
+    pub differences: Vec<f64>,
+}
+
+/// Total paid or received by one cash flow over the horizon in every scenario, with its taxes
+/// totalled separately.
+#[derive(Serialize, Clone, Debug)]
+pub struct CashFlowTotals {
+    pub cash_flow: String,
+    pub kind: PaymentKind,
+    pub totals: Vec<f64>,
+    pub differences: Vec<f64>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum CrossoverDirection {
+    Above,
+    Below,
+}
+
+/// A date on which a scenario's balance moves above or below the baseline balance.
+#[derive(Serialize, Clone, Debug)]
+pub struct Crossover {
+    pub date: chrono::NaiveDate,
+    pub scenario: String,
+    pub direction: CrossoverDirection,
+}
+
+/// A single input that differs between the baseline and another scenario. `baseline` or
+/// `value` is `None` when the field only exists on one side.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct InputDifference {
+    pub scenario: String,
+    pub path: String,
+    pub baseline: Option<Value>,
+    pub value: Option<Value>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct Comparison {
+    pub scenarios: Vec<String>,
+    pub start_date: chrono::NaiveDate,
+    pub end_date: chrono::NaiveDate,
+    pub results: Vec<SimulationResult>,
+    pub balances: Vec<BalanceDifference>,
+    pub cash_flows: Vec<CashFlowTotals>,
+    pub crossovers: Vec<Crossover>,
+    pub inputs: Vec<InputDifference>,
+}
+
+/// Runs every scenario over the same horizon and lines the results up against the first one.
+///
+/// When `horizon` is `None` the baseline scenario's start and end dates are used, and every
+/// other scenario is simulated over those dates regardless of its own. The scenario names are
+/// not reported as a differing input.
+pub fn compare(
+    scenarios: &[Scenario],
+    horizon: Option<(chrono::NaiveDate, chrono::NaiveDate)>,
+) -> Result<Comparison> {
+    let baseline = scenarios
+        .first()
+        .ok_or_else(|| Error::Invalid("compare needs at least one scenario".to_string()))?;
+    let (start_date, end_date) = horizon.unwrap_or((baseline.start_date, baseline.end_date));
+
+    let results: Vec<SimulationResult> = scenarios
+        .iter()
+        .map(|s| {
+            let mut s = s.clone();
+            s.start_date = start_date;
+            s.end_date = end_date;
+            s.run(Verbosity::Quiet)
+        })
+        .collect::<Result<_>>()?;
+
+    let names: Vec<String> = scenarios.iter().map(|s| s.name.clone()).collect();
+    let balances = balance_differences(&results);
+    let crossovers = crossovers(&names, &balances);
+
+    let base_doc = document(baseline)?;
+    let mut inputs = vec![];
+    for s in &scenarios[1..] {
+        let doc = document(s)?;
+        for (path, b, v) in diff_documents(&base_doc, &doc) {
+            if path == "name" {
+                continue;
+            }
+            inputs.push(InputDifference {
+                scenario: s.name.clone(),
+                path,
+                baseline: b,
+                value: v,
+            });
+        }
+    }
+
+    Ok(Comparison {
+        scenarios: names,
+        start_date,
+        end_date,
+        cash_flows: cash_flow_totals(&results),
+        results,
+        balances,
+        crossovers,
+        inputs,
+    })
+}
+
+fn balance_differences(results: &[SimulationResult]) -> Vec<BalanceDifference> {
+    let by_date: Vec<HashMap<chrono::NaiveDate, f64>> = results
+        .iter()
+        .map(|r| r.total_balances().into_iter().collect())
+        .collect();
+
+    results[0]
+        .total_balances()
+        .into_iter()
+        .map(|(date, baseline)| {
+            let balances: Vec<f64> = by_date
+                .iter()
+                .map(|m| *m.get(&date).unwrap_or(&0.0))
+                .collect();
+            let differences = balances.iter().map(|v| v - baseline).collect();
+            BalanceDifference {
+                date,
+                balances,
+                differences,
+            }
+        })
+        .collect()
+}
+
+fn cash_flow_totals(results: &[SimulationResult]) -> Vec<CashFlowTotals> {
+    let mut names: Vec<(String, PaymentKind)> = vec![];
+    let mut totals: HashMap<(String, PaymentKind), Vec<Decimal>> = HashMap::new();
+    for (i, r) in results.iter().enumerate() {
+        for p in r.payments.iter() {
+            let key = (p.name().to_string(), p.kind);
+            let t = totals.entry(key.clone()).or_insert_with(|| {
+                names.push(key);
+                vec![Decimal::ZERO; results.len()]
+            });
+            t[i] += p.amount.amount();
+        }
+    }
+
+    names
+        .into_iter()
+        .map(|(name, kind)| {
+            let totals: Vec<f64> = totals
+                .remove(&(name.clone(), kind))
+                .unwrap()
+                .iter()
+                .map(|t| t.to_f64().unwrap_or_default())
+                .collect();
+            let differences = totals.iter().map(|t| t - totals[0]).collect();
+            CashFlowTotals {
+                cash_flow: name,
+                kind,
+                totals,
+                differences,
+            }
+        })
+        .collect()
+}
+
+fn crossovers(names: &[String], balances: &[BalanceDifference]) -> Vec<Crossover> {
+    let mut crossovers = vec![];
+    for (i, name) in names.iter().enumerate().skip(1) {
+        let mut previous: Option<CrossoverDirection> = None;
+        for b in balances {
+            let d = b.differences[i];
+            let direction = if d > 0.0 {
+                CrossoverDirection::Above
+            } else if d < 0.0 {
+                CrossoverDirection::Below
+            } else {
+                continue;
+            };
+            if previous.is_some() && previous != Some(direction) {
+                crossovers.push(Crossover {
+                    date: b.date,
+                    scenario: name.clone(),
+                    direction,
+                });
+            }
+            previous = Some(direction);
+        }
+    }
+    crossovers.sort_by_key(|c| c.date);
+    crossovers
+}
+
+/// Structural diff of two serialized scenarios, as `(path, baseline, other)` triples.
+///
+/// Paths use the same syntax as [`super::params::ParameterPath`]. Arrays whose elements all
+/// carry a `name` are matched by name, so reordering cash flows is not reported as a change.
+pub fn diff_documents(baseline: &Value, other: &Value) -> Vec<(String, Option<Value>, Option<Value>)> {
+    let mut out = vec![];
+    diff_values("", Some(baseline), Some(other), &mut out);
+    out
+}
+
+fn diff_values(
+    path: &str,
+    baseline: Option<&Value>,
+    other: Option<&Value>,
+    out: &mut Vec<(String, Option<Value>, Option<Value>)>,
+) {
+    let child = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", path, key)
+        }
+    };
+
+    match (baseline, other) {
+        (Some(Value::Object(a)), Some(Value::Object(b))) => {
+            for (k, v) in a {
+                diff_values(&child(k), Some(v), b.get(k), out);
+            }
+            for (k, v) in b {
+                if !a.contains_key(k) {
+                    diff_values(&child(k), None, Some(v), out);
+                }
+            }
+        }
+        (Some(Value::Array(a)), Some(Value::Array(b))) => {
+            let named = |items: &Vec<Value>| {
+                items
+                    .iter()
+                    .all(|i| i.get("name").map(|n| n.is_string()).unwrap_or(false))
+            };
+            if named(a) && named(b) {
+                let name = |v: &Value| v["name"].as_str().unwrap().to_string();
+                for v in a {
+                    let other = b.iter().find(|o| name(o) == name(v));
+                    diff_values(&child(&name(v)), Some(v), other, out);
+                }
+                for v in b {
+                    if !a.iter().any(|o| name(o) == name(v)) {
+                        diff_values(&child(&name(v)), None, Some(v), out);
+                    }
+                }
+            } else {
+                for i in 0..a.len().max(b.len()) {
+                    diff_values(&child(&i.to_string()), a.get(i), b.get(i), out);
+                }
+            }
+        }
+        (a, b) => {
+            if a != b {
+                out.push((path.to_string(), a.cloned(), b.cloned()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod compare_tests {
+    use super::*;
+    use crate::sim::cash::{Account, CashFlow, Frequency};
+    use crate::sim::money::Money;
+    use crate::sim::testing;
+
+    fn scenario(name: &str, account: Account) -> Scenario {
+        let mut s = Scenario::from_account(account, None);
+        s.name = name.to_string();
+        s
+    }
+
+    fn account(rent: f64, end_date: chrono::NaiveDate) -> Account {
+        let mut account = testing::account(1000.0, &[("Income", 500.0), ("Housing", rent)]);
+        account.end_date = end_date;
+        account
+    }
+
+    #[test]
+    fn test_compare() {
+        let end = chrono::NaiveDate::from_ymd_opt(2020, 12, 31).unwrap();
+        let mut buy = account(-800.0, chrono::NaiveDate::from_ymd_opt(2021, 6, 30).unwrap());
+        buy.cash_flows.push(CashFlow::new(
+            Some("Down Payment".to_string()),
+            Money::try_from(-1500.0).unwrap(),
+            Some(Frequency::Once),
+            Some(chrono::NaiveDate::from_ymd_opt(2020, 1, 1).unwrap()),
+            None,
+            None,
+        ));
+        let scenarios = vec![
+            scenario("Rent", account(-400.0, end)),
+            scenario("Buy", buy),
+        ];
+        let c = compare(&scenarios, None).unwrap();
+
+        // both scenarios use the baseline horizon
+        assert_eq!(c.balances.len(), c.results[0].balances.len());
+        assert_eq!(c.results[1].balances.last().unwrap().date, end.pred_opt().unwrap());
+
+        let housing = c.cash_flows.iter().find(|t| t.cash_flow == "Housing").unwrap();
+        assert_eq!(housing.totals, vec![-4800.0, -9600.0]);
+        assert_eq!(housing.differences, vec![0.0, -4800.0]);
+        let down = c.cash_flows.iter().find(|t| t.cash_flow == "Down Payment").unwrap();
+        assert_eq!(down.totals, vec![0.0, -1500.0]);
+
+        let paths: Vec<&str> = c.inputs.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "accounts.Test Account.cash_flows.Housing.amount",
+                "accounts.Test Account.cash_flows.Down Payment",
+                "accounts.Test Account.end_date",
+                "end_date",
+            ]
+        );
+
+        assert!(compare(&[], None).is_err());
+    }
+
+    #[test]
+    fn test_crossovers() {
+        let end = chrono::NaiveDate::from_ymd_opt(2020, 12, 31).unwrap();
+        let baseline = account(-400.0, end);
+        let mut other = account(-600.0, end);
+        other.balance = Money::try_from(2000.0).unwrap();
+        let c = compare(
+            &[scenario("Baseline", baseline), scenario("Other", other)],
+            None,
+        )
+        .unwrap();
+        // starts 1000 ahead and falls 200 behind per month, so it drops below in June
+        assert_eq!(c.crossovers.len(), 1);
+        assert_eq!(
+            c.crossovers[0].date,
+            chrono::NaiveDate::from_ymd_opt(2020, 6, 1).unwrap()
+        );
+        assert_eq!(c.crossovers[0].direction, CrossoverDirection::Below);
+    }
+}
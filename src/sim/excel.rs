@@ -1,41 +1,832 @@
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, BTreeSet};
 use xlsxwriter::prelude::*;
+use xlsxwriter::worksheet::validation::{DataValidation, DataValidationType};
 
-pub fn write_sim(results: super::SimulationResult, file: &str) {
-    let workbook = Workbook::new(file).unwrap();
-    write_account_balance(&workbook, &results);
-    write_cash_flows(&workbook, &results);
-    workbook.close().unwrap();
+use super::cash::Frequency;
+use super::compare::Comparison;
+use super::money::Currency;
+use super::monte_carlo::MonteCarloResult;
+use super::rollup::{rollup, RollupKey};
+use super::scenario::Scenario;
+use super::statement::statements;
+use super::variance::{Period, VarianceReport};
+use crate::Result;
+
+/// Writes the results of a simulation, and the percentile bands of its Monte Carlo paths if
+/// they were drawn, with a "Charts" sheet plotting them.
+pub fn write_sim(
+    results: super::SimulationResult,
+    monte_carlo: Option<&MonteCarloResult>,
+    file: &str,
+) -> Result<()> {
+    let workbook = Workbook::new(file)?;
+    write_account_balance(&workbook, &results)?;
+    write_cash_flows(&workbook, &results)?;
+    write_category_pivot(&workbook, &results)?;
+    write_statements(&workbook, &results, Period::Month, "Monthly Statements")?;
+    write_statements(&workbook, &results, Period::Year, "Annual Statements")?;
+    if results
+        .balances
+        .iter()
+        .any(|b| b.balance.currency() != b.converted.currency())
+    {
+        write_consolidated(&workbook, &results)?;
+    }
+    if !results.reconciliations.is_empty() {
+        write_reconciliations(&workbook, &results)?;
+    }
+    write_charts(&workbook, &results, monte_carlo)?;
+    workbook.close()?;
+    Ok(())
+}
+
+fn write_account_balance(workbook: &Workbook, results: &super::SimulationResult) -> Result<()> {
+    let mut sheet = workbook.add_worksheet(Some("Account Balance"))?;
+    write_balance_columns(&mut sheet, 0, results)
+}
+
+fn write_cash_flows(workbook: &Workbook, results: &super::SimulationResult) -> Result<()> {
+    let mut sheet = workbook.add_worksheet(Some("Cash Flows"))?;
+    write_cash_flow_columns(&mut sheet, 0, results)
+}
+
+/// Payment totals with a row per category, subcategories indented under their parent, and a
+/// column per month, or per year for simulations longer than three years.
+fn write_category_pivot(workbook: &Workbook, results: &super::SimulationResult) -> Result<()> {
+    let mut sheet = workbook.add_worksheet(Some("Categories"))?;
+    let dates = &results.payments.date;
+    let period = match (dates.iter().min(), dates.iter().max()) {
+        (Some(first), Some(last)) if (*last - *first).num_days() > 3 * 366 => Period::Year,
+        _ => Period::Month,
+    };
+    let rows = rollup(&results.payments, RollupKey::Category, period)?;
+
+    let mut periods: Vec<&str> = rows.iter().map(|r| r.period.as_str()).collect();
+    periods.dedup();
+    let mut categories: Vec<(Vec<&str>, usize, Currency)> = rows
+        .iter()
+        .map(|r| (r.key.split(':').collect(), r.depth, r.total.currency()))
+        .collect();
+    categories.sort();
+    categories.dedup();
+
+    sheet.write_string(0, 0, "Category", None)?;
+    sheet.write_string(0, 1, "Currency", None)?;
+    for (col, p) in (2..).zip(periods.iter()) {
+        sheet.write_string(0, col, p, None)?;
+    }
+    for (row, (segments, depth, currency)) in (1..).zip(categories.iter()) {
+        let name = format!("{}{}", "  ".repeat(*depth), segments[*depth]);
+        sheet.write_string(row, 0, &name, None)?;
+        sheet.write_string(row, 1, currency.code(), None)?;
+        for r in rows.iter().filter(|r| {
+            r.key.split(':').eq(segments.iter().copied()) && r.total.currency() == *currency
+        }) {
+            let col = 2 + periods.iter().position(|p| *p == r.period).unwrap() as u16;
+            sheet.write_number(row, col, r.total.to_f64(), None)?;
+        }
+    }
+    Ok(())
+}
+
+/// One row per account and period, see [`statements`].
+fn write_statements(
+    workbook: &Workbook,
+    results: &super::SimulationResult,
+    period: Period,
+    name: &str,
+) -> Result<()> {
+    let mut sheet = workbook.add_worksheet(Some(name))?;
+    let headers = [
+        "Period",
+        "Account",
+        "Opening",
+        "Inflows",
+        "Outflows",
+        "Taxes",
+        "Growth",
+        "Transfers",
+        "Closing",
+        "Savings Rate",
+    ];
+    for (col, h) in (0..).zip(headers.iter()) {
+        sheet.write_string(0, col, h, None)?;
+    }
+    for (row, s) in (1..).zip(statements(results, period)?.iter()) {
+        sheet.write_string(row, 0, &s.period, None)?;
+        sheet.write_string(row, 1, &s.account_name, None)?;
+        let amounts = [
+            s.opening, s.inflows, s.outflows, s.taxes, s.growth, s.transfers, s.closing,
+        ];
+        for (col, a) in (2..).zip(amounts.iter()) {
+            sheet.write_number(row, col, a.to_f64(), None)?;
+        }
+        if let Some(rate) = s.savings_rate {
+            sheet.write_number(row, 9, rate, None)?;
+        }
+    }
+    Ok(())
+}
+
+const FREQUENCIES: [&str; 7] = [
+    "Once",
+    "MonthStart",
+    "MonthEnd",
+    "SemiMonthly",
+    "Annually",
+    "Weekly",
+    "BiWeekly",
+];
+
+/// Writes a scenario as a workbook that forecasts with Excel formulas instead of simulated
+/// numbers, so the forecast follows any assumption edited in Excel.
+///
+/// The "Assumptions" sheet holds the start date, every account's opening balance and expected
+/// monthly return, and every cash flow's amount, frequency, dates and tax rate, each as a
+/// named cell such as `checking.rent_amount`. The "Schedule" sheet computes each cash flow's
+/// payments after tax in every month from them, and the "Forecast" sheet each account's
+/// closing balance. Returns are the weighted mean returns of the account's portfolios, and
+/// accounts are forecast in their own currencies.
+pub fn write_formulas(scenario: &Scenario, file: &str) -> Result<()> {
+    let workbook = Workbook::new(file)?;
+    let mut assumptions = workbook.add_worksheet(Some("Assumptions"))?;
+    let mut schedule = workbook.add_worksheet(Some("Schedule"))?;
+    let mut forecast = workbook.add_worksheet(Some("Forecast"))?;
+    let mut date_format = Format::new();
+    date_format.set_num_format("yyyy-mm-dd");
+    let mut month_format = Format::new();
+    month_format.set_num_format("yyyy-mm");
+    let mut money_format = Format::new();
+    money_format.set_num_format("#,##0.00");
+    let mut rate_format = Format::new();
+    rate_format.set_num_format("0.00%");
+
+    let mut accounts = scenario.accounts.clone();
+    for a in accounts.iter_mut() {
+        a.assign_ids();
+    }
+    let name = |sheet: &str, row: u32, col: u16, name: &str| {
+        let cell = format!("={}!${}${}", sheet, column_name(col), row + 1);
+        workbook.define_name(name, &cell)
+    };
+
+    assumptions.write_string(0, 0, "Start Date", None)?;
+    assumptions.write_datetime(0, 1, &scenario.start_date.into(), Some(&date_format))?;
+    name("Assumptions", 0, 1, "start_date")?;
+
+    let headers = ["Account", "Id", "Opening Balance", "Monthly Return", "Currency"];
+    for (col, h) in (0..).zip(headers.iter()) {
+        assumptions.write_string(2, col, h, None)?;
+    }
+    for (row, a) in (3..).zip(accounts.iter()) {
+        let id = a.id();
+        let monthly_return = scenario
+            .portfolios
+            .iter()
+            .filter(|p| p.account == a.name)
+            .map(|p| {
+                let assets = p.portfolio.assets.iter().zip(p.portfolio.weights.iter());
+                assets.map(|(asset, w)| asset.mean_return * w).sum::<f64>()
+            })
+            .sum::<f64>()
+            * rebalances_per_month(&scenario.rebalance_frequency);
+        assumptions.write_string(row, 0, &a.name, None)?;
+        assumptions.write_string(row, 1, &id, None)?;
+        assumptions.write_number(row, 2, a.balance.to_f64(), Some(&money_format))?;
+        assumptions.write_number(row, 3, monthly_return, Some(&rate_format))?;
+        assumptions.write_string(row, 4, a.balance.currency().code(), None)?;
+        name("Assumptions", row, 2, &defined_name(&id, "balance"))?;
+        name("Assumptions", row, 3, &defined_name(&id, "return"))?;
+    }
+
+    // cash flows are active between the later of their own and their account's start date
+    // and the earlier of the end dates, as when simulated
+    let first = accounts.len() as u32 + 4;
+    let headers = [
+        "Account",
+        "Cash Flow",
+        "Id",
+        "Amount",
+        "Frequency",
+        "Start Date",
+        "End Date",
+        "Tax Rate",
+    ];
+    for (col, h) in (0..).zip(headers.iter()) {
+        assumptions.write_string(first, col, h, None)?;
+    }
+    let frequencies = DataValidation::new(
+        DataValidationType::List {
+            ignore_blank: false,
+            dropdown: true,
+            values: FREQUENCIES.iter().map(|f| f.to_string()).collect(),
+        },
+        None,
+        None,
+    );
+    let mut flows: Vec<(usize, String, String)> = vec![];
+    for (i, a) in accounts.iter().enumerate() {
+        for f in &a.cash_flows {
+            let id = f.id.clone().unwrap_or_default();
+            let mut start = f.start_date.map_or(a.start_date, |d| d.max(a.start_date));
+            // weekly flows keep the day of the week they started on
+            if matches!(f.frequency, Frequency::Weekly | Frequency::BiWeekly) {
+                while !f.frequency.matches(&start, &f.start_date, &None)? {
+                    start += chrono::Duration::days(1);
+                }
+            }
+            let last_day = a.end_date.pred_opt().unwrap_or(a.end_date);
+            let end = f.end_date.map_or(last_day, |d| d.min(last_day));
+            let row = first + 1 + flows.len() as u32;
+            let flow_name = f.name.clone().unwrap_or_else(|| id.clone());
+            assumptions.write_string(row, 0, &a.name, None)?;
+            assumptions.write_string(row, 1, &flow_name, None)?;
+            assumptions.write_string(row, 2, &id, None)?;
+            assumptions.write_number(row, 3, f.amount.to_f64(), Some(&money_format))?;
+            assumptions.write_string(row, 4, &format!("{:?}", f.frequency), None)?;
+            assumptions.data_validation_cell(row, 4, &frequencies)?;
+            assumptions.write_datetime(row, 5, &start.into(), Some(&date_format))?;
+            assumptions.write_datetime(row, 6, &end.into(), Some(&date_format))?;
+            assumptions.write_number(row, 7, f.tax_rate, Some(&rate_format))?;
+            for (col, field) in [(3, "amount"), (4, "frequency"), (5, "start"), (6, "end")] {
+                name("Assumptions", row, col, &defined_name(&id, field))?;
+            }
+            name("Assumptions", row, 7, &defined_name(&id, "tax_rate"))?;
+            flows.push((i, flow_name, id));
+        }
+    }
+    assumptions.set_column(0, 2, 18.0, None)?;
+    assumptions.set_column(3, 7, 14.0, None)?;
+
+    // a row per month from the start date up to the end date
+    let mut months = 0;
+    let mut month = Period::Month.start(scenario.start_date);
+    while month < scenario.end_date {
+        months += 1;
+        month = Period::Month.next(month);
+    }
+    schedule.write_string(0, 0, "Month", None)?;
+    forecast.write_string(0, 0, "Month", None)?;
+    for (col, (i, flow_name, _)) in (1..).zip(flows.iter()) {
+        let header = format!("{}: {}", accounts[*i].name, flow_name);
+        schedule.write_string(0, col, &header, None)?;
+    }
+    for (col, a) in (1..).zip(accounts.iter()) {
+        forecast.write_string(0, col, &a.name, None)?;
+    }
+    for row in 1..=months {
+        let r = row + 1;
+        let month = match row {
+            1 => "=DATE(YEAR(start_date),MONTH(start_date),1)".to_string(),
+            _ => format!("=DATE(YEAR(A{0}),MONTH(A{0})+1,1)", r - 1),
+        };
+        schedule.write_formula(row, 0, &month, Some(&month_format))?;
+        forecast.write_formula(row, 0, &format!("=Schedule!A{}", r), Some(&month_format))?;
+        for (col, (_, _, id)) in (1..).zip(flows.iter()) {
+            let payments = format!(
+                "={}*(1-{})*{}",
+                defined_name(id, "amount"),
+                defined_name(id, "tax_rate"),
+                occurrences(id, r)
+            );
+            schedule.write_formula(row, col, &payments, Some(&money_format))?;
+        }
+        for (col, a) in (1..).zip(accounts.iter()) {
+            let i = col as usize - 1;
+            let previous = match row {
+                1 => defined_name(&a.id(), "balance"),
+                _ => format!("{}{}", column_name(col), r - 1),
+            };
+            let mut balance = format!("={0}*(1+{1})", previous, defined_name(&a.id(), "return"));
+            // an account's flows are next to each other on the schedule
+            let columns: Vec<u16> = (1..)
+                .zip(flows.iter())
+                .filter(|(_, f)| f.0 == i)
+                .map(|(c, _)| c)
+                .collect();
+            if let (Some(first), Some(last)) = (columns.first(), columns.last()) {
+                balance += &format!(
+                    "+SUM(Schedule!{}{2}:{}{2})",
+                    column_name(*first),
+                    column_name(*last),
+                    r
+                );
+            }
+            forecast.write_formula(row, col, &balance, Some(&money_format))?;
+        }
+    }
+    schedule.set_column(1, flows.len() as u16, 18.0, None)?;
+    forecast.set_column(1, accounts.len() as u16, 14.0, None)?;
+
+    workbook.close()?;
+    Ok(())
 }
 
-fn write_account_balance(workbook: &Workbook, results: &super::SimulationResult) {
-    let mut sheet = workbook.add_worksheet(Some("Account Balance")).unwrap();
-    sheet.write_string(0, 0, "Date", None).unwrap();
-    sheet.write_string(0, 1, "Account", None).unwrap();
-    sheet.write_string(0, 2, "Balance", None).unwrap();
+// How many payments of a cash flow fall in the month starting in column A of `row`, as an
+// Excel formula over the flow's named frequency, start and end.
+fn occurrences(id: &str, row: u32) -> String {
+    let [frequency, start, end] = ["frequency", "start", "end"].map(|f| defined_name(id, f));
+    let first = format!("$A{}", row);
+    let last = format!("DATE(YEAR({0}),MONTH({0})+1,0)", first);
+    let between = |d: &str| format!("({0}>={1})*({0}<={2})", d, start, end);
+    let anniversary = format!("DATE(YEAR({}),MONTH({1}),DAY({1}))", first, start);
+    // whole periods from the start to the last day in the month and the flow, less those
+    // before the first
+    let every = |days: u32| {
+        format!(
+            "MAX(0,INT((MIN({last},{e})-{s})/{d})-ROUNDUP((MAX({first},{s})-{s})/{d},0)+1)",
+            last = last,
+            e = end,
+            s = start,
+            first = first,
+            d = days
+        )
+    };
+    format!(
+        "IF({f}=\"Once\",({s}>={first})*({s}<={last}),\
+         IF({f}=\"MonthStart\",{month_start},\
+         IF({f}=\"MonthEnd\",{month_end},\
+         IF({f}=\"SemiMonthly\",{fifteenth}+{month_end},\
+         IF({f}=\"Annually\",(MONTH({s})=MONTH({first}))*{annually},\
+         IF({f}=\"Weekly\",{weekly},\
+         IF({f}=\"BiWeekly\",{biweekly},0)))))))",
+        f = frequency,
+        s = start,
+        first = first,
+        last = last,
+        month_start = between(&first),
+        month_end = between(&last),
+        fifteenth = between(&format!("({}+14)", first)),
+        annually = between(&anniversary),
+        weekly = every(7),
+        biweekly = every(14),
+    )
+}
+
+fn rebalances_per_month(frequency: &Frequency) -> f64 {
+    match frequency {
+        Frequency::Once => 0.0,
+        Frequency::MonthStart | Frequency::MonthEnd => 1.0,
+        Frequency::SemiMonthly => 2.0,
+        Frequency::Annually => 1.0 / 12.0,
+        Frequency::Weekly => 365.25 / 12.0 / 7.0,
+        Frequency::BiWeekly => 365.25 / 12.0 / 14.0,
+    }
+}
+
+// Excel names may not contain dashes or start with a digit, so `joint-checking` and `balance`
+// become `joint_checking_balance`
+fn defined_name(id: &str, field: &str) -> String {
+    let name = format!("{}_{}", id.replace('-', "_"), field);
+    match name.starts_with(|c: char| c.is_ascii_digit()) {
+        true => format!("_{}", name),
+        false => name,
+    }
+}
 
-    let mut row = 1;
-    for b in &results.balances {
-        sheet.write_datetime(row, 0, &b.date.into(), None).unwrap();
-        sheet.write_string(row, 1, &b.account_name, None).unwrap();
-        sheet.write_number(row, 2, b.balance, None).unwrap();
-        row += 1;
+/// The letters of a zero-based column: A, B, ..., Z, AA, AB, ...
+pub(crate) fn column_name(col: u16) -> String {
+    let mut name = String::new();
+    let mut n = col as u32 + 1;
+    while n > 0 {
+        let rem = (n - 1) % 26;
+        name.insert(0, (b'A' + rem as u8) as char);
+        n = (n - 1) / 26;
     }
+    name
 }
 
-fn write_cash_flows(workbook: &Workbook, results: &super::SimulationResult) {
-    let mut sheet = workbook.add_worksheet(Some("Cash Flows")).unwrap();
-    sheet.write_string(0, 0, "Date", None).unwrap();
-    sheet.write_string(0, 1, "Cash Flow", None).unwrap();
-    sheet.write_string(0, 2, "Amount", None).unwrap();
+const CHART_DATA: &str = "Chart Data";
 
-    let mut row = 1;
-    for f in &results.payments {
-        let name = &f.cash_flow.name;
-        sheet.write_datetime(row, 0, &f.date.into(), None).unwrap();
-        sheet
-            .write_string(row, 1, name.as_ref().unwrap(), None)
-            .unwrap();
-        sheet.write_number(row, 2, f.amount, None).unwrap();
-        row += 1;
+/// A "Charts" sheet with a balance line chart per account, a stacked column chart of monthly
+/// totals by top-level category and, given Monte Carlo results, a fan chart of the combined
+/// balance between each pair of neighbouring percentiles. The charted values are written to
+/// a "Chart Data" sheet after it.
+fn write_charts(
+    workbook: &Workbook,
+    results: &super::SimulationResult,
+    monte_carlo: Option<&MonteCarloResult>,
+) -> Result<()> {
+    let mut sheet = workbook.add_worksheet(Some("Charts"))?;
+    let mut data = workbook.add_worksheet(Some(CHART_DATA))?;
+    let mut date_format = Format::new();
+    date_format.set_num_format("yyyy-mm-dd");
+    let mut charts: Vec<Chart> = vec![];
+
+    // balances, a row per date and a column per account
+    let (dates, accounts, balances) = balance_pivot(results);
+    data.write_string(0, 0, "Date", None)?;
+    for (row, d) in (1..).zip(dates.iter()) {
+        data.write_datetime(row, 0, &(*d).into(), Some(&date_format))?;
+    }
+    for ((row, col), b) in &balances {
+        data.write_number(*row, *col, *b, None)?;
+    }
+    for (col, name) in (1..).zip(accounts.iter()) {
+        data.write_string(0, col, name, None)?;
+        let mut chart = workbook.add_chart(ChartType::Line);
+        chart.add_title(&format!("{} Balance", name))?;
+        let mut series = chart.add_series(None, None)?;
+        series.set_categories(CHART_DATA, 1, 0, dates.len() as u32, 0)?;
+        series.set_values(CHART_DATA, 1, col, dates.len() as u32, col)?;
+        series.set_name(name)?;
+        charts.push(chart);
+    }
+
+    // monthly totals by top level category, inflows stacked above zero and outflows below
+    let first = accounts.len() as u16 + 2;
+    let rows = rollup(&results.payments, RollupKey::Category, Period::Month)?;
+    let mut months: Vec<&str> = rows.iter().map(|r| r.period.as_str()).collect();
+    months.dedup();
+    let mut categories: BTreeMap<&str, BTreeMap<usize, f64>> = BTreeMap::new();
+    for r in rows.iter().filter(|r| r.depth == 0) {
+        let month = months.iter().position(|m| *m == r.period).unwrap();
+        *categories.entry(&r.key).or_default().entry(month).or_default() += r.total.to_f64();
+    }
+    data.write_string(0, first, "Month", None)?;
+    for (row, m) in (1..).zip(months.iter()) {
+        data.write_string(row, first, m, None)?;
+    }
+    let mut chart = workbook.add_chart(ChartType::ColumnStacked);
+    chart.add_title("Monthly Cash Flows by Category")?;
+    for (col, (category, totals)) in (first + 1..).zip(categories.iter()) {
+        data.write_string(0, col, category, None)?;
+        for (month, total) in totals {
+            data.write_number(1 + *month as u32, col, *total, None)?;
+        }
+        let mut series = chart.add_series(None, None)?;
+        series.set_categories(CHART_DATA, 1, first, months.len() as u32, first)?;
+        series.set_values(CHART_DATA, 1, col, months.len() as u32, col)?;
+        series.set_name(category)?;
     }
+    if !categories.is_empty() {
+        charts.push(chart);
+    }
+
+    // Monte Carlo percentiles, then the lowest one and the width of each band above it to
+    // stack into a fan with the area below the lowest percentile left unfilled
+    if let Some(mc) = monte_carlo.filter(|mc| !mc.bands.is_empty()) {
+        let first = first + categories.len() as u16 + 2;
+        let n = mc.percentiles.len() as u16;
+        let last_row = mc.bands.len() as u32;
+        data.write_string(0, first, "Date", None)?;
+        for (col, p) in (first + 1..).zip(mc.percentiles.iter()) {
+            data.write_string(0, col, &format!("P{}", p), None)?;
+        }
+        let widths = first + n + 2;
+        for (i, p) in (0..).zip(mc.percentiles.iter()) {
+            let header = match i {
+                0 => format!("Below P{}", p),
+                _ => format!("P{}-P{}", mc.percentiles[i as usize - 1], p),
+            };
+            data.write_string(0, widths + i, &header, None)?;
+        }
+        for (row, band) in (1..).zip(mc.bands.iter()) {
+            data.write_datetime(row, first, &band.date.into(), Some(&date_format))?;
+            let mut below = 0.0;
+            for (col, b) in (0..).zip(band.balances.iter()) {
+                data.write_number(row, first + 1 + col, *b, None)?;
+                data.write_number(row, widths + col, b - below, None)?;
+                below = *b;
+            }
+        }
+
+        let mut chart = workbook.add_chart(ChartType::AreaStacked);
+        chart.add_title(&format!("Combined Balance over {} Paths", mc.samples))?;
+        for col in 0..n {
+            let mut series = chart.add_series(None, None)?;
+            series.set_categories(CHART_DATA, 1, first, last_row, first)?;
+            series.set_values(CHART_DATA, 1, widths + col, last_row, widths + col)?;
+            series.set_name_range(CHART_DATA, 0, widths + col)?;
+            let mut fill = ChartFill::new();
+            // the inner bands are the most opaque
+            let inner = col.min(n - col) as u8;
+            fill.color = FormatColor::Custom(0x4472C4);
+            fill.none = col == 0;
+            fill.transparency = 80u8.saturating_sub(20 * inner).max(20);
+            series.set_fill(&fill);
+        }
+        charts.push(chart);
+    }
+
+    // two charts side by side, each about 15 rows by 8 columns
+    for (i, chart) in (0..).zip(charts.iter()) {
+        sheet.insert_chart(1 + 16 * (i / 2), 1 + 9 * (i % 2) as u16, chart)?;
+    }
+    Ok(())
+}
+
+// cells of the chart data sheet by (row, column)
+type Cells = BTreeMap<(u32, u16), f64>;
+
+// Dates with a recorded balance, account names in the order they were simulated, and the
+// balances in their cells on the chart data sheet.
+fn balance_pivot(results: &super::SimulationResult) -> (Vec<NaiveDate>, Vec<&str>, Cells) {
+    let dates: Vec<NaiveDate> = results
+        .balances
+        .date
+        .iter()
+        .copied()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let mut accounts: Vec<&str> = vec![];
+    let mut balances = BTreeMap::new();
+    for b in results.balances.iter() {
+        let col = match accounts.iter().position(|a| *a == b.account.name) {
+            Some(i) => i,
+            None => {
+                accounts.push(&b.account.name);
+                accounts.len() - 1
+            }
+        };
+        let row = dates.binary_search(&b.date).unwrap();
+        balances.insert((1 + row as u32, 1 + col as u16), b.balance.to_f64());
+    }
+    (dates, accounts, balances)
+}
+
+fn write_consolidated(workbook: &Workbook, results: &super::SimulationResult) -> Result<()> {
+    let mut sheet = workbook.add_worksheet(Some("Consolidated"))?;
+    sheet.write_string(0, 0, "Date", None)?;
+    sheet.write_string(0, 1, "Balance", None)?;
+    sheet.write_string(0, 2, "FX Gain/Loss", None)?;
+    sheet.write_string(0, 3, "Currency", None)?;
+
+    for (row, c) in (1..).zip(results.consolidated.iter()) {
+        sheet.write_datetime(row, 0, &c.date.into(), None)?;
+        sheet.write_number(row, 1, c.balance.to_f64(), None)?;
+        sheet.write_number(row, 2, c.fx_gain_loss.to_f64(), None)?;
+        sheet.write_string(row, 3, c.balance.currency().code(), None)?;
+    }
+    Ok(())
+}
+
+fn write_reconciliations(workbook: &Workbook, results: &super::SimulationResult) -> Result<()> {
+    let mut sheet = workbook.add_worksheet(Some("Reconciliation"))?;
+    sheet.write_string(0, 0, "Date", None)?;
+    sheet.write_string(0, 1, "Account", None)?;
+    sheet.write_string(0, 2, "Projected", None)?;
+    sheet.write_string(0, 3, "Actual", None)?;
+    sheet.write_string(0, 4, "Difference", None)?;
+
+    for (row, r) in (1..).zip(results.reconciliations.iter()) {
+        sheet.write_datetime(row, 0, &r.date.into(), None)?;
+        sheet.write_string(row, 1, &r.account_name, None)?;
+        sheet.write_number(row, 2, r.projected.to_f64(), None)?;
+        sheet.write_number(row, 3, r.actual.to_f64(), None)?;
+        sheet.write_number(row, 4, r.difference.to_f64(), None)?;
+    }
+    Ok(())
+}
+
+fn write_balance_columns(
+    sheet: &mut Worksheet,
+    col: u16,
+    results: &super::SimulationResult,
+) -> Result<()> {
+    sheet.write_string(0, col, "Date", None)?;
+    sheet.write_string(0, col + 1, "Account", None)?;
+    sheet.write_string(0, col + 2, "Balance", None)?;
+
+    for (row, b) in (1..).zip(results.balances.iter()) {
+        sheet.write_datetime(row, col, &b.date.into(), None)?;
+        sheet.write_string(row, col + 1, &b.account.name, None)?;
+        sheet.write_number(row, col + 2, b.balance.to_f64(), None)?;
+    }
+    Ok(())
+}
+
+fn write_cash_flow_columns(
+    sheet: &mut Worksheet,
+    col: u16,
+    results: &super::SimulationResult,
+) -> Result<()> {
+    sheet.write_string(0, col, "Date", None)?;
+    sheet.write_string(0, col + 1, "Cash Flow", None)?;
+    sheet.write_string(0, col + 2, "Amount", None)?;
+    sheet.write_string(0, col + 3, "Kind", None)?;
+
+    for (row, f) in (1..).zip(results.payments.iter()) {
+        let name = &f.cash_flow().name;
+        sheet.write_datetime(row, col, &f.date.into(), None)?;
+        sheet.write_string(row, col + 1, name.as_deref().unwrap_or_default(), None)?;
+        sheet.write_number(row, col + 2, f.amount.to_f64(), None)?;
+        sheet.write_string(row, col + 3, &f.kind.to_string(), None)?;
+    }
+    Ok(())
+}
+
+/// Writes one sheet per compared scenario, holding its balances and cash flows, followed by a
+/// "Comparison" sheet with balance differences, cash flow totals, crossover dates and the
+/// inputs that differ from the baseline.
+pub fn write_comparison(comparison: &Comparison, file: &str) -> Result<()> {
+    let workbook = Workbook::new(file)?;
+    let mut used: Vec<String> = vec!["Comparison".to_string()];
+
+    for (name, results) in comparison.scenarios.iter().zip(comparison.results.iter()) {
+        let sheet_name = sheet_name(name, &used);
+        let mut sheet = workbook.add_worksheet(Some(&sheet_name))?;
+        write_balance_columns(&mut sheet, 0, results)?;
+        write_cash_flow_columns(&mut sheet, 4, results)?;
+        used.push(sheet_name);
+    }
+
+    let mut sheet = workbook.add_worksheet(Some("Comparison"))?;
+    let n = comparison.scenarios.len() as u16;
+
+    // balances and differences from the baseline
+    sheet.write_string(0, 0, "Date", None)?;
+    for (i, name) in (0..).zip(comparison.scenarios.iter()) {
+        sheet.write_string(0, 1 + i, name, None)?;
+        if i > 0 {
+            let header = format!("{} - {}", name, comparison.scenarios[0]);
+            sheet.write_string(0, n + i, &header, None)?;
+        }
+    }
+    for (row, b) in (1..).zip(comparison.balances.iter()) {
+        sheet.write_datetime(row, 0, &b.date.into(), None)?;
+        for (i, v) in (0..).zip(b.balances.iter()) {
+            sheet.write_number(row, 1 + i, *v, None)?;
+        }
+        for (i, v) in (0..).zip(b.differences.iter()).skip(1) {
+            sheet.write_number(row, n + i, *v, None)?;
+        }
+    }
+
+    // cash flow totals
+    let col = 2 * n + 1;
+    sheet.write_string(0, col, "Cash Flow", None)?;
+    sheet.write_string(0, col + 1, "Kind", None)?;
+    for (i, name) in (0..).zip(comparison.scenarios.iter()) {
+        sheet.write_string(0, col + 2 + i, name, None)?;
+        if i > 0 {
+            let header = format!("{} - {}", name, comparison.scenarios[0]);
+            sheet.write_string(0, col + 1 + n + i, &header, None)?;
+        }
+    }
+    for (row, t) in (1..).zip(comparison.cash_flows.iter()) {
+        sheet.write_string(row, col, &t.cash_flow, None)?;
+        sheet.write_string(row, col + 1, &t.kind.to_string(), None)?;
+        for (i, v) in (0..).zip(t.totals.iter()) {
+            sheet.write_number(row, col + 2 + i, *v, None)?;
+        }
+        for (i, v) in (0..).zip(t.differences.iter()).skip(1) {
+            sheet.write_number(row, col + 1 + n + i, *v, None)?;
+        }
+    }
+
+    // crossover dates
+    let col = col + 2 * n + 2;
+    sheet.write_string(0, col, "Crossover Date", None)?;
+    sheet.write_string(0, col + 1, "Scenario", None)?;
+    sheet.write_string(0, col + 2, "Direction", None)?;
+    for (row, c) in (1..).zip(comparison.crossovers.iter()) {
+        let direction = format!("{:?}", c.direction);
+        sheet.write_datetime(row, col, &c.date.into(), None)?;
+        sheet.write_string(row, col + 1, &c.scenario, None)?;
+        sheet.write_string(row, col + 2, &direction, None)?;
+    }
+
+    // differing inputs
+    let col = col + 4;
+    sheet.write_string(0, col, "Scenario", None)?;
+    sheet.write_string(0, col + 1, "Input", None)?;
+    sheet.write_string(0, col + 2, "Baseline", None)?;
+    sheet.write_string(0, col + 3, "Value", None)?;
+    for (row, d) in (1..).zip(comparison.inputs.iter()) {
+        let show = |v: &Option<serde_json::Value>| match v {
+            Some(serde_json::Value::String(s)) => s.clone(),
+            Some(v) => v.to_string(),
+            None => String::new(),
+        };
+        sheet.write_string(row, col, &d.scenario, None)?;
+        sheet.write_string(row, col + 1, &d.path, None)?;
+        sheet.write_string(row, col + 2, &show(&d.baseline), None)?;
+        sheet.write_string(row, col + 3, &show(&d.value), None)?;
+    }
+
+    workbook.close()?;
+    Ok(())
+}
+
+/// Writes a "Budget vs Actual" sheet with the report's rows and an "Unmatched" sheet with
+/// the transactions outside every planned category.
+pub fn write_variance(report: &VarianceReport, file: &str) -> Result<()> {
+    let workbook = Workbook::new(file)?;
+    let mut sheet = workbook.add_worksheet(Some("Budget vs Actual"))?;
+    sheet.write_string(0, 0, "Category", None)?;
+    sheet.write_string(0, 1, "Period", None)?;
+    sheet.write_string(0, 2, "Start Date", None)?;
+    sheet.write_string(0, 3, "Planned", None)?;
+    sheet.write_string(0, 4, "Actual", None)?;
+    sheet.write_string(0, 5, "Variance", None)?;
+    sheet.write_string(0, 6, "Cumulative Variance", None)?;
+    for (row, r) in (1..).zip(report.rows.iter()) {
+        sheet.write_string(row, 0, &r.category, None)?;
+        sheet.write_string(row, 1, &r.period, None)?;
+        sheet.write_datetime(row, 2, &r.start_date.into(), None)?;
+        sheet.write_number(row, 3, r.planned.to_f64(), None)?;
+        sheet.write_number(row, 4, r.actual.to_f64(), None)?;
+        sheet.write_number(row, 5, r.variance.to_f64(), None)?;
+        sheet.write_number(row, 6, r.cumulative_variance.to_f64(), None)?;
+    }
+
+    let mut sheet = workbook.add_worksheet(Some("Unmatched"))?;
+    sheet.write_string(0, 0, "Date", None)?;
+    sheet.write_string(0, 1, "Payee", None)?;
+    sheet.write_string(0, 2, "Category", None)?;
+    sheet.write_string(0, 3, "Amount", None)?;
+    for (row, t) in (1..).zip(report.unmatched.iter()) {
+        sheet.write_datetime(row, 0, &t.date.into(), None)?;
+        sheet.write_string(row, 1, &t.payee, None)?;
+        sheet.write_string(row, 2, t.category.as_deref().unwrap_or_default(), None)?;
+        sheet.write_number(row, 3, t.amount.to_f64(), None)?;
+    }
+
+    workbook.close()?;
+    Ok(())
+}
+
+// Excel sheet names are limited to 31 characters and may not contain []:*?/\
+fn sheet_name(name: &str, used: &[String]) -> String {
+    let clean: String = name
+        .chars()
+        .map(|c| if "[]:*?/\\".contains(c) { '_' } else { c })
+        .take(31)
+        .collect();
+    let mut candidate = clean.clone();
+    let mut n = 2;
+    while candidate.is_empty() || used.iter().any(|u| u.eq_ignore_ascii_case(&candidate)) {
+        let suffix = format!(" ({})", n);
+        let keep = 31 - suffix.len();
+        candidate = format!("{}{}", clean.chars().take(keep).collect::<String>(), suffix);
+        n += 1;
+    }
+    candidate
+}
+
+#[test]
+fn test_sheet_name() {
+    let used = vec!["Comparison".to_string()];
+    assert_eq!(sheet_name("Buy: 20% down", &used), "Buy_ 20% down");
+    assert_eq!(sheet_name("comparison", &used), "comparison (2)");
+    assert_eq!(sheet_name(&"x".repeat(40), &used).len(), 31);
+}
+
+#[test]
+fn test_balance_pivot() {
+    let path = std::path::Path::new("./scenarios/examples/default_scenario.yaml");
+    let scenario = super::scenario::Scenario::from_file(path).unwrap();
+    let results = scenario.run(super::terminal::Verbosity::Quiet).unwrap();
+    let (dates, accounts, balances) = balance_pivot(&results);
+    assert!(dates.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(accounts.len(), 2);
+    assert_eq!(balances.len(), results.balances.len());
+    let first = results.balances.get(0);
+    let col = 1 + accounts.iter().position(|a| *a == first.account.name).unwrap() as u16;
+    assert_eq!(balances[&(1, col)], first.balance.to_f64());
+
+    let mut settings = scenario.monte_carlo.clone().unwrap();
+    settings.samples = 10;
+    let mc = super::monte_carlo::run_monte_carlo(&scenario, &settings).unwrap();
+    let file = std::env::temp_dir().join(format!("budget-charts-{}.xlsx", std::process::id()));
+    write_sim(results, Some(&mc), file.to_str().unwrap()).unwrap();
+    assert!(file.metadata().unwrap().len() > 0);
+    std::fs::remove_file(file).unwrap();
+}
+
+#[test]
+fn test_defined_names() {
+    assert_eq!(defined_name("joint-checking", "balance"), "joint_checking_balance");
+    assert_eq!(defined_name("checking.rent", "amount"), "checking.rent_amount");
+    assert_eq!(defined_name("401k", "return"), "_401k_return");
+    assert_eq!(column_name(0), "A");
+    assert_eq!(column_name(25), "Z");
+    assert_eq!(column_name(27), "AB");
+}
+
+#[test]
+fn test_write_formulas() {
+    let formula = occurrences("checking.rent", 2);
+    let mut depth = 0;
+    for c in formula.chars() {
+        depth += match c {
+            '(' => 1,
+            ')' => -1,
+            _ => 0,
+        };
+        assert!(depth >= 0);
+    }
+    assert_eq!(depth, 0);
+    assert!(formula.contains("checking.rent_frequency=\"SemiMonthly\""));
+    assert!(formula.contains("checking.rent_frequency=\"BiWeekly\""));
+
+    let path = std::path::Path::new("./scenarios/examples/default_scenario.yaml");
+    let scenario = super::scenario::Scenario::from_file(path).unwrap();
+    let file = std::env::temp_dir().join(format!("budget-formulas-{}.xlsx", std::process::id()));
+    write_formulas(&scenario, file.to_str().unwrap()).unwrap();
+    assert!(file.metadata().unwrap().len() > 0);
+    std::fs::remove_file(file).unwrap();
 }
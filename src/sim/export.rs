@@ -0,0 +1,294 @@
+use polars::prelude::*;
+use rust_decimal::prelude::ToPrimitive;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::cash::PaymentKind;
+use super::money::Money;
+use super::SimulationResult;
+use crate::{Error, Result};
+
+/// File formats that result frames are written in by [`export`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Parquet,
+    /// Arrow IPC, also known as Feather.
+    Arrow,
+}
+
+impl Format {
+    /// The format implied by a file's extension.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Parquet => "parquet",
+            Format::Arrow => "arrow",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv",
+            Format::Parquet => "application/vnd.apache.parquet",
+            Format::Arrow => "application/vnd.apache.arrow.file",
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Format> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "parquet" => Ok(Format::Parquet),
+            "arrow" | "ipc" | "feather" => Ok(Format::Arrow),
+            _ => Err(Error::Invalid(format!(
+                "`{}` is not an export format, expected csv, parquet or arrow",
+                s
+            ))),
+        }
+    }
+}
+
+/// The frames a result is exported as, by name.
+pub const TABLES: [&str; 4] = ["balances", "payments", "consolidated", "summary"];
+
+impl SimulationResult {
+    /// One row per recorded balance, see [`super::columns::BalanceTable::data_frame`].
+    pub fn balances_frame(&self) -> Result<DataFrame> {
+        self.balances.data_frame()
+    }
+
+    /// One row per payment, see [`super::columns::PaymentTable::data_frame`].
+    pub fn payments_frame(&self) -> Result<DataFrame> {
+        self.payments.data_frame()
+    }
+
+    /// The combined balance of all accounts in the reporting currency on each date, with
+    /// `date`, `balance`, `fx_gain_loss` and `currency` columns.
+    pub fn consolidated_frame(&self) -> Result<DataFrame> {
+        let c = &self.consolidated;
+        Ok(DataFrame::new(vec![
+            Series::new("date", c.iter().map(|c| c.date).collect::<Vec<_>>()),
+            Series::new(
+                "balance",
+                c.iter().map(|c| c.balance.to_f64()).collect::<Vec<_>>(),
+            ),
+            Series::new(
+                "fx_gain_loss",
+                c.iter()
+                    .map(|c| c.fx_gain_loss.to_f64())
+                    .collect::<Vec<_>>(),
+            ),
+            Series::new(
+                "currency",
+                c.iter()
+                    .map(|c| c.balance.currency().code().to_string())
+                    .collect::<Vec<_>>(),
+            ),
+        ])?)
+    }
+
+    /// One row per account with its `first_date`, `last_date`, `opening_balance`,
+    /// `ending_balance` and `minimum_balance` as recorded, and the totals of its `inflows`,
+    /// `outflows`, `taxes` and `interest` over the whole simulation.
+    pub fn summary_frame(&self) -> Result<DataFrame> {
+        #[derive(Default)]
+        struct Summary {
+            name: String,
+            currency: String,
+            first: Option<chrono::NaiveDate>,
+            last: Option<chrono::NaiveDate>,
+            opening: Option<Money>,
+            ending: Option<Money>,
+            minimum: Option<Money>,
+            totals: [rust_decimal::Decimal; 4],
+            payments: u32,
+        }
+        let mut accounts: BTreeMap<&str, Summary> = BTreeMap::new();
+        for b in self.balances.iter() {
+            let s = accounts.entry(&b.account.id).or_default();
+            s.name = b.account.name.clone();
+            s.currency = b.balance.currency().code().to_string();
+            s.first = s.first.or(Some(b.date));
+            s.last = Some(b.date);
+            s.opening = s.opening.or(Some(b.balance));
+            s.ending = Some(b.balance);
+            s.minimum = Some(match s.minimum {
+                Some(m) if m.amount() <= b.balance.amount() => m,
+                _ => b.balance,
+            });
+        }
+        for p in self.payments.iter() {
+            let Some(account) = p.account else { continue };
+            let s = accounts.entry(&account.id).or_default();
+            s.name = account.name.clone();
+            s.currency = p.amount.currency().code().to_string();
+            let column = match p.kind {
+                PaymentKind::Tax => 2,
+                PaymentKind::Interest => 3,
+                _ if p.amount.is_negative() => 1,
+                _ => 0,
+            };
+            s.totals[column] += p.amount.amount();
+            s.payments += 1;
+        }
+
+        let money = |f: &dyn Fn(&Summary) -> Option<Money>| -> Vec<Option<f64>> {
+            accounts
+                .values()
+                .map(|s| f(s).map(|m| m.to_f64()))
+                .collect()
+        };
+        let total = |i: usize| -> Vec<f64> {
+            accounts
+                .values()
+                .map(|s| s.totals[i].to_f64().unwrap_or_default())
+                .collect()
+        };
+        Ok(DataFrame::new(vec![
+            Series::new("account_id", accounts.keys().copied().collect::<Vec<_>>()),
+            Series::new(
+                "account",
+                accounts
+                    .values()
+                    .map(|s| s.name.as_str())
+                    .collect::<Vec<_>>(),
+            ),
+            Series::new(
+                "currency",
+                accounts
+                    .values()
+                    .map(|s| s.currency.as_str())
+                    .collect::<Vec<_>>(),
+            ),
+            Series::new(
+                "first_date",
+                accounts.values().map(|s| s.first).collect::<Vec<_>>(),
+            ),
+            Series::new(
+                "last_date",
+                accounts.values().map(|s| s.last).collect::<Vec<_>>(),
+            ),
+            Series::new("opening_balance", money(&|s| s.opening)),
+            Series::new("ending_balance", money(&|s| s.ending)),
+            Series::new("minimum_balance", money(&|s| s.minimum)),
+            Series::new("inflows", total(0)),
+            Series::new("outflows", total(1)),
+            Series::new("taxes", total(2)),
+            Series::new("interest", total(3)),
+            Series::new(
+                "payments",
+                accounts.values().map(|s| s.payments).collect::<Vec<_>>(),
+            ),
+        ])?)
+    }
+
+    /// The frame named in [`TABLES`].
+    pub fn frame(&self, table: &str) -> Result<DataFrame> {
+        match table {
+            "balances" => self.balances_frame(),
+            "payments" => self.payments_frame(),
+            "consolidated" => self.consolidated_frame(),
+            "summary" => self.summary_frame(),
+            _ => Err(Error::Invalid(format!(
+                "`{}` is not a result table, expected one of {}",
+                table,
+                TABLES.join(", ")
+            ))),
+        }
+    }
+}
+
+/// Writes a frame in the given format.
+pub fn write_frame<W: std::io::Write>(df: &mut DataFrame, writer: W, format: Format) -> Result<()> {
+    match format {
+        Format::Csv => CsvWriter::new(writer).include_header(true).finish(df)?,
+        Format::Parquet => {
+            ParquetWriter::new(writer).finish(df)?;
+        }
+        Format::Arrow => IpcWriter::new(writer).finish(df)?,
+    }
+    Ok(())
+}
+
+/// Writes every frame of a result into `dir`, as `balances.parquet`, `payments.parquet` and
+/// so on, creating the directory if needed. Returns the files written.
+pub fn export(results: &SimulationResult, dir: &Path, format: Format) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    let mut files = vec![];
+    for table in TABLES {
+        let path = dir.join(format!("{}.{}", table, format.extension()));
+        let mut df = results.frame(table)?;
+        write_frame(&mut df, std::fs::File::create(&path)?, format)?;
+        files.push(path);
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod export_tests {
+    use super::*;
+    use crate::sim::scenario::Scenario;
+    use crate::sim::terminal::Verbosity;
+
+    fn results() -> SimulationResult {
+        let path = Path::new("./scenarios/examples/default_scenario.yaml");
+        Scenario::from_file(path).unwrap().run(Verbosity::Quiet).unwrap()
+    }
+
+    #[test]
+    fn test_summary_frame() {
+        let results = results();
+        let df = results.summary_frame().unwrap();
+        assert_eq!(df.height(), 2);
+        let ids: Vec<Option<&str>> = df
+            .column("account_id")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(ids, vec![Some("brokerage"), Some("checking")]);
+        // 24 months of rent out of checking
+        let outflows = df.column("outflows").unwrap().f64().unwrap().get(1);
+        assert_eq!(outflows, Some(-43200.0));
+        let interest = df
+            .column("interest")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert!(interest > 0.0);
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let results = results();
+        let dir = std::env::temp_dir().join(format!("budget-export-{}", std::process::id()));
+        for format in [Format::Csv, Format::Parquet, Format::Arrow] {
+            let files = export(&results, &dir, format).unwrap();
+            assert_eq!(files.len(), TABLES.len());
+            let path = dir.join(format!("payments.{}", format.extension()));
+            let df = match format {
+                Format::Csv => CsvReader::from_path(&path).unwrap().finish().unwrap(),
+                Format::Parquet => ParquetReader::new(std::fs::File::open(&path).unwrap())
+                    .finish()
+                    .unwrap(),
+                Format::Arrow => IpcReader::new(std::fs::File::open(&path).unwrap())
+                    .finish()
+                    .unwrap(),
+            };
+            assert_eq!(df.height(), results.payments.len());
+            assert_eq!(Format::from_path(&path), Some(format));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
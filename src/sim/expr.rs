@@ -0,0 +1,581 @@
+use chrono::{Days, Months, NaiveDate};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use super::params::ParameterPath;
+
+/// Key of the scenario's variable definitions.
+pub const VARIABLES: &str = "variables";
+
+/// The value of an expression. Dates and durations only combine in the ways that make sense
+/// for calendars: a date plus or minus a duration is a date, and the difference of two dates
+/// is a number of days.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Term {
+    Number(f64),
+    Date(NaiveDate),
+    Duration { months: i64, days: i64 },
+}
+
+impl Term {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Term::Number(_) => "number",
+            Term::Date(_) => "date",
+            Term::Duration { .. } => "duration",
+        }
+    }
+}
+
+/// An expression that could not be evaluated. `path` names the field (or `variables.<name>`)
+/// holding the expression and `column` is the 1-based character where the problem starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprError {
+    pub path: String,
+    pub expression: String,
+    pub column: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ExprError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} at column {} of `{}`",
+            self.path, self.message, self.column, self.expression
+        )
+    }
+}
+
+/// Parses a variable value given as text, e.g. on the command line: numbers become numbers
+/// and anything else (a date or an expression) is kept as a string.
+pub fn parse_override(text: &str) -> Value {
+    match text.parse::<f64>() {
+        Ok(n) => serde_json::json!(n),
+        Err(_) => Value::String(text.to_string()),
+    }
+}
+
+/// True if a string value is an expression rather than a literal.
+pub fn is_expression(s: &str) -> bool {
+    s.contains("${")
+}
+
+/// Replaces every expression string in a scenario document with its value.
+///
+/// Expressions may reference any entry of the document's `variables`, which may themselves
+/// be numbers, dates or expressions over other variables. The `variables` section is left
+/// untouched so it can still be changed and the document evaluated again. Returns the
+/// location and source of each expression that was replaced, or every error found.
+pub fn evaluate_document(doc: &mut Value) -> Result<Vec<(ParameterPath, String)>, Vec<ExprError>> {
+    let variables = doc
+        .get(VARIABLES)
+        .and_then(|v| v.as_object())
+        .cloned()
+        .unwrap_or_default();
+    let mut evaluator = Evaluator::new(&variables);
+    let mut expressions = vec![];
+    let mut errors = vec![];
+
+    // every variable is checked, even the unused ones
+    for name in variables.keys() {
+        if let Err(e) = evaluator.variable(name, 0, "") {
+            if !errors.contains(&e) {
+                errors.push(e);
+            }
+        }
+    }
+
+    if let Value::Object(map) = doc {
+        for (k, v) in map.iter_mut() {
+            if k != VARIABLES {
+                walk(v, k, k, &mut evaluator, &mut expressions, &mut errors);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(expressions)
+    } else {
+        Err(errors)
+    }
+}
+
+fn walk(
+    v: &mut Value,
+    path: &str,
+    label: &str,
+    evaluator: &mut Evaluator,
+    expressions: &mut Vec<(ParameterPath, String)>,
+    errors: &mut Vec<ExprError>,
+) {
+    match v {
+        Value::Object(map) => {
+            for (k, child) in map.iter_mut() {
+                let p = format!("{}.{}", path, k);
+                let l = format!("{}.{}", label, k);
+                walk(child, &p, &l, evaluator, expressions, errors);
+            }
+        }
+        Value::Array(items) => {
+            for (i, child) in items.iter_mut().enumerate() {
+                let name = child
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .map(|n| n.to_string())
+                    .unwrap_or(i.to_string());
+                let p = format!("{}.{}", path, i);
+                let l = format!("{}.{}", label, name);
+                walk(child, &p, &l, evaluator, expressions, errors);
+            }
+        }
+        Value::String(s) if is_expression(s) => {
+            let source = s.clone();
+            let result = evaluator.evaluate(&source, label).and_then(|t| match t {
+                Term::Number(n) => Ok(serde_json::json!(n)),
+                Term::Date(d) => Ok(Value::String(d.to_string())),
+                Term::Duration { .. } => Err(ExprError {
+                    path: label.to_string(),
+                    expression: source.clone(),
+                    column: 1,
+                    message: "expression is a duration, expected a number or a date".to_string(),
+                }),
+            });
+            match result {
+                Ok(value) => {
+                    *v = value;
+                    expressions.push((ParameterPath::parse(path), source));
+                }
+                Err(e) => {
+                    if !errors.contains(&e) {
+                        errors.push(e)
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+struct Evaluator<'a> {
+    variables: &'a Map<String, Value>,
+    values: HashMap<String, Term>,
+    stack: Vec<String>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(variables: &'a Map<String, Value>) -> Evaluator<'a> {
+        Evaluator {
+            variables,
+            values: HashMap::new(),
+            stack: vec![],
+        }
+    }
+
+    fn evaluate(&mut self, source: &str, path: &str) -> Result<Term, ExprError> {
+        let tokens = tokenize(source).map_err(|(column, message)| ExprError {
+            path: path.to_string(),
+            expression: source.to_string(),
+            column,
+            message,
+        })?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+            source,
+            path,
+            evaluator: self,
+        };
+        let term = parser.expression()?;
+        if let Some(t) = parser.tokens.get(parser.pos) {
+            return Err(parser.error(t.column, "unexpected input".to_string()));
+        }
+        Ok(term)
+    }
+
+    /// Value of a variable. `column` and `source` locate the reference for error messages.
+    fn variable(&mut self, name: &str, column: usize, source: &str) -> Result<Term, ExprError> {
+        if let Some(t) = self.values.get(name) {
+            return Ok(*t);
+        }
+        let path = format!("{}.{}", VARIABLES, name);
+        if self.stack.iter().any(|n| n == name) {
+            let mut chain = self.stack.clone();
+            chain.push(name.to_string());
+            return Err(ExprError {
+                path: String::new(),
+                expression: source.to_string(),
+                column,
+                message: format!("variables reference each other: {}", chain.join(" -> ")),
+            });
+        }
+
+        let term = match self.variables.get(name) {
+            Some(Value::Number(n)) => Term::Number(n.as_f64().unwrap()),
+            Some(Value::String(s)) => {
+                self.stack.push(name.to_string());
+                let t = self.evaluate(s, &path);
+                self.stack.pop();
+                t?
+            }
+            Some(v) => {
+                return Err(ExprError {
+                    path,
+                    expression: v.to_string(),
+                    column: 1,
+                    message: "variables must be numbers, dates or expressions".to_string(),
+                })
+            }
+            None => {
+                return Err(ExprError {
+                    path: String::new(),
+                    expression: source.to_string(),
+                    column,
+                    message: format!("unknown variable `{}`", name),
+                })
+            }
+        };
+        self.values.insert(name.to_string(), term);
+        Ok(term)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Term(Term),
+    Variable(String),
+    Op(char),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: Kind,
+    column: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, (usize, String)> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token {
+                kind: Kind::Op(c),
+                column,
+            });
+            i += 1;
+        } else if c == '$' {
+            if chars.get(i + 1) != Some(&'{') {
+                return Err((column, "expected `{` after `$`".to_string()));
+            }
+            let end = chars[i..]
+                .iter()
+                .position(|c| *c == '}')
+                .ok_or((column, "unterminated variable reference".to_string()))?;
+            let name: String = chars[i + 2..i + end].iter().collect::<String>().trim().to_string();
+            if name.is_empty() {
+                return Err((column, "empty variable reference".to_string()));
+            }
+            tokens.push(Token {
+                kind: Kind::Variable(name),
+                column,
+            });
+            i += end + 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.' || chars[i] == '_')
+            {
+                i += 1;
+            }
+            // dates contain dashes, which would otherwise read as subtraction
+            if i - start == 4 && chars.get(i) == Some(&'-') {
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '-') {
+                    i += 1;
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(Token {
+                kind: Kind::Term(literal(&text).ok_or((column, format!("invalid literal `{}`", text)))?),
+                column,
+            });
+        } else {
+            return Err((column, format!("unexpected character `{}`", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+fn literal(text: &str) -> Option<Term> {
+    if let Ok(d) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Some(Term::Date(d));
+    }
+    let text = text.replace('_', "");
+    if let Ok(n) = text.parse::<f64>() {
+        return Some(Term::Number(n));
+    }
+    let (count, unit) = text.split_at(text.len() - 1);
+    let count: i64 = count.parse().ok()?;
+    match unit {
+        "y" => Some(Term::Duration { months: count.checked_mul(12)?, days: 0 }),
+        "m" => Some(Term::Duration { months: count, days: 0 }),
+        "w" => Some(Term::Duration { months: 0, days: count.checked_mul(7)? }),
+        "d" => Some(Term::Duration { months: 0, days: count }),
+        _ => None,
+    }
+}
+
+struct Parser<'p, 'a> {
+    tokens: &'p [Token],
+    pos: usize,
+    source: &'p str,
+    path: &'p str,
+    evaluator: &'p mut Evaluator<'a>,
+}
+
+impl<'p, 'a> Parser<'p, 'a> {
+    fn error(&self, column: usize, message: String) -> ExprError {
+        ExprError {
+            path: self.path.to_string(),
+            expression: self.source.to_string(),
+            column,
+            message,
+        }
+    }
+
+    fn peek_op(&self, ops: &str) -> Option<(char, usize)> {
+        match self.tokens.get(self.pos) {
+            Some(Token {
+                kind: Kind::Op(c),
+                column,
+            }) if ops.contains(*c) => Some((*c, *column)),
+            _ => None,
+        }
+    }
+
+    fn expression(&mut self) -> Result<Term, ExprError> {
+        let mut lhs = self.product()?;
+        while let Some((op, column)) = self.peek_op("+-") {
+            self.pos += 1;
+            let rhs = self.product()?;
+            lhs = self.apply(op, lhs, rhs, column)?;
+        }
+        Ok(lhs)
+    }
+
+    fn product(&mut self) -> Result<Term, ExprError> {
+        let mut lhs = self.unary()?;
+        while let Some((op, column)) = self.peek_op("*/") {
+            self.pos += 1;
+            let rhs = self.unary()?;
+            lhs = self.apply(op, lhs, rhs, column)?;
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Term, ExprError> {
+        if let Some((_, column)) = self.peek_op("-") {
+            self.pos += 1;
+            return match self.unary()? {
+                Term::Number(n) => Ok(Term::Number(-n)),
+                Term::Duration { months, days } => Ok(Term::Duration {
+                    months: -months,
+                    days: -days,
+                }),
+                t => Err(self.error(column, format!("cannot negate a {}", t.type_name()))),
+            };
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Term, ExprError> {
+        let token = match self.tokens.get(self.pos) {
+            Some(t) => t.clone(),
+            None => {
+                return Err(self.error(
+                    self.source.chars().count() + 1,
+                    "unexpected end of expression".to_string(),
+                ))
+            }
+        };
+        self.pos += 1;
+        match token.kind {
+            Kind::Term(t) => Ok(t),
+            Kind::Variable(name) => self
+                .evaluator
+                .variable(&name, token.column, self.source)
+                .map_err(|mut e| {
+                    if e.path.is_empty() {
+                        e.path = self.path.to_string();
+                    }
+                    e
+                }),
+            Kind::Op('(') => {
+                let t = self.expression()?;
+                match self.peek_op(")") {
+                    Some(_) => {
+                        self.pos += 1;
+                        Ok(t)
+                    }
+                    None => Err(self.error(token.column, "unclosed `(`".to_string())),
+                }
+            }
+            Kind::Op(c) => Err(self.error(token.column, format!("unexpected `{}`", c))),
+        }
+    }
+
+    fn apply(&self, op: char, lhs: Term, rhs: Term, column: usize) -> Result<Term, ExprError> {
+        use Term::*;
+        let result = match (op, lhs, rhs) {
+            ('+', Number(a), Number(b)) => Some(Number(a + b)),
+            ('-', Number(a), Number(b)) => Some(Number(a - b)),
+            ('*', Number(a), Number(b)) => Some(Number(a * b)),
+            ('/', Number(_), Number(0.0)) => {
+                return Err(self.error(column, "division by zero".to_string()))
+            }
+            ('/', Number(a), Number(b)) => Some(Number(a / b)),
+            ('+', Date(d), Duration { months, days }) | ('+', Duration { months, days }, Date(d)) => {
+                shift(d, months, days)
+            }
+            ('-', Date(d), Duration { months, days }) => shift(d, -months, -days),
+            ('-', Date(a), Date(b)) => Some(Number((a - b).num_days() as f64)),
+            ('+', Duration { months: m1, days: d1 }, Duration { months: m2, days: d2 }) => {
+                m1.checked_add(m2)
+                    .zip(d1.checked_add(d2))
+                    .map(|(months, days)| Duration { months, days })
+            }
+            ('-', Duration { months: m1, days: d1 }, Duration { months: m2, days: d2 }) => {
+                m1.checked_sub(m2)
+                    .zip(d1.checked_sub(d2))
+                    .map(|(months, days)| Duration { months, days })
+            }
+            ('*', Duration { months, days }, Number(n)) | ('*', Number(n), Duration { months, days })
+                if n.fract() == 0.0 =>
+            {
+                months
+                    .checked_mul(n as i64)
+                    .zip(days.checked_mul(n as i64))
+                    .map(|(months, days)| Duration { months, days })
+            }
+            _ => {
+                let verb = match op {
+                    '+' => "add",
+                    '-' => "subtract",
+                    '*' => "multiply",
+                    _ => "divide",
+                };
+                return Err(self.error(
+                    column,
+                    format!(
+                        "cannot {} a {} and a {}",
+                        verb,
+                        lhs.type_name(),
+                        rhs.type_name()
+                    ),
+                ));
+            }
+        };
+        result.ok_or_else(|| self.error(column, "date out of range".to_string()))
+    }
+}
+
+// None when the date, or the duration itself, is out of range
+fn shift(d: NaiveDate, months: i64, days: i64) -> Option<Term> {
+    let whole_months = Months::new(u32::try_from(months.unsigned_abs()).ok()?);
+    let d = if months >= 0 {
+        d.checked_add_months(whole_months)?
+    } else {
+        d.checked_sub_months(whole_months)?
+    };
+    let d = if days >= 0 {
+        d.checked_add_days(Days::new(days.unsigned_abs()))?
+    } else {
+        d.checked_sub_days(Days::new(days.unsigned_abs()))?
+    };
+    Some(Term::Date(d))
+}
+
+#[cfg(test)]
+mod expr_tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_evaluate_document() {
+        let mut doc = json!({
+            "variables": {
+                "salary": 120000,
+                "retirement_date": "2048-06-01",
+                "pension_start": "${retirement_date} + 1y",
+            },
+            "accounts": [{
+                "name": "Checking",
+                "cash_flows": [
+                    {"name": "Income", "amount": "${salary} / 24", "end_date": "${retirement_date}"},
+                    {"name": "Pension", "amount": "-(${salary} * 0.4 - 12) / 12 * -1", "start_date": "${pension_start} + 2w"},
+                ]
+            }]
+        });
+        let expressions = evaluate_document(&mut doc).unwrap();
+        let flows = &doc["accounts"][0]["cash_flows"];
+        assert_eq!(flows[0]["amount"], json!(5000.0));
+        assert_eq!(flows[0]["end_date"], json!("2048-06-01"));
+        assert_eq!(flows[1]["amount"], json!(3999.0));
+        assert_eq!(flows[1]["start_date"], json!("2049-06-15"));
+        assert_eq!(doc["variables"]["salary"], json!(120000));
+        assert_eq!(expressions.len(), 4);
+        assert_eq!(expressions[0].0.to_string(), "accounts.0.cash_flows.0.amount");
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut doc = json!({
+            "variables": {"start": "2020-01-01", "loop": "${loop} + 1"},
+            "accounts": [{
+                "name": "Checking",
+                "cash_flows": [
+                    {"name": "Income", "amount": "${salry} / 24"},
+                    {"name": "Rent", "amount": "${start} * 2"},
+                ]
+            }]
+        });
+        let errors = evaluate_document(&mut doc).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "variables.loop: variables reference each other: loop -> loop at column 1 of `${loop} + 1`",
+                "accounts.Checking.cash_flows.Income.amount: unknown variable `salry` at column 1 of `${salry} / 24`",
+                "accounts.Checking.cash_flows.Rent.amount: cannot multiply a date and a number at column 10 of `${start} * 2`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_date_math() {
+        let mut doc = json!({
+            "variables": {"a": "2020-01-31"},
+            "x": "${a} + 1m",
+            "y": "${a} - 2020-01-01",
+            "z": "${a} + 3 * 1w - 1d",
+        });
+        evaluate_document(&mut doc).unwrap();
+        assert_eq!(doc["x"], json!("2020-02-29"));
+        assert_eq!(doc["y"], json!(30.0));
+        assert_eq!(doc["z"], json!("2020-02-20"));
+
+        for (expression, error) in [
+            ("${a} + 999999999d", "date out of range"),
+            ("${a} - 9999999999m", "date out of range"),
+            ("${a} + 1000000000000000000y", "invalid literal"),
+            ("${a} + 1w * 9000000000000000000", "date out of range"),
+        ] {
+            let mut doc = json!({"variables": {"a": "2020-01-31"}, "x": expression});
+            let errors = evaluate_document(&mut doc).unwrap_err();
+            assert!(errors[0].to_string().contains(error), "{}", errors[0]);
+        }
+    }
+}
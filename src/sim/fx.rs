@@ -0,0 +1,302 @@
+use chrono::NaiveDate;
+use rand::RngCore;
+use rand_distr::{Distribution, StandardNormal};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use super::money::{Currency, Money};
+use super::scenario::Scenario;
+use super::{ConsolidatedBalance, SimulationResult};
+use crate::{Error, Result};
+
+/// Key of the scenario's FX rates, see [`FxRate`].
+pub const FX: &str = "fx";
+
+/// `FxRate` converts one currency into the scenario's reporting currency.
+///
+/// A rate is the number of units of the reporting currency one unit of this currency buys,
+/// e.g. `1.1` for EUR in a USD scenario. Exactly one source is given:
+///
+/// - `rate`, a constant, optionally moved by a geometric Brownian motion with an annual
+///   `drift` and `volatility` that is sampled again for every simulation path,
+/// - `series`, rates by date, each holding until the next one,
+/// - `file`, the same series as `date,rate` CSV rows, relative to the scenario file.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+pub struct FxRate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate: Option<f64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub series: BTreeMap<NaiveDate, f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(default)]
+    pub drift: f64,
+    #[serde(default)]
+    pub volatility: f64,
+    /// The series read from `file`.
+    #[serde(skip)]
+    loaded: BTreeMap<NaiveDate, f64>,
+}
+
+impl FxRate {
+    pub fn constant(rate: f64) -> FxRate {
+        FxRate {
+            rate: Some(rate),
+            ..Default::default()
+        }
+    }
+
+    /// Reads `file`, if set, so that simulations don't have to.
+    pub fn load(&mut self) -> Result<()> {
+        if let Some(file) = &self.file {
+            self.loaded = read_series(Path::new(file))?;
+        }
+        Ok(())
+    }
+
+    /// Rates on each of `dates`, which must be sorted. A stochastic rate starts at `rate` on
+    /// `start_date` and draws its path from `rng`.
+    pub fn rates(
+        &self,
+        dates: &[NaiveDate],
+        start_date: NaiveDate,
+        rng: &mut dyn RngCore,
+    ) -> Result<Vec<f64>> {
+        let series = if self.file.is_some() {
+            &self.loaded
+        } else {
+            &self.series
+        };
+        if let Some(rate) = self.rate {
+            if self.volatility == 0.0 && self.drift == 0.0 {
+                return Ok(vec![rate; dates.len()]);
+            }
+            let mut rates = Vec::with_capacity(dates.len());
+            let (mut date, mut current) = (start_date, rate);
+            for d in dates {
+                let years = (*d - date).num_days().max(0) as f64 / 365.25;
+                let z: f64 = StandardNormal.sample(rng);
+                current *= ((self.drift - self.volatility.powi(2) / 2.0) * years
+                    + self.volatility * years.sqrt() * z)
+                    .exp();
+                rates.push(current);
+                date = *d;
+            }
+            return Ok(rates);
+        }
+        let first = series
+            .values()
+            .next()
+            .ok_or_else(|| Error::Invalid("FX rate has no rate, series or file".to_string()))?;
+        Ok(dates
+            .iter()
+            .map(|d| series.range(..=d).next_back().map(|r| r.1).unwrap_or(first))
+            .copied()
+            .collect())
+    }
+}
+
+/// Reads `date,rate` rows. A header row and blank lines are skipped.
+pub fn read_series(path: &Path) -> Result<BTreeMap<NaiveDate, f64>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| Error::Invalid(format!("{}: {}", path.display(), e)))?;
+    let mut series = BTreeMap::new();
+    for (i, line) in content.lines().enumerate() {
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+        if fields.iter().all(|f| f.is_empty()) {
+            continue;
+        }
+        let date = match NaiveDate::parse_from_str(fields[0], "%Y-%m-%d") {
+            Ok(date) => date,
+            Err(_) if i == 0 => continue,
+            Err(e) => {
+                return Err(Error::Invalid(format!(
+                    "{}:{}: `{}` is not a date: {}",
+                    path.display(),
+                    i + 1,
+                    fields[0],
+                    e
+                )))
+            }
+        };
+        let rate = fields.get(1).and_then(|r| r.parse::<f64>().ok()).ok_or_else(|| {
+            Error::Invalid(format!("{}:{}: expected `date,rate`", path.display(), i + 1))
+        })?;
+        series.insert(date, rate);
+    }
+    Ok(series)
+}
+
+/// Converts every balance into the scenario's reporting currency and adds the consolidated
+/// balance and cumulative FX gain or loss on each recorded date.
+///
+/// The gain or loss on a date is what the balances held in other currencies on the previous
+/// recorded date gained or lost through the change in rates since then.
+pub fn consolidate(
+    results: &mut SimulationResult,
+    scenario: &Scenario,
+    rng: &mut dyn RngCore,
+) -> Result<()> {
+    let reporting = scenario.reporting_currency();
+    let mut dates: Vec<NaiveDate> = results.balances.iter().map(|b| b.date).collect();
+    dates.sort();
+    dates.dedup();
+
+    // sampled in currency order so seeded paths are reproducible
+    let mut rates: BTreeMap<Currency, BTreeMap<NaiveDate, f64>> = BTreeMap::new();
+    for (currency, fx) in &scenario.fx {
+        let path = fx.rates(&dates, scenario.start_date, rng)?;
+        rates.insert(*currency, dates.iter().copied().zip(path).collect());
+    }
+    let rate_on = |currency: Currency, date: NaiveDate| -> Result<f64> {
+        if currency == reporting {
+            return Ok(1.0);
+        }
+        rates
+            .get(&currency)
+            .and_then(|r| r.get(&date))
+            .copied()
+            .ok_or_else(|| {
+                Error::Invalid(format!("No FX rate from {} to {}", currency, reporting))
+            })
+    };
+
+    let mut totals: BTreeMap<NaiveDate, ConsolidatedBalance> = BTreeMap::new();
+    let mut previous: BTreeMap<String, (Money, f64)> = BTreeMap::new();
+    let balances = &mut results.balances;
+    for i in 0..balances.len() {
+        let b = balances.get(i);
+        let rounding = scenario
+            .accounts
+            .iter()
+            .find(|a| a.name == b.account.name)
+            .map(|a| a.rounding)
+            .unwrap_or_default();
+        let rate = rate_on(b.balance.currency(), b.date)?;
+        let converted = b.balance.convert(reporting, rate, rounding)?;
+        let (date, balance, account) = (b.date, b.balance, b.account.id.clone());
+        balances.converted[i] = converted;
+
+        let total = totals.entry(date).or_insert_with(|| ConsolidatedBalance {
+            date,
+            balance: Money::zero(reporting),
+            fx_gain_loss: Money::zero(reporting),
+        });
+        total.balance = total.balance.checked_add(converted)?;
+        if let Some((held, previous_rate)) = previous.get(&account) {
+            let change = held.convert(reporting, rate - previous_rate, rounding)?;
+            total.fx_gain_loss = total.fx_gain_loss.checked_add(change)?;
+        }
+        previous.insert(account, (balance, rate));
+    }
+
+    let mut cumulative = Money::zero(reporting);
+    results.consolidated = totals
+        .into_values()
+        .map(|mut c| {
+            cumulative = cumulative.checked_add(c.fx_gain_loss)?;
+            c.fx_gain_loss = cumulative;
+            Ok(c)
+        })
+        .collect::<Result<_>>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod fx_tests {
+    use super::*;
+    use crate::sim::terminal::Verbosity;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const SCENARIO: &str = "
+version: 1
+name: Abroad
+start_date: 2020-01-01
+end_date: 2020-04-01
+step: Monthly
+reporting_currency: USD
+fx:
+  EUR:
+    series:
+      2020-01-01: 1.1
+      2020-03-01: 1.2
+accounts:
+  - name: Checking
+    balance: 100.0
+    start_date: 2020-01-01
+    end_date: 2020-04-01
+    cash_flows: []
+  - name: Girokonto
+    balance: 1000 EUR
+    start_date: 2020-01-01
+    end_date: 2020-04-01
+    cash_flows: []
+";
+
+    #[test]
+    fn test_consolidated_balances() {
+        let scenario = Scenario::from_yaml(SCENARIO).unwrap();
+        let results = scenario.run(Verbosity::Quiet).unwrap();
+        let balances: Vec<(f64, f64)> = results
+            .consolidated
+            .iter()
+            .map(|c| (c.balance.to_f64(), c.fx_gain_loss.to_f64()))
+            .collect();
+        assert_eq!(balances, vec![(1200.0, 0.0), (1200.0, 0.0), (1300.0, 100.0)]);
+        assert_eq!(results.ending_balance(), 1300.0);
+
+        let girokonto = results
+            .balances
+            .iter()
+            .find(|b| b.account.name == "Girokonto")
+            .unwrap();
+        assert_eq!(girokonto.balance.currency().code(), "EUR");
+        assert_eq!(girokonto.converted, Money::try_from(1100.0).unwrap());
+    }
+
+    #[test]
+    fn test_stochastic_rates() {
+        let fx = FxRate {
+            volatility: 0.2,
+            ..FxRate::constant(1.1)
+        };
+        let start = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+        let dates: Vec<NaiveDate> = (1..=12)
+            .map(|m| NaiveDate::from_ymd_opt(2020, m, 1).unwrap())
+            .collect();
+        let a = fx.rates(&dates, start, &mut StdRng::seed_from_u64(1)).unwrap();
+        let b = fx.rates(&dates, start, &mut StdRng::seed_from_u64(1)).unwrap();
+        let c = fx.rates(&dates, start, &mut StdRng::seed_from_u64(2)).unwrap();
+        assert_eq!(a[0], 1.1);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert!(a.iter().all(|r| *r > 0.0));
+    }
+
+    #[test]
+    fn test_missing_rate() {
+        let yaml = SCENARIO.replace("  EUR:", "  GBP:");
+        let scenario = Scenario::from_yaml(&yaml).unwrap();
+        assert!(scenario.run(Verbosity::Quiet).is_err());
+    }
+
+    #[test]
+    fn test_example() {
+        let path = Path::new("./scenarios/examples/multi_currency.yaml");
+        let scenario = crate::sim::validate::validate_file(path).ok().unwrap();
+        let results = scenario.run(Verbosity::Quiet).unwrap();
+        let last = results.consolidated.last().unwrap();
+        assert_eq!(last.balance.currency(), Currency::USD);
+        assert_ne!(last.fx_gain_loss, Money::zero(Currency::USD));
+    }
+
+    #[test]
+    fn test_read_series() {
+        let series = read_series(Path::new("./scenarios/examples/gbp_usd.csv")).unwrap();
+        let date = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+        assert_eq!(series.get(&date), Some(&1.31));
+    }
+}
@@ -0,0 +1,500 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::ledger::{transaction_ids, Transaction};
+use super::money::{Currency, Money};
+use crate::{Error, Result};
+
+/// Bank and credit card export formats understood by [`import_file`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+pub enum Format {
+    Csv,
+    /// OFX 1.x (SGML) and 2.x (XML), including Quicken's QFX.
+    Ofx,
+    Qif,
+}
+
+impl Format {
+    /// The format implied by a file's extension.
+    pub fn from_path(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "csv" => Some(Format::Csv),
+            "ofx" | "qfx" => Some(Format::Ofx),
+            "qif" => Some(Format::Qif),
+            _ => None,
+        }
+    }
+}
+
+/// `CsvMapping` describes where a bank's CSV export keeps each field.
+///
+/// Columns are named by their header, or by their 0-based position when the file has no
+/// header row. The amount is either one signed `amount` column or separate `debit` and
+/// `credit` columns.
+///
+/// # Example
+///
+/// ```yaml
+/// date: Posting Date
+/// date_format: "%m/%d/%Y"
+/// amount: Amount
+/// payee: Description
+/// ```
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct CsvMapping {
+    pub date: String,
+    /// A chrono format string, e.g. `%d.%m.%Y`.
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    pub amount: Option<String>,
+    pub debit: Option<String>,
+    pub credit: Option<String>,
+    pub payee: String,
+    pub memo: Option<String>,
+    pub category: Option<String>,
+    /// The bank's reference for each transaction, used to recognize it on re-import.
+    pub id: Option<String>,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    #[serde(default = "default_has_headers")]
+    pub has_headers: bool,
+    /// Lines before the header (or the first transaction), such as an account summary.
+    #[serde(default)]
+    pub skip_rows: usize,
+    /// Flips the sign of amounts, for card exports that show purchases as positive.
+    #[serde(default)]
+    pub negate: bool,
+    /// Amounts are written like `1.234,56`.
+    #[serde(default)]
+    pub decimal_comma: bool,
+}
+
+fn default_date_format() -> String {
+    "%Y-%m-%d".to_string()
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_has_headers() -> bool {
+    true
+}
+
+/// Reads the transactions in a bank export, recognizing the format by the file's extension
+/// unless it is given. CSV files need a mapping. Amounts without a currency of their own are
+/// in `currency`.
+pub fn import_file(
+    path: &Path,
+    format: Option<Format>,
+    mapping: Option<&CsvMapping>,
+    currency: Currency,
+) -> Result<Vec<Transaction>> {
+    let format = format.or_else(|| Format::from_path(path)).ok_or_else(|| {
+        Error::Invalid(format!(
+            "{}: unknown export format, expected .csv, .ofx, .qfx or .qif",
+            path.display()
+        ))
+    })?;
+    let bytes = std::fs::read(path)?;
+    // exports are often Latin-1 rather than UTF-8
+    let source = match String::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => e.into_bytes().iter().map(|b| *b as char).collect(),
+    };
+    let in_file = |e: Error| Error::Invalid(format!("{}: {}", path.display(), e));
+    match format {
+        Format::Csv => {
+            let mapping = mapping.ok_or_else(|| {
+                Error::Invalid(format!("{}: CSV imports need a column mapping", path.display()))
+            })?;
+            read_csv(&source, mapping, currency).map_err(in_file)
+        }
+        Format::Ofx => read_ofx(&source, currency).map_err(in_file),
+        Format::Qif => read_qif(&source, currency).map_err(in_file),
+    }
+}
+
+/// Parses an amount as banks write it: with currency symbols, thousands separators, and
+/// negatives as `-12.00`, `12.00-` or `(12.00)`.
+pub fn parse_amount(text: &str, decimal_comma: bool) -> Result<Decimal> {
+    let mut s: String = text
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '-' | '+' | '(' | ')'))
+        .collect();
+    let mut negative = false;
+    if s.starts_with('(') && s.ends_with(')') {
+        negative = true;
+        s = s[1..s.len() - 1].to_string();
+    }
+    if s.ends_with('-') {
+        negative = !negative;
+        s.pop();
+    }
+    let s = if decimal_comma {
+        s.replace('.', "").replace(',', ".")
+    } else {
+        s.replace(',', "")
+    };
+    let amount = Decimal::from_str(&s)
+        .map_err(|_| Error::Invalid(format!("`{}` is not an amount", text.trim())))?;
+    Ok(if negative { -amount } else { amount })
+}
+
+/// Reads a CSV export with the given column mapping.
+pub fn read_csv(source: &str, mapping: &CsvMapping, currency: Currency) -> Result<Vec<Transaction>> {
+    if mapping.amount.is_none() && mapping.debit.is_none() && mapping.credit.is_none() {
+        return Err(Error::Invalid(
+            "the CSV mapping needs an `amount` column or `debit` and `credit` columns".to_string(),
+        ));
+    }
+    let body: String = source
+        .lines()
+        .skip(mapping.skip_rows)
+        .collect::<Vec<&str>>()
+        .join("\n");
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(mapping.delimiter as u8)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(body.as_bytes());
+    let mut records = reader.records();
+
+    let headers: Vec<String> = if mapping.has_headers {
+        match records.next() {
+            Some(r) => r
+                .map_err(|e| Error::Invalid(e.to_string()))?
+                .iter()
+                .map(|h| h.trim().to_lowercase())
+                .collect(),
+            None => return Ok(vec![]),
+        }
+    } else {
+        vec![]
+    };
+    let column = |name: &Option<String>| -> Result<Option<usize>> {
+        let name = match name {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        if let Some(i) = headers.iter().position(|h| *h == name.trim().to_lowercase()) {
+            return Ok(Some(i));
+        }
+        name.parse::<usize>()
+            .map(Some)
+            .map_err(|_| Error::Invalid(format!("no column named `{}`", name)))
+    };
+    let date = column(&Some(mapping.date.clone()))?.unwrap();
+    let payee = column(&Some(mapping.payee.clone()))?.unwrap();
+    let (amount, debit, credit) = (
+        column(&mapping.amount)?,
+        column(&mapping.debit)?,
+        column(&mapping.credit)?,
+    );
+    let (memo, category, id) = (
+        column(&mapping.memo)?,
+        column(&mapping.category)?,
+        column(&mapping.id)?,
+    );
+
+    let mut transactions = vec![];
+    for (i, record) in records.enumerate() {
+        let line = i + 1 + mapping.skip_rows + mapping.has_headers as usize;
+        let at_line = |e: Error| Error::Invalid(format!("line {}: {}", line, e));
+        let record = record.map_err(|e| at_line(Error::Invalid(e.to_string())))?;
+        if record.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+        let field = |c: Option<usize>| {
+            c.and_then(|c| record.get(c))
+                .map(|f| f.trim())
+                .filter(|f| !f.is_empty())
+        };
+        let text = field(Some(date)).unwrap_or_default();
+        let date = NaiveDate::parse_from_str(text, &mapping.date_format).map_err(|e| {
+            at_line(Error::Invalid(format!(
+                "`{}` does not match `{}`: {}",
+                text, mapping.date_format, e
+            )))
+        })?;
+        let mut value = match field(amount) {
+            Some(a) => parse_amount(a, mapping.decimal_comma).map_err(at_line)?,
+            None => {
+                let credit = match field(credit) {
+                    Some(c) => parse_amount(c, mapping.decimal_comma).map_err(at_line)?.abs(),
+                    None => Decimal::ZERO,
+                };
+                let debit = match field(debit) {
+                    Some(d) => parse_amount(d, mapping.decimal_comma).map_err(at_line)?.abs(),
+                    None => Decimal::ZERO,
+                };
+                credit - debit
+            }
+        };
+        if mapping.negate {
+            value = -value;
+        }
+        transactions.push(Transaction {
+            id: field(id).unwrap_or_default().to_string(),
+            date,
+            amount: Money::new(value, currency),
+            payee: field(Some(payee)).unwrap_or_default().to_string(),
+            memo: field(memo).map(|m| m.to_string()),
+            category: field(category).map(|c| c.to_string()),
+        });
+    }
+    transaction_ids(&mut transactions);
+    Ok(transactions)
+}
+
+/// Reads an OFX statement. OFX 1.x leaves elements unclosed and 2.x is XML; both are read
+/// as a sequence of `<TAG>value` pairs within each `<STMTTRN>` aggregate.
+pub fn read_ofx(source: &str, currency: Currency) -> Result<Vec<Transaction>> {
+    let currency = match ofx_fields(source).get("CURDEF") {
+        Some(c) => c.parse()?,
+        None => currency,
+    };
+    let mut transactions = vec![];
+    for block in source.split("<STMTTRN>").skip(1) {
+        let block = block.split("</STMTTRN>").next().unwrap_or_default();
+        let fields = ofx_fields(block);
+        let get = |tag: &str| fields.get(tag).map(|v| v.as_str());
+        let posted = get("DTPOSTED")
+            .ok_or_else(|| Error::Invalid("transaction without DTPOSTED".to_string()))?;
+        let date = posted
+            .get(..8)
+            .and_then(|d| NaiveDate::parse_from_str(d, "%Y%m%d").ok())
+            .ok_or_else(|| Error::Invalid(format!("`{}` is not an OFX date", posted)))?;
+        let amount = get("TRNAMT")
+            .ok_or_else(|| Error::Invalid(format!("transaction on {} without TRNAMT", date)))?;
+        transactions.push(Transaction {
+            id: get("FITID").unwrap_or_default().to_string(),
+            date,
+            amount: Money::new(parse_amount(amount, false)?, currency),
+            payee: get("NAME")
+                .or_else(|| get("MEMO"))
+                .unwrap_or_default()
+                .to_string(),
+            memo: get("MEMO").map(|m| m.to_string()),
+            category: None,
+        });
+    }
+    transaction_ids(&mut transactions);
+    Ok(transactions)
+}
+
+// The first value of every element in an OFX fragment.
+fn ofx_fields(source: &str) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    for part in source.split('<').skip(1) {
+        let (tag, rest) = match part.split_once('>') {
+            Some(t) => t,
+            None => continue,
+        };
+        if tag.starts_with(['/', '?', '!']) {
+            continue;
+        }
+        let value = rest.trim();
+        if !value.is_empty() {
+            fields
+                .entry(tag.trim().to_uppercase())
+                .or_insert_with(|| unescape(value));
+        }
+    }
+    fields
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Reads a QIF file. Dates are month first, as Quicken writes them, with the year after `/`
+/// or, for years from 2000, after `'`.
+pub fn read_qif(source: &str, currency: Currency) -> Result<Vec<Transaction>> {
+    let mut transactions = vec![];
+    let mut date: Option<NaiveDate> = None;
+    let mut amount: Option<Decimal> = None;
+    let (mut payee, mut memo, mut category) = (None, None, None);
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim_end();
+        let at_line = |e: Error| Error::Invalid(format!("line {}: {}", i + 1, e));
+        let (code, value) = match line.chars().next() {
+            Some(c) => (c, line[c.len_utf8()..].trim()),
+            None => continue,
+        };
+        match code {
+            // headers, and check numbers, which don't identify a transaction across accounts
+            '!' | 'N' => {}
+            'D' => date = Some(parse_qif_date(value).map_err(at_line)?),
+            'T' | 'U' => amount = Some(parse_amount(value, false).map_err(at_line)?),
+            'P' => payee = Some(value.to_string()),
+            'M' => memo = Some(value.to_string()),
+            'L' => category = Some(value.to_string()),
+            '^' => {
+                let (d, a) = match (date.take(), amount.take()) {
+                    (Some(d), Some(a)) => (d, a),
+                    _ => {
+                        return Err(at_line(Error::Invalid(
+                            "transaction without a date or amount".to_string(),
+                        )))
+                    }
+                };
+                let memo = memo.take();
+                transactions.push(Transaction {
+                    id: String::new(),
+                    date: d,
+                    amount: Money::new(a, currency),
+                    payee: payee.take().or_else(|| memo.clone()).unwrap_or_default(),
+                    memo,
+                    category: category.take(),
+                });
+            }
+            _ => {}
+        }
+    }
+    transaction_ids(&mut transactions);
+    Ok(transactions)
+}
+
+fn parse_qif_date(value: &str) -> Result<NaiveDate> {
+    let invalid = || Error::Invalid(format!("`{}` is not a QIF date", value));
+    let parts: Vec<&str> = value
+        .split(['/', '-', '.', '\''])
+        .map(|p| p.trim())
+        .collect();
+    let [month, day, year] = parts[..] else {
+        return Err(invalid());
+    };
+    let (month, day, mut year) = (
+        month.parse::<u32>().map_err(|_| invalid())?,
+        day.parse::<u32>().map_err(|_| invalid())?,
+        year.parse::<i32>().map_err(|_| invalid())?,
+    );
+    if year < 100 {
+        year += if value.contains('\'') || year < 70 { 2000 } else { 1900 };
+    }
+    NaiveDate::from_ymd_opt(year, month, day).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod import_tests {
+    use super::*;
+
+    fn mapping(yaml: &str) -> CsvMapping {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        let amount = |s| parse_amount(s, false).unwrap().to_string();
+        assert_eq!(amount("$1,234.50"), "1234.50");
+        assert_eq!(amount("(12.00)"), "-12.00");
+        assert_eq!(amount("12.00-"), "-12.00");
+        assert_eq!(parse_amount("-1.234,56 €", true).unwrap().to_string(), "-1234.56");
+        assert!(parse_amount("n/a", false).is_err());
+    }
+
+    #[test]
+    fn test_read_csv() {
+        let source = "Account: 1234\n\
+            Date,Description,Debit,Credit,Ref\n\
+            01/02/2020,\"Coffee, Main St\",3.50,,A1\n\
+            01/03/2020,Payroll,,\"1,000.00\",A2\n\
+            \n";
+        let m = mapping(
+            "date: Date\ndate_format: \"%m/%d/%Y\"\ndebit: Debit\ncredit: Credit\n\
+             payee: Description\nid: Ref\nskip_rows: 1\n",
+        );
+        let t = read_csv(source, &m, Currency::USD).unwrap();
+        assert_eq!(t.len(), 2);
+        assert_eq!(t[0].payee, "Coffee, Main St");
+        assert_eq!(t[0].amount, Money::try_from(-3.5).unwrap());
+        assert_eq!(t[1].amount, Money::try_from(1000.0).unwrap());
+        assert_eq!(t[1].id, "A2");
+
+        let m = mapping("date: 0\namount: 2\npayee: 1\nhas_headers: false\nnegate: true\n");
+        let t = read_csv("2020-01-05,Books,25.00\n", &m, Currency::USD).unwrap();
+        assert_eq!(t[0].amount, Money::try_from(-25.0).unwrap());
+        assert!(!t[0].id.is_empty());
+
+        let err = read_csv("2020-13-05,Books,25.00\n", &m, Currency::USD).unwrap_err();
+        assert!(err.to_string().starts_with("line 1: `2020-13-05`"));
+    }
+
+    #[test]
+    fn test_read_ofx() {
+        let sgml = "OFXHEADER:100\nDATA:OFXSGML\n\n<OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS>\
+            <CURDEF>EUR\n<BANKTRANLIST>\n\
+            <STMTTRN>\n<TRNTYPE>DEBIT\n<DTPOSTED>20200102120000[-5:EST]\n<TRNAMT>-3.50\n\
+            <FITID>1001\n<NAME>Coffee &amp; Co\n</STMTTRN>\n\
+            <STMTTRN>\n<TRNTYPE>CREDIT\n<DTPOSTED>20200103\n<TRNAMT>1000.00\n<FITID>1002\n\
+            <NAME>Payroll\n<MEMO>January\n</STMTTRN>\n</BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>";
+        let t = read_ofx(sgml, Currency::USD).unwrap();
+        assert_eq!(t.len(), 2);
+        assert_eq!(t[0].payee, "Coffee & Co");
+        assert_eq!(t[0].amount, "-3.50 EUR".parse().unwrap());
+        assert_eq!(t[0].date, NaiveDate::from_ymd_opt(2020, 1, 2).unwrap());
+        assert_eq!(t[1].id, "1002");
+        assert_eq!(t[1].memo.as_deref(), Some("January"));
+
+        let xml = "<?xml version=\"1.0\"?><?OFX OFXHEADER=\"200\"?><OFX><STMTTRN>\
+            <TRNTYPE>DEBIT</TRNTYPE><DTPOSTED>20200104</DTPOSTED><TRNAMT>-20.00</TRNAMT>\
+            <FITID>X9</FITID><NAME>Groceries</NAME></STMTTRN></OFX>";
+        let t = read_ofx(xml, Currency::USD).unwrap();
+        assert_eq!(t[0].amount, Money::try_from(-20.0).unwrap());
+        assert_eq!(t[0].payee, "Groceries");
+    }
+
+    #[test]
+    fn test_read_qif() {
+        let source = "!Type:CCard\nD1/5'20\nT-45.20\nPGas Station\nLAuto:Fuel\n^\n\
+            D01/06/2020\nU-1,200.00\nPLandlord\nMJanuary rent\nN1042\n^\n";
+        let t = read_qif(source, Currency::USD).unwrap();
+        assert_eq!(t.len(), 2);
+        assert_eq!(t[0].date, NaiveDate::from_ymd_opt(2020, 1, 5).unwrap());
+        assert_eq!(t[0].category.as_deref(), Some("Auto:Fuel"));
+        assert_eq!(t[1].amount, Money::try_from(-1200.0).unwrap());
+        assert_eq!(t[1].memo.as_deref(), Some("January rent"));
+
+        assert!(read_qif("D1/5'20\nPNo amount\n^\n", Currency::USD).is_err());
+    }
+
+    #[test]
+    fn test_examples() {
+        let dir = Path::new("./scenarios/examples/bank");
+        let mapping: CsvMapping = serde_yaml::from_str(
+            &std::fs::read_to_string(dir.join("checking_mapping.yaml")).unwrap(),
+        )
+        .unwrap();
+        let account = crate::sim::cash::Account::new(
+            "Checking".to_string(),
+            Money::try_from(0.0).unwrap(),
+            vec![],
+            NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2021, 1, 1).unwrap(),
+        );
+        let mut ledger = crate::sim::ledger::Ledger::new(&account);
+        let csv = dir.join("checking.csv");
+        let t = import_file(&csv, None, Some(&mapping), Currency::USD).unwrap();
+        assert_eq!(ledger.import(t).unwrap().added, 6);
+        let t = import_file(&csv, None, Some(&mapping), Currency::USD).unwrap();
+        assert_eq!(ledger.import(t).unwrap().duplicates, 6);
+
+        let t = import_file(&dir.join("checking.ofx"), None, None, Currency::USD).unwrap();
+        assert_eq!(ledger.import(t).unwrap().added, 3);
+        let t = import_file(&dir.join("card.qif"), None, None, Currency::USD).unwrap();
+        assert_eq!(t.len(), 3);
+        assert!(import_file(&csv, None, None, Currency::USD).is_err());
+    }
+}
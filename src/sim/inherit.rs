@@ -0,0 +1,239 @@
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+use crate::{Error, Result};
+
+/// Key naming the file a scenario is based on, relative to the file that declares it.
+pub const EXTENDS: &str = "extends";
+
+/// Marks an element of a named list (accounts, cash flows, portfolios, assets) for removal.
+pub const REMOVE: &str = "remove";
+
+/// Reads a scenario file and resolves its `extends` chain into one document.
+///
+/// Every file is parsed as YAML (so JSON works too). A file that `extends` another is merge
+/// patched onto its fully resolved base with [`merge`]. The `extends` key is removed from the
+/// result. A file that extends itself, directly or through other files, is an error that
+/// names the whole chain.
+pub fn resolve(path: &Path) -> Result<Value> {
+    resolve_chain(path, &mut vec![])
+}
+
+/// The files a scenario is built from, starting with `path` itself and followed by each base
+/// it extends in turn.
+pub fn files(path: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![path.to_path_buf()];
+    let mut current = path.to_path_buf();
+    loop {
+        let config = std::fs::read_to_string(&current)
+            .map_err(|e| Error::Invalid(format!("{}: {}", current.display(), e)))?;
+        let doc: Value = serde_yaml::from_str(&config)
+            .map_err(|e| Error::Invalid(format!("{}: {}", current.display(), e)))?;
+        match doc.get(EXTENDS).and_then(|e| e.as_str()) {
+            Some(base) => {
+                current = current.parent().unwrap_or(Path::new(".")).join(base);
+                if files.len() > 64 {
+                    return Err(Error::Invalid(format!(
+                        "{}: too many levels of `{}`",
+                        path.display(),
+                        EXTENDS
+                    )));
+                }
+                files.push(current.clone());
+            }
+            None => return Ok(files),
+        }
+    }
+}
+
+fn resolve_chain(path: &Path, chain: &mut Vec<PathBuf>) -> Result<Value> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| Error::Invalid(format!("{}: {}", path.display(), e)))?;
+    if chain.contains(&canonical) {
+        let mut names: Vec<String> = chain.iter().map(|p| p.display().to_string()).collect();
+        names.push(canonical.display().to_string());
+        return Err(Error::Invalid(format!(
+            "Scenario inheritance cycle: {}",
+            names.join(" -> ")
+        )));
+    }
+    chain.push(canonical);
+
+    let config = std::fs::read_to_string(path)
+        .map_err(|e| Error::Invalid(format!("{}: {}", path.display(), e)))?;
+    let mut doc: Value = serde_yaml::from_str(&config)
+        .map_err(|e| Error::Invalid(format!("{}: {}", path.display(), e)))?;
+
+    rebase_fx_files(&mut doc, path.parent().unwrap_or(Path::new(".")));
+
+    let extends = match doc.as_object_mut().and_then(|o| o.remove(EXTENDS)) {
+        None => None,
+        Some(Value::String(base)) => Some(base),
+        Some(_) => {
+            return Err(Error::Invalid(format!(
+                "{}: `{}` must be a file name",
+                path.display(),
+                EXTENDS
+            )))
+        }
+    };
+
+    let resolved = match extends {
+        Some(base) => {
+            let base_path = path.parent().unwrap_or(Path::new(".")).join(base);
+            let mut base = resolve_chain(&base_path, chain)?;
+            merge(&mut base, doc);
+            base
+        }
+        None => doc,
+    };
+
+    chain.pop();
+    Ok(resolved)
+}
+
+// FX rate files are named relative to the file that declares them, like `extends`.
+fn rebase_fx_files(doc: &mut Value, dir: &Path) {
+    let rates = match doc.get_mut(super::fx::FX).and_then(|fx| fx.as_object_mut()) {
+        Some(rates) => rates,
+        None => return,
+    };
+    for rate in rates.values_mut() {
+        if let Some(Value::String(file)) = rate.get_mut("file") {
+            if Path::new(file.as_str()).is_relative() {
+                *file = dir.join(file.as_str()).display().to_string();
+            }
+        }
+    }
+}
+
+/// Applies `patch` to `target` with JSON merge patch (RFC 7386) semantics, extended for
+/// named lists.
+///
+/// Objects are merged key by key and a `null` value removes the key. Lists whose elements
+/// all carry a `name` are merged element by element: an element with a name already in the
+/// target is merge patched onto it, an element with `remove: true` deletes it, and any other
+/// element is appended. Every other value, including unnamed lists, replaces the target.
+pub fn merge(target: &mut Value, patch: Value) {
+    match (target, patch) {
+        (Value::Object(t), Value::Object(p)) => {
+            for (k, v) in p {
+                if v.is_null() {
+                    t.remove(&k);
+                } else {
+                    merge(t.entry(k).or_insert(Value::Null), v);
+                }
+            }
+        }
+        (Value::Array(t), Value::Array(p)) if is_named(t) && is_named(&p) => {
+            for v in p {
+                let name = v["name"].clone();
+                let existing = t.iter().position(|e| e["name"] == name);
+                let remove = v.get(REMOVE).and_then(|r| r.as_bool()).unwrap_or(false);
+                match (existing, remove) {
+                    (Some(i), true) => {
+                        t.remove(i);
+                    }
+                    (Some(i), false) => merge(&mut t[i], v),
+                    (None, true) => {}
+                    (None, false) => t.push(strip_nulls(v)),
+                }
+            }
+        }
+        (t, p) => *t = strip_nulls(p),
+    }
+}
+
+fn is_named(items: &[Value]) -> bool {
+    items
+        .iter()
+        .all(|i| i.get("name").map(|n| n.is_string()).unwrap_or(false))
+}
+
+/// A merge patch value that adds new content cannot contain nulls, as they only mean
+/// "remove" inside a patch.
+fn strip_nulls(v: Value) -> Value {
+    match v {
+        Value::Object(o) => Value::Object(
+            o.into_iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k, strip_nulls(v)))
+                .collect::<Map<String, Value>>(),
+        ),
+        v => v,
+    }
+}
+
+#[cfg(test)]
+mod inherit_tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_objects() {
+        let mut target = json!({"name": "Base", "seed": 1, "monte_carlo": {"samples": 10, "percentiles": [50.0]}});
+        merge(
+            &mut target,
+            json!({"name": "Variant", "seed": null, "monte_carlo": {"samples": 20}}),
+        );
+        assert_eq!(
+            target,
+            json!({"name": "Variant", "monte_carlo": {"samples": 20, "percentiles": [50.0]}})
+        );
+    }
+
+    #[test]
+    fn test_merge_named_lists() {
+        let mut target = json!({"cash_flows": [
+            {"name": "Income", "amount": 100.0, "tax_rate": 0.25},
+            {"name": "Rent", "amount": -50.0},
+        ]});
+        merge(
+            &mut target,
+            json!({"cash_flows": [
+                {"name": "Income", "amount": 120.0},
+                {"name": "Rent", "remove": true},
+                {"name": "Car", "amount": -30.0, "end_date": null},
+            ]}),
+        );
+        assert_eq!(
+            target,
+            json!({"cash_flows": [
+                {"name": "Income", "amount": 120.0, "tax_rate": 0.25},
+                {"name": "Car", "amount": -30.0},
+            ]})
+        );
+    }
+
+    #[test]
+    fn test_unnamed_lists_are_replaced() {
+        let mut target = json!({"weights": [0.5, 0.5]});
+        merge(&mut target, json!({"weights": [0.8, 0.2]}));
+        assert_eq!(target, json!({"weights": [0.8, 0.2]}));
+    }
+
+    #[test]
+    fn test_resolve_example() {
+        let doc = resolve(Path::new("./scenarios/examples/default_scenario_raise.yaml")).unwrap();
+        assert!(doc.get(EXTENDS).is_none());
+        assert_eq!(doc["name"], json!("Example Household with Raise"));
+        assert_eq!(doc["variables"]["salary"], json!(66000.0));
+        assert_eq!(doc["variables"]["contribution"], json!(500.0));
+        assert_eq!(doc["accounts"][0]["cash_flows"][2]["name"], json!("Car Payment"));
+        assert_eq!(doc["accounts"][1]["balance"], json!(10000.0));
+    }
+
+    #[test]
+    fn test_cycle() {
+        let dir = std::env::temp_dir().join(format!("budget_inherit_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.yaml"), "extends: b.yaml\nname: A\n").unwrap();
+        std::fs::write(dir.join("b.yaml"), "extends: a.yaml\nname: B\n").unwrap();
+
+        let err = resolve(&dir.join("a.yaml")).unwrap_err().to_string();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(err.starts_with("Scenario inheritance cycle"));
+        assert!(err.contains("a.yaml -> "));
+    }
+}
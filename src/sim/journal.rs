@@ -0,0 +1,516 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use super::cash::PaymentKind;
+use super::columns::{PaymentRow, PaymentTable};
+use super::import::parse_amount;
+use super::ledger::{transaction_ids, Transaction};
+use super::money::{Currency, Money};
+use crate::{Error, Result};
+
+/// Plain-text accounting file formats. hledger reads and writes the `Ledger` format.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    Ledger,
+    Beancount,
+}
+
+impl Dialect {
+    /// The dialect implied by a journal file's extension, if it is one.
+    pub fn from_path(path: &Path) -> Option<Dialect> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "ledger" | "journal" | "hledger" | "dat" => Some(Dialect::Ledger),
+            "beancount" | "bean" => Some(Dialect::Beancount),
+            _ => None,
+        }
+    }
+}
+
+/// `JournalMapping` names the journal accounts that payments are posted to.
+///
+/// Simulated accounts default to `Assets:<Name>`, and categories to `Income:<Category>` or
+/// `Expenses:<Category>` depending on the direction of the payment. Taxes on cash flows go
+/// to `taxes`.
+///
+/// # Example
+///
+/// ```yaml
+/// accounts:
+///   Checking: Assets:Bank:Checking
+/// categories:
+///   Housing: Expenses:Home:Rent
+/// taxes: Expenses:Taxes:Federal
+/// ```
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct JournalMapping {
+    #[serde(default)]
+    pub accounts: BTreeMap<String, String>,
+    #[serde(default)]
+    pub categories: BTreeMap<String, String>,
+    #[serde(default = "default_taxes")]
+    pub taxes: String,
+}
+
+fn default_taxes() -> String {
+    "Expenses:Taxes".to_string()
+}
+
+impl Default for JournalMapping {
+    fn default() -> JournalMapping {
+        JournalMapping {
+            accounts: BTreeMap::new(),
+            categories: BTreeMap::new(),
+            taxes: default_taxes(),
+        }
+    }
+}
+
+impl JournalMapping {
+    /// The journal account of a simulated account.
+    pub fn account(&self, name: &str) -> String {
+        self.accounts
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| format!("Assets:{}", component(name)))
+    }
+
+    /// The journal account a payment is posted against.
+    pub fn counterpart(&self, payment: &PaymentRow) -> String {
+        let flow = payment.cash_flow();
+        if payment.kind == PaymentKind::Tax {
+            return self.taxes.clone();
+        }
+        let category = flow.category();
+        self.categories.get(category).cloned().unwrap_or_else(|| {
+            let root = if payment.amount.is_negative() {
+                "Expenses"
+            } else {
+                "Income"
+            };
+            let path: Vec<String> = category.split(':').map(component).collect();
+            format!("{}:{}", root, path.join(":"))
+        })
+    }
+}
+
+// An account name component both dialects accept: capitalized words joined by dashes.
+fn component(name: &str) -> String {
+    let words: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut chars = w.chars();
+            let first = chars.next().unwrap().to_uppercase();
+            first.chain(chars).collect()
+        })
+        .collect();
+    if words.is_empty() {
+        "Uncategorized".to_string()
+    } else {
+        words.join("-")
+    }
+}
+
+// A tag both dialects accept.
+fn tag(tag: &str) -> String {
+    tag.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect()
+}
+
+/// Writes payments as journal entries, one per payment with a posting to the paying account
+/// and one to its counterpart. Beancount journals open every account on the first date.
+pub fn write_journal(payments: &PaymentTable, mapping: &JournalMapping, dialect: Dialect) -> String {
+    let mut out = String::new();
+    if dialect == Dialect::Beancount {
+        let mut opened = BTreeSet::new();
+        for p in payments.iter() {
+            opened.insert(mapping.account(p.account_name().unwrap_or_default()));
+            opened.insert(mapping.counterpart(&p));
+        }
+        if let Some(first) = payments.iter().map(|p| p.date).min() {
+            for account in opened {
+                out.push_str(&format!("{} open {}\n", first, account));
+            }
+            out.push('\n');
+        }
+    }
+
+    for p in payments.iter() {
+        let description = p.name();
+        let account = mapping.account(p.account_name().unwrap_or_default());
+        let amount = |m: Money| {
+            let places = m.currency().minor_units() as usize;
+            format!("{:.*} {}", places, m.amount(), m.currency())
+        };
+        let tags: Vec<String> = p.tags().iter().map(|t| tag(t)).collect();
+        match dialect {
+            Dialect::Ledger => {
+                out.push_str(&format!("{} * {}\n", p.date, description));
+                if !tags.is_empty() {
+                    out.push_str(&format!("    ; :{}:\n", tags.join(":")));
+                }
+            }
+            Dialect::Beancount => {
+                out.push_str(&format!("{} * \"{}\"", p.date, description.replace('"', "'")));
+                for t in &tags {
+                    out.push_str(&format!(" #{}", t));
+                }
+                out.push('\n');
+            }
+        }
+        out.push_str(&format!("    {}  {}\n", account, amount(p.amount)));
+        out.push_str(&format!("    {}  {}\n\n", mapping.counterpart(&p), amount(-p.amount)));
+    }
+    out
+}
+
+// One posting: the account and, unless it was left out to balance the entry, its amount.
+struct Posting {
+    account: String,
+    amount: Option<Money>,
+}
+
+struct Entry {
+    line: usize,
+    date: NaiveDate,
+    payee: String,
+    memo: Option<String>,
+    postings: Vec<Posting>,
+}
+
+/// Reads the entries of a ledger, hledger or beancount journal that post to `account`, as
+/// transactions of that account. The amount is the posting's (or the balance of the others
+/// when it has none), the payee the entry's description and the category the first other
+/// account. Directives other than transactions are skipped.
+pub fn read_journal(source: &str, account: &str) -> Result<Vec<Transaction>> {
+    let mut transactions = vec![];
+    for entry in entries(source)? {
+        let at_line = |e: Error| Error::Invalid(format!("line {}: {}", entry.line, e));
+        let posting = match entry.postings.iter().position(|p| p.account == account) {
+            Some(i) => i,
+            None => continue,
+        };
+        let amount = match entry.postings[posting].amount {
+            Some(amount) => amount,
+            None => {
+                let mut others = entry.postings.iter().filter_map(|p| p.amount);
+                let first = others.next().ok_or_else(|| {
+                    at_line(Error::Invalid("no posting has an amount".to_string()))
+                })?;
+                -others
+                    .try_fold(first, |total, a| total.checked_add(a))
+                    .map_err(at_line)?
+            }
+        };
+        let category = entry
+            .postings
+            .iter()
+            .enumerate()
+            .find(|(i, _)| *i != posting)
+            .map(|(_, p)| p.account.clone());
+        transactions.push(Transaction {
+            id: String::new(),
+            date: entry.date,
+            amount,
+            payee: entry.payee,
+            memo: entry.memo,
+            category,
+        });
+    }
+    transaction_ids(&mut transactions);
+    Ok(transactions)
+}
+
+/// Reads a journal file, see [`read_journal`].
+pub fn read_journal_file(path: &Path, account: &str) -> Result<Vec<Transaction>> {
+    read_journal(&std::fs::read_to_string(path)?, account)
+        .map_err(|e| Error::Invalid(format!("{}: {}", path.display(), e)))
+}
+
+fn entries(source: &str) -> Result<Vec<Entry>> {
+    let mut entries: Vec<Entry> = vec![];
+    // whether indented lines belong to a transaction rather than another directive
+    let mut in_transaction = false;
+    for (i, raw) in source.lines().enumerate() {
+        let at_line = |e: Error| Error::Invalid(format!("line {}: {}", i + 1, e));
+        let line = strip_comment(raw);
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !raw.starts_with([' ', '\t']) {
+            in_transaction = false;
+            let (date, rest) = match line.split_once(char::is_whitespace) {
+                Some((date, rest)) => (date, rest.trim()),
+                None => (line.trim(), ""),
+            };
+            // ledger's auxiliary dates, `2020-01-01=2020-01-03`
+            let date = date.split('=').next().unwrap_or_default().replace('/', "-");
+            let date = match NaiveDate::parse_from_str(&date, "%Y-%m-%d") {
+                Ok(date) => date,
+                // automated and periodic transactions, options, includes and the like
+                Err(_) => continue,
+            };
+            if let Some((payee, memo)) = description(rest) {
+                entries.push(Entry {
+                    line: i + 1,
+                    date,
+                    payee,
+                    memo,
+                    postings: vec![],
+                });
+                in_transaction = true;
+            }
+            continue;
+        }
+        if !in_transaction {
+            continue;
+        }
+        if let Some(posting) = posting(line.trim()).map_err(at_line)? {
+            entries.last_mut().unwrap().postings.push(posting);
+        }
+    }
+    Ok(entries)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            '#' if !quoted && i == 0 => return "",
+            _ => {}
+        }
+    }
+    line
+}
+
+// The payee and memo of a transaction header after its date, or None for other directives
+// such as beancount's `open` and `balance`.
+fn description(rest: &str) -> Option<(String, Option<String>)> {
+    let mut rest = rest;
+    let mut flagged = false;
+    for flag in ["txn", "*", "!"] {
+        if let Some(r) = rest.strip_prefix(flag) {
+            if flag != "txn" || r.starts_with(char::is_whitespace) {
+                rest = r.trim_start();
+                flagged = true;
+                break;
+            }
+        }
+    }
+    if rest.starts_with('(') {
+        if let Some((_, r)) = rest.split_once(')') {
+            rest = r.trim_start();
+        }
+    }
+
+    if rest.starts_with('"') {
+        // beancount: `"payee" "narration"` or just `"narration"`, then tags and links
+        let strings: Vec<&str> = rest.split('"').skip(1).step_by(2).collect();
+        return match strings[..] {
+            [narration] => Some((narration.to_string(), None)),
+            [payee, narration, ..] => Some((
+                payee.to_string(),
+                Some(narration.to_string()).filter(|n| !n.is_empty()),
+            )),
+            [] => None,
+        };
+    }
+    let word = rest.split_whitespace().next().unwrap_or_default();
+    let directive = [
+        "open", "close", "balance", "pad", "price", "note", "document", "commodity", "event",
+        "query", "custom",
+    ];
+    if !flagged && directive.contains(&word) {
+        return None;
+    }
+    // hledger's `payee | note`
+    match rest.split_once('|') {
+        Some((payee, note)) => Some((payee.trim().to_string(), Some(note.trim().to_string()))),
+        None => Some((rest.trim().to_string(), None)),
+    }
+}
+
+// A posting line, or None for metadata such as `id: "1234"`.
+fn posting(line: &str) -> Result<Option<Posting>> {
+    let line = line.trim_start_matches(['*', '!']).trim_start();
+    // ledger accounts may contain single spaces, so two spaces or a tab end them
+    let (account, rest) = match line.find("  ").or_else(|| line.find('\t')) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => match line.split_once(' ') {
+            // beancount accounts never contain spaces
+            Some((account, _)) if account.ends_with(':') => return Ok(None),
+            Some((account, rest)) if rest.contains(|c: char| c.is_ascii_digit()) => {
+                (account, rest.trim())
+            }
+            _ => (line, ""),
+        },
+    };
+    if account.ends_with(':') || !account.contains(':') {
+        return Ok(None);
+    }
+    // virtual postings
+    let account = account.trim_matches(['(', ')', '[', ']']).to_string();
+    // prices and costs don't change the amount in the posting's own commodity
+    let rest = rest.split(['@', '{']).next().unwrap_or_default().trim();
+    if rest.is_empty() {
+        return Ok(Some(Posting {
+            account,
+            amount: None,
+        }));
+    }
+    Ok(Some(Posting {
+        account,
+        amount: Some(money(rest)?),
+    }))
+}
+
+// `1500.00 USD`, `USD -1500.00`, `$1,500.00` or `-€12,50`.
+fn money(text: &str) -> Result<Money> {
+    let symbol: String = text
+        .chars()
+        .filter(|c| !c.is_ascii_digit() && !matches!(c, '.' | ',' | '-' | '+' | ' '))
+        .collect();
+    let currency = match symbol.as_str() {
+        "" => Currency::default(),
+        "$" => "USD".parse()?,
+        "€" => "EUR".parse()?,
+        "£" => "GBP".parse()?,
+        "¥" => "JPY".parse()?,
+        code => code.parse().map_err(|_| {
+            Error::Invalid(format!("`{}` is not a currency this simulation can use", code))
+        })?,
+    };
+    let number: String = text
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '-' | '+'))
+        .collect();
+    let amount: Decimal = parse_amount(&number, false)?;
+    Ok(Money::new(amount, currency))
+}
+
+#[cfg(test)]
+mod journal_tests {
+    use super::*;
+    use crate::sim::cash::{Account, CashFlow, Frequency};
+
+    fn date(m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2020, m, d).unwrap()
+    }
+
+    fn payments() -> PaymentTable {
+        let mut rent = CashFlow::new(
+            Some("Rent".to_string()),
+            Money::try_from(-1500.0).unwrap(),
+            Some(Frequency::MonthStart),
+            None,
+            None,
+            None,
+        );
+        rent.category = Some("Housing".to_string());
+        rent.tags = vec!["fixed".to_string()];
+        let pay = CashFlow::new(
+            Some("Acme Payroll".to_string()),
+            Money::try_from(3000.0).unwrap(),
+            Some(Frequency::MonthEnd),
+            None,
+            None,
+            Some(0.2),
+        );
+        let mut account = Account::new(
+            "Checking".to_string(),
+            Money::try_from(0.0).unwrap(),
+            vec![rent, pay],
+            date(1, 1),
+            date(3, 1),
+        );
+        account.payments(date(1, 1), date(1, 31)).unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn test_write_ledger() {
+        let journal = write_journal(&payments(), &JournalMapping::default(), Dialect::Ledger);
+        assert_eq!(
+            journal,
+            "2020-01-01 * Rent\n    ; :fixed:\n    Assets:Checking  -1500.00 USD\n    Expenses:Housing  1500.00 USD\n\n\
+             2020-01-31 * Acme Payroll\n    Assets:Checking  3000.00 USD\n    Income:Acme-Payroll  -3000.00 USD\n\n\
+             2020-01-31 * Acme Payroll\n    Assets:Checking  -600.00 USD\n    Expenses:Taxes  600.00 USD\n\n"
+        );
+    }
+
+    #[test]
+    fn test_beancount_round_trip() {
+        let mapping: JournalMapping =
+            serde_yaml::from_str("accounts:\n  Checking: Assets:Bank:Checking\n").unwrap();
+        let journal = write_journal(&payments(), &mapping, Dialect::Beancount);
+        assert!(journal.starts_with("2020-01-01 open Assets:Bank:Checking\n"));
+        assert!(journal.contains("2020-01-01 * \"Rent\" #fixed\n"));
+
+        let transactions = read_journal(&journal, "Assets:Bank:Checking").unwrap();
+        let amounts: Vec<f64> = transactions.iter().map(|t| t.amount.to_f64()).collect();
+        assert_eq!(amounts, vec![-1500.0, 3000.0, -600.0]);
+        assert_eq!(transactions[0].category.as_deref(), Some("Expenses:Housing"));
+        assert_eq!(transactions[2].payee, "Acme Payroll");
+        assert_eq!(transactions[2].category.as_deref(), Some("Expenses:Taxes"));
+    }
+
+    #[test]
+    fn test_read_ledger() {
+        let source = "; opening\n\
+            account Assets:Checking\n\
+            = /Expenses/\n    (Budget)  -1\n\n\
+            2020/01/02=2020/01/04 * (1042) Corner Cafe | coffee with Sam\n\
+            \x20   Expenses:Food:Dining Out    $4.50  ; tip included\n\
+            \x20   Assets:Checking\n\n\
+            2020-01-03 Payroll\n\
+            \x20   Assets:Checking        2,000.00 USD\n\
+            \x20   Income:Salary and Wages\n\n\
+            2020-01-05 Transfer\n\
+            \x20   Assets:Savings    100 USD\n\
+            \x20   Assets:Brokerage\n";
+        let t = read_journal(source, "Assets:Checking").unwrap();
+        assert_eq!(t.len(), 2);
+        assert_eq!(t[0].date, date(1, 2));
+        assert_eq!(t[0].payee, "Corner Cafe");
+        assert_eq!(t[0].memo.as_deref(), Some("coffee with Sam"));
+        assert_eq!(t[0].amount, Money::try_from(-4.5).unwrap());
+        assert_eq!(t[0].category.as_deref(), Some("Expenses:Food:Dining Out"));
+        assert_eq!(t[1].amount, Money::try_from(2000.0).unwrap());
+        assert_eq!(t[1].category.as_deref(), Some("Income:Salary and Wages"));
+    }
+
+    #[test]
+    fn test_read_beancount() {
+        let source = "option \"operating_currency\" \"EUR\"\n\
+            2020-01-01 open Assets:Girokonto EUR\n\
+            2020-01-02 balance Assets:Girokonto 0 EUR\n\
+            2020-01-03 * \"Bäckerei\" \"Brötchen\" #breakfast\n\
+            \x20 id: \"abc\"\n\
+            \x20 Assets:Girokonto -3.20 EUR\n\
+            \x20 Expenses:Food\n\
+            2020-01-04 txn \"Miete\"\n\
+            \x20 Assets:Girokonto -900 EUR\n\
+            \x20 Expenses:Rent 900 EUR\n";
+        let t = read_journal(source, "Assets:Girokonto").unwrap();
+        assert_eq!(t.len(), 2);
+        assert_eq!(t[0].payee, "Bäckerei");
+        assert_eq!(t[0].memo.as_deref(), Some("Brötchen"));
+        assert_eq!(t[0].amount, "-3.20 EUR".parse().unwrap());
+        assert_eq!(t[1].payee, "Miete");
+        assert_eq!(t[1].category.as_deref(), Some("Expenses:Rent"));
+    }
+
+    #[test]
+    fn test_unknown_commodity() {
+        let source = "2020-01-01 Buy\n    Assets:Checking  10 AAPL\n    Assets:Cash\n";
+        let err = read_journal(source, "Assets:Checking").unwrap_err();
+        assert!(err.to_string().starts_with("line 2: `AAPL`"));
+    }
+}
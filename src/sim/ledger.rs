@@ -0,0 +1,228 @@
+use chrono::NaiveDate;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use super::cash::Account;
+use super::money::{Currency, Money};
+use crate::{Error, Result};
+
+/// `Transaction` is one posted entry from a bank or credit card statement.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct Transaction {
+    /// Identifies the transaction across imports: the bank's own id when the export has one,
+    /// otherwise derived from its date, amount and payee (see [`transaction_ids`]).
+    pub id: String,
+    pub date: NaiveDate,
+    /// Positive for money coming into the account.
+    pub amount: Money,
+    pub payee: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    /// Category assigned by the bank or in a QIF file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+}
+
+/// What happened when transactions were added to a [`Ledger`].
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub duplicates: usize,
+}
+
+/// `Ledger` holds the actual transactions of one account, in date order.
+///
+/// Ledgers are kept as YAML files next to the scenario and grow with every import. Importing
+/// the same export twice, or overlapping exports, adds each transaction once.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct Ledger {
+    /// Name of the [`Account`] the transactions belong to.
+    pub account: String,
+    pub currency: Currency,
+    #[serde(default)]
+    pub transactions: Vec<Transaction>,
+}
+
+impl Ledger {
+    pub fn new(account: &Account) -> Ledger {
+        Ledger {
+            account: account.name.clone(),
+            currency: account.balance.currency(),
+            transactions: vec![],
+        }
+    }
+
+    /// Reads a ledger file, or starts an empty one for `account` if the file does not exist.
+    pub fn open(path: &Path, account: &Account) -> Result<Ledger> {
+        if !path.exists() {
+            return Ok(Ledger::new(account));
+        }
+        let ledger: Ledger = serde_yaml::from_str(&std::fs::read_to_string(path)?)?;
+        if ledger.account != account.name {
+            return Err(Error::Invalid(format!(
+                "{} is the ledger of account {}, not {}",
+                path.display(),
+                ledger.account,
+                account.name
+            )));
+        }
+        Ok(ledger)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Adds the transactions whose ids are not in the ledger yet. If any transaction is in
+    /// another currency none of them are added.
+    pub fn import(&mut self, transactions: Vec<Transaction>) -> Result<ImportSummary> {
+        if let Some(t) = transactions
+            .iter()
+            .find(|t| t.amount.currency() != self.currency)
+        {
+            return Err(Error::Invalid(format!(
+                "Transaction {} on {} is in {} but the ledger of {} is in {}",
+                t.payee,
+                t.date,
+                t.amount.currency(),
+                self.account,
+                self.currency
+            )));
+        }
+        let mut ids: HashSet<String> = self.transactions.iter().map(|t| t.id.clone()).collect();
+        let mut summary = ImportSummary {
+            added: 0,
+            duplicates: 0,
+        };
+        for t in transactions {
+            if ids.insert(t.id.clone()) {
+                self.transactions.push(t);
+                summary.added += 1;
+            } else {
+                summary.duplicates += 1;
+            }
+        }
+        self.transactions.sort_by_key(|t| t.date);
+        Ok(summary)
+    }
+
+    /// Transactions on or after `start_date` and before `end_date`.
+    pub fn between(&self, start_date: NaiveDate, end_date: NaiveDate) -> Vec<&Transaction> {
+        self.transactions
+            .iter()
+            .filter(|t| t.date >= start_date && t.date < end_date)
+            .collect()
+    }
+
+    /// The balance at the end of `date`, from the account's opening balance and the ledger's
+    /// transactions since the account started. This is only the actual balance when the
+    /// ledger holds every transaction since then.
+    pub fn balance_at(&self, account: &Account, date: NaiveDate) -> Result<Money> {
+        let end = date
+            .succ_opt()
+            .ok_or_else(|| Error::Invalid(format!("{} is out of range", date)))?;
+        self.between(account.start_date, end)
+            .into_iter()
+            .try_fold(account.balance, |balance, t| balance.checked_add(t.amount))
+    }
+}
+
+/// Fills in the ids of transactions the export didn't identify, in file order.
+///
+/// The id hashes the date, amount and payee. Identical transactions in one export (two
+/// coffees on the same day) are told apart by their position among each other, so
+/// re-importing the export, or a longer one covering the same days, produces the same ids.
+pub fn transaction_ids(transactions: &mut [Transaction]) {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for t in transactions.iter_mut().filter(|t| t.id.is_empty()) {
+        let key = format!(
+            "{}|{}|{}",
+            t.date,
+            t.amount.amount().normalize(),
+            t.payee.trim().to_lowercase()
+        );
+        let n = seen.entry(key.clone()).or_insert(0);
+        *n += 1;
+        t.id = format!("{:016x}", fnv1a(format!("{}|{}", key, n).as_bytes()));
+    }
+}
+
+// FNV-1a, which unlike the standard library's hasher is stable across Rust releases
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod ledger_tests {
+    use super::*;
+
+    fn transaction(day: u32, amount: f64, payee: &str) -> Transaction {
+        Transaction {
+            id: String::new(),
+            date: NaiveDate::from_ymd_opt(2020, 1, day).unwrap(),
+            amount: Money::try_from(amount).unwrap(),
+            payee: payee.to_string(),
+            memo: None,
+            category: None,
+        }
+    }
+
+    #[test]
+    fn test_reimport_is_deduplicated() {
+        let account = Account::new(
+            "Checking".to_string(),
+            Money::try_from(0.0).unwrap(),
+            vec![],
+            NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2021, 1, 1).unwrap(),
+        );
+        let mut ledger = Ledger::new(&account);
+
+        let mut first = vec![
+            transaction(2, -3.5, "Coffee"),
+            transaction(2, -3.5, "Coffee"),
+            transaction(3, 1000.0, "Payroll"),
+        ];
+        transaction_ids(&mut first);
+        assert_ne!(first[0].id, first[1].id);
+        let summary = ledger.import(first).unwrap();
+        assert_eq!(summary, ImportSummary { added: 3, duplicates: 0 });
+
+        let mut second = vec![
+            transaction(2, -3.5, "Coffee"),
+            transaction(2, -3.5, "Coffee"),
+            transaction(3, 1000.0, "Payroll"),
+            transaction(4, -50.0, "Groceries"),
+        ];
+        transaction_ids(&mut second);
+        let summary = ledger.import(second).unwrap();
+        assert_eq!(summary, ImportSummary { added: 1, duplicates: 3 });
+        assert_eq!(ledger.transactions.len(), 4);
+    }
+
+    #[test]
+    fn test_import_in_another_currency_adds_nothing() {
+        let account = Account::new(
+            "Checking".to_string(),
+            Money::try_from(0.0).unwrap(),
+            vec![],
+            NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2021, 1, 1).unwrap(),
+        );
+        let mut ledger = Ledger::new(&account);
+
+        let mut transactions = vec![
+            transaction(2, -3.5, "Coffee"),
+            transaction(3, 1000.0, "Payroll"),
+        ];
+        transactions[1].amount = "1000 EUR".parse().unwrap();
+        transaction_ids(&mut transactions);
+        assert!(ledger.import(transactions).is_err());
+        assert!(ledger.transactions.is_empty());
+    }
+}
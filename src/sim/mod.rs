@@ -1,103 +1,291 @@
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
 use serde::Serialize;
 
+use rand::RngCore;
+
+use self::columns::{BalanceRecord, BalanceTable, PaymentRecord, PaymentTable};
+use self::money::Money;
 use self::portfolio::Invest;
+use self::terminal::Verbosity;
 use crate::sim::cash::Frequency;
+use crate::{Error, Result};
 pub mod cash;
+pub mod columns;
+pub mod compare;
 pub mod excel;
+pub mod export;
+pub mod expr;
+pub mod fx;
+pub mod import;
+pub mod inherit;
+pub mod journal;
+pub mod ledger;
+pub mod money;
+pub mod monte_carlo;
+pub mod params;
 pub mod portfolio;
+pub mod recurring;
+pub mod reforecast;
+pub mod report;
+pub mod rollup;
 mod sample;
+pub mod scenario;
+pub mod sensitivity;
+pub mod statement;
+pub mod sweep;
+pub mod template;
+pub mod terminal;
+#[cfg(test)]
+mod testing;
+pub mod validate;
+pub mod variance;
 
-#[allow(dead_code)]
+/// Combined balance of all accounts on one date in the reporting currency, and the FX gain or
+/// loss on balances held in other currencies since the start of the simulation.
 #[derive(Serialize, Clone)]
-pub struct AccountBalance {
+pub struct ConsolidatedBalance {
     pub date: chrono::NaiveDate,
-    pub account_name: String,
-    pub balance: f64,
+    pub balance: Money,
+    pub fx_gain_loss: Money,
 }
 
-impl AccountBalance {
-    fn new(date: chrono::NaiveDate, account_name: String, balance: f64) -> AccountBalance {
-        AccountBalance {
-            date,
-            account_name,
-            balance,
-        }
-    }
+/// Balances and payments of a simulation, stored as columns (see [`columns`]) so that every
+/// cash flow and account name is kept once however long the simulation runs.
+///
+/// It serializes as it did before it was columnar, with a list of `balances` (date,
+/// `account_name`, `account_id`, balance and converted balance) and a list of `payments`
+/// each holding its cash flow. Use [`SimulationResult::columnar`] for the compact form.
+#[derive(Clone, Default)]
+pub struct SimulationResult {
+    pub balances: BalanceTable,
+    pub payments: PaymentTable,
+    pub consolidated: Vec<ConsolidatedBalance>,
+    /// Gaps between projected and observed balances where a scenario was re-anchored.
+    pub reconciliations: Vec<reforecast::Reconciliation>,
 }
 
-#[derive(Serialize, Clone)]
-pub struct SimulationResult {
-    pub balances: Vec<AccountBalance>,
-    pub payments: Vec<cash::Payment>,
+/// A [`SimulationResult`] serialized as its columns and lookup tables.
+#[derive(Serialize)]
+pub struct ColumnarResult<'a> {
+    pub balances: &'a BalanceTable,
+    pub payments: &'a PaymentTable,
+    pub consolidated: &'a [ConsolidatedBalance],
+    pub reconciliations: &'a [reforecast::Reconciliation],
+}
+
+impl Serialize for SimulationResult {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        // rows are built one at a time while they are written
+        struct Rows<F>(F);
+        impl<F: Fn() -> I, I: Iterator<Item = T>, T: Serialize> Serialize for Rows<F> {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+                s.collect_seq((self.0)())
+            }
+        }
+
+        let mut s = serializer.serialize_struct("SimulationResult", 4)?;
+        s.serialize_field("balances", &Rows(|| self.balances.iter().map(BalanceRecord::from)))?;
+        s.serialize_field("payments", &Rows(|| self.payments.iter().map(PaymentRecord::from)))?;
+        s.serialize_field("consolidated", &self.consolidated)?;
+        s.serialize_field("reconciliations", &self.reconciliations)?;
+        s.end()
+    }
 }
 
 impl SimulationResult {
-    pub fn new(balances: Vec<AccountBalance>, payments: Vec<cash::Payment>) -> SimulationResult {
-        SimulationResult { balances, payments }
+    pub fn new() -> SimulationResult {
+        SimulationResult::default()
+    }
+
+    /// The result in its compact serialized form, with columns and lookup tables in place of
+    /// lists of balances and payments.
+    pub fn columnar(&self) -> ColumnarResult<'_> {
+        ColumnarResult {
+            balances: &self.balances,
+            payments: &self.payments,
+            consolidated: &self.consolidated,
+            reconciliations: &self.reconciliations,
+        }
+    }
+
+    /// Appends another result, e.g. the simulation of a second account in the same scenario.
+    pub fn extend(&mut self, mut other: SimulationResult) {
+        self.balances.append(&other.balances);
+        self.payments.append(&other.payments);
+        self.consolidated.append(&mut other.consolidated);
+        self.reconciliations.append(&mut other.reconciliations);
+    }
+
+    /// Combined balance of all accounts in the reporting currency on each recorded date, in
+    /// date order. Balances are summed exactly and converted to floating point for analysis.
+    pub fn total_balances(&self) -> Vec<(chrono::NaiveDate, f64)> {
+        let mut totals: std::collections::BTreeMap<chrono::NaiveDate, Decimal> =
+            std::collections::BTreeMap::new();
+        for (date, converted) in self.balances.date.iter().zip(&self.balances.converted) {
+            *totals.entry(*date).or_default() += converted.amount();
+        }
+        totals
+            .into_iter()
+            .map(|(d, t)| (d, t.to_f64().unwrap_or_default()))
+            .collect()
+    }
+
+    /// Combined balance on the last simulated day, or 0.0 if nothing was simulated.
+    pub fn ending_balance(&self) -> f64 {
+        self.total_balances().last().map(|b| b.1).unwrap_or(0.0)
+    }
+
+    /// Lowest combined balance reached at any point in the simulation, or 0.0 if nothing was
+    /// simulated.
+    pub fn minimum_balance(&self) -> f64 {
+        self.total_balances()
+            .iter()
+            .map(|b| b.1)
+            .reduce(f64::min)
+            .unwrap_or(0.0)
+    }
+
+    /// First date on which the combined balance drops below zero, if it ever does.
+    pub fn depletion_date(&self) -> Option<chrono::NaiveDate> {
+        self.total_balances()
+            .iter()
+            .find(|b| b.1 < 0.0)
+            .map(|b| b.0)
     }
 }
 
+/// Simulates one account day by day, investing its balance in `portfolio` at the start of
+/// every month. Prints as much as `verbosity` asks for, see [`Verbosity`].
 pub fn run_simulation(
-    mut account: cash::Account,
+    account: cash::Account,
     portfolio: Option<portfolio::Portfolio>,
-    print_results: bool,
-) -> SimulationResult {
-    let rebalance_frequency = Frequency::MonthStart;
+    verbosity: Verbosity,
+) -> Result<SimulationResult> {
+    let portfolios: Vec<scenario::ScenarioPortfolio> = portfolio
+        .into_iter()
+        .map(|p| scenario::ScenarioPortfolio {
+            name: format!("{} Portfolio", account.name),
+            account: account.name.clone(),
+            portfolio: p,
+        })
+        .collect();
+    let portfolios: Vec<&scenario::ScenarioPortfolio> = portfolios.iter().collect();
+    let results = simulate_account(
+        account,
+        &portfolios,
+        &Frequency::MonthStart,
+        &scenario::Step::Daily,
+        &mut rand::thread_rng(),
+        verbosity,
+    )?;
+    if verbosity >= Verbosity::Summary {
+        terminal::print_summary(&results)?;
+    }
+    Ok(results)
+}
 
-    // read config from file account.yaml
+/// Simulates a single account, investing its balance in each of `portfolios` whenever
+/// `rebalance_frequency` matches and recording the balance on every date `step` includes.
+/// Investment income is recorded as an `Interest` payment of each portfolio. Every step is
+/// printed at [`Verbosity::Daily`].
+pub(crate) fn simulate_account(
+    mut account: cash::Account,
+    portfolios: &[&scenario::ScenarioPortfolio],
+    rebalance_frequency: &Frequency,
+    step: &scenario::Step,
+    rng: &mut dyn RngCore,
+    verbosity: Verbosity,
+) -> Result<SimulationResult> {
+    let print_results = verbosity == Verbosity::Daily;
     if print_results {
         println!("--- Beginning Simulation ---");
         println!("Loaded Account: {}\n", account.name);
     }
 
-    let mut results = SimulationResult::new(vec![], vec![]);
+    account.assign_ids();
+    let id = account.id();
+    let mut results = SimulationResult::new();
+    let mut interest_payments: Vec<cash::Payment> = vec![];
 
     let mut d = account.start_date;
 
     while d < account.end_date {
-        let b = account.balance_at(d);
+        let b = account.balance_at(d)?;
 
         // TODO: This attributes the full future month's investment income to the first day of the month. This is not correct.
-        if rebalance_frequency.matches(&d, &Some(account.start_date), &Some(account.end_date))
-            && portfolio.is_some()
-        {
-            let i = account.invest(portfolio.as_ref().unwrap()) * rebalance_frequency.fraction();
-            if print_results {
-                println!("Investment income of {}, on {}", i, d);
+        for portfolio in portfolios {
+            if rebalance_frequency.matches(&d, &Some(account.start_date), &Some(account.end_date))? {
+                let before = account.balance;
+                let i = account.invest_with(&portfolio.portfolio, rng)? * rebalance_frequency.fraction();
+                if print_results {
+                    println!("Investment income of {}, on {}", i, d);
+                }
+                let income = account.balance.checked_add(-before)?;
+                if income.amount() != Decimal::ZERO {
+                    interest_payments.push(interest(&account, portfolio, d, income));
+                }
             }
         }
 
-        if print_results {
-            println!("{}, {} balance, {}", d, account.name, b);
+        if step.includes(&d, &account.start_date) {
+            if print_results {
+                println!("{}, {} balance, {}", d, account.name, b);
+            }
+            results.balances.push(d, &id, &account.name, b, b);
         }
-        results
-            .balances
-            .push(AccountBalance::new(d, account.name.clone(), b));
 
-        d = d.succ_opt().unwrap();
+        d = d.succ_opt().ok_or_else(|| Error::Invalid(format!("{} is out of range", d)))?;
     }
 
+    let mut interest_payments = interest_payments.iter().peekable();
     let mut d = account.start_date;
     while d < account.end_date {
-        let flows = account.flows_at(d);
+        while let Some(p) = interest_payments.next_if(|p| p.date <= d) {
+            results.payments.push(p);
+        }
+        let flows = account.flows_at(d)?;
         for f in &flows {
             if print_results {
-                println!("{}, {}, {}", d, f.cash_flow.name.clone().unwrap(), f.amount);
+                println!(
+                    "{}, {}, {}",
+                    d,
+                    f.cash_flow.name.clone().unwrap_or_default(),
+                    f.amount
+                );
             }
-            results.payments.push(f.clone());
+            results.payments.push(f);
         }
-        d = d.succ_opt().unwrap();
+        d = d.succ_opt().ok_or_else(|| Error::Invalid(format!("{} is out of range", d)))?;
     }
 
     if print_results {
         println!("--- End of Simulation ---");
     }
-    results
+    Ok(results)
+}
+
+// the returns of a portfolio on one date, as a payment of a cash flow named after it
+fn interest(
+    account: &cash::Account,
+    portfolio: &scenario::ScenarioPortfolio,
+    date: chrono::NaiveDate,
+    income: Money,
+) -> cash::Payment {
+    let zero = Money::zero(income.currency());
+    let mut flow = cash::CashFlow::new(Some(portfolio.name.clone()), zero, None, None, None, None);
+    flow.id = Some(format!("{}.{}", account.id(), cash::generated_id(&portfolio.name)));
+    let mut payment = cash::Payment::new(date, income, flow);
+    payment.kind = cash::PaymentKind::Interest;
+    payment.account = Some(account.name.clone());
+    payment.account_id = Some(account.id());
+    payment
 }
 
 #[test]
 fn test() {
     let config = std::fs::read_to_string("./scenarios/examples/default_account.yaml").unwrap();
     let account: cash::Account = serde_yaml::from_str(&config).unwrap();
-    run_simulation(account, None, false);
+    run_simulation(account, None, Verbosity::Quiet).unwrap();
 }
@@ -0,0 +1,403 @@
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::{Decimal, RoundingStrategy};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{
+    InstanceType, Metadata, Schema, SchemaObject, StringValidation, SubschemaValidation,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::str::FromStr;
+
+use crate::{Error, Result};
+
+/// An ISO 4217 currency code such as `USD` or `EUR`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const USD: Currency = Currency(*b"USD");
+
+    pub fn code(&self) -> &str {
+        std::str::from_utf8(&self.0).unwrap()
+    }
+
+    /// Number of decimal places amounts in this currency are rounded to, e.g. 2 for cents.
+    pub fn minor_units(&self) -> u32 {
+        match self.code() {
+            "CLP" | "ISK" | "JPY" | "KRW" | "PYG" | "UGX" | "VND" | "XAF" | "XOF" => 0,
+            "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+            _ => 2,
+        }
+    }
+}
+
+/// Amounts without a currency are in US dollars.
+impl Default for Currency {
+    fn default() -> Currency {
+        Currency::USD
+    }
+}
+
+impl FromStr for Currency {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Currency> {
+        match s.as_bytes() {
+            [a, b, c] if s.bytes().all(|b| b.is_ascii_uppercase()) => Ok(Currency([*a, *b, *c])),
+            _ => Err(Error::Invalid(format!(
+                "`{}` is not a three letter currency code",
+                s
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for Currency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Currency, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl JsonSchema for Currency {
+    fn schema_name() -> String {
+        "Currency".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some("^[A-Z]{3}$".to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+/// How amounts are rounded to the currency's minor unit when a payment is made.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, JsonSchema, Default)]
+pub enum Rounding {
+    /// Midpoints round away from zero, so 0.125 becomes 0.13.
+    #[default]
+    HalfUp,
+    /// Midpoints round to the nearest even digit, so 0.125 becomes 0.12 and 0.135 becomes
+    /// 0.14. Also known as banker's rounding.
+    Bankers,
+}
+
+impl Rounding {
+    fn strategy(&self) -> RoundingStrategy {
+        match self {
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::Bankers => RoundingStrategy::MidpointNearestEven,
+        }
+    }
+}
+
+/// `Money` is an exact decimal amount in a currency.
+///
+/// The cash ledger (balances, cash flows and payments) is kept in `Money` so that totals add
+/// up to the cent. Investment returns are still drawn in floating point and enter the ledger
+/// through [`Money::from_f64`]; analyses such as Monte Carlo percentiles read amounts back
+/// with [`Money::to_f64`].
+///
+/// In scenario files an amount is either a number, in the default currency, or a string
+/// with a currency code such as `"1200.50 EUR"`.
+///
+/// # Example
+///
+/// ```
+/// use budget::sim::money::{Money, Rounding};
+///
+/// let rent: Money = "1200.125 EUR".parse().unwrap();
+/// assert_eq!(rent.round(Rounding::HalfUp).to_string(), "1200.13 EUR");
+/// assert_eq!(rent.round(Rounding::Bankers).to_string(), "1200.12 EUR");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Money {
+    amount: Decimal,
+    currency: Currency,
+}
+
+impl Money {
+    pub fn new(amount: Decimal, currency: Currency) -> Money {
+        Money { amount, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Money {
+        Money::new(Decimal::ZERO, currency)
+    }
+
+    /// Converts a floating point result, such as investment income, rounding it to the
+    /// currency's minor unit. Values that are not finite or too large are an error.
+    pub fn from_f64(value: f64, currency: Currency, rounding: Rounding) -> Result<Money> {
+        Ok(Money::new(decimal(value)?, currency).round(rounding))
+    }
+
+    pub fn amount(&self) -> Decimal {
+        self.amount
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.amount.to_f64().unwrap_or_default()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.amount.is_sign_negative() && !self.amount.is_zero()
+    }
+
+    /// The amount rounded to the currency's minor unit.
+    pub fn round(&self, rounding: Rounding) -> Money {
+        let amount = self
+            .amount
+            .round_dp_with_strategy(self.currency.minor_units(), rounding.strategy());
+        Money::new(amount, self.currency)
+    }
+
+    /// The amount multiplied by a rate such as a tax rate, without rounding.
+    pub fn scale(&self, factor: f64) -> Result<Money> {
+        Ok(Money::new(self.checked_mul(decimal(factor)?)?, self.currency))
+    }
+
+    /// The amount in another currency, given how many units of it one unit of this currency
+    /// buys.
+    pub fn convert(&self, to: Currency, rate: f64, rounding: Rounding) -> Result<Money> {
+        Ok(Money::new(self.checked_mul(decimal(rate)?)?, to).round(rounding))
+    }
+
+    fn checked_mul(&self, factor: Decimal) -> Result<Decimal> {
+        self.amount.checked_mul(factor).ok_or_else(|| {
+            Error::Invalid(format!("{} times {} is out of range", self, factor))
+        })
+    }
+
+    /// Adds two amounts, failing if they are in different currencies.
+    pub fn checked_add(&self, other: Money) -> Result<Money> {
+        if self.currency != other.currency {
+            return Err(Error::Invalid(format!(
+                "Cannot add {} to {}",
+                other.currency, self.currency
+            )));
+        }
+        let amount = self.amount.checked_add(other.amount).ok_or_else(|| {
+            Error::Invalid(format!("{} plus {} is out of range", self, other))
+        })?;
+        Ok(Money::new(amount, self.currency))
+    }
+}
+
+fn decimal(value: f64) -> Result<Decimal> {
+    Decimal::from_f64(value)
+        .ok_or_else(|| Error::Invalid(format!("{} is not an amount", value)))
+}
+
+/// An amount in the default currency with the shortest decimal representation of `value`,
+/// so `0.1` is exactly ten cents. Values that are not finite or too large are an error.
+impl TryFrom<f64> for Money {
+    type Error = Error;
+
+    fn try_from(value: f64) -> Result<Money> {
+        let amount = match Decimal::from_str(&value.to_string()) {
+            Ok(amount) => amount,
+            Err(_) => decimal(value)?,
+        };
+        Ok(Money::new(amount, Currency::default()))
+    }
+}
+
+impl FromStr for Money {
+    type Err = Error;
+
+    /// Parses `"1200.50"` in the default currency or `"1200.50 EUR"`.
+    fn from_str(s: &str) -> Result<Money> {
+        let mut parts = s.split_whitespace();
+        let (amount, currency) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(amount), None, None) => (amount, Currency::default()),
+            (Some(amount), Some(currency), None) => (amount, currency.parse()?),
+            _ => {
+                return Err(Error::Invalid(format!(
+                    "`{}` is not an amount such as `1200.50 EUR`",
+                    s
+                )))
+            }
+        };
+        let amount = Decimal::from_str(amount)
+            .map_err(|e| Error::Invalid(format!("`{}` is not an amount: {}", s, e)))?;
+        Ok(Money::new(amount, currency))
+    }
+}
+
+impl std::fmt::Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let places = self.currency.minor_units().max(self.amount.scale()) as usize;
+        write!(f, "{:.*} {}", places, self.amount, self.currency)
+    }
+}
+
+impl std::ops::Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money::new(-self.amount, self.currency)
+    }
+}
+
+// Amounts are written as decimal strings so they survive JSON exactly, e.g. `"1200.1"` in the
+// default currency and `"1200.1 EUR"` in any other. Both are read back as they were written,
+// and plain numbers are still accepted.
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if self.currency == Currency::default() {
+            serializer.serialize_str(&self.amount.normalize().to_string())
+        } else {
+            serializer.serialize_str(&format!("{} {}", self.amount.normalize(), self.currency))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Money, D::Error> {
+        struct MoneyVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for MoneyVisitor {
+            type Value = Money;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a number or an amount with a currency such as `1200.50 EUR`")
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> std::result::Result<Money, E> {
+                Ok(Money::new(Decimal::from(v), Currency::default()))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> std::result::Result<Money, E> {
+                Ok(Money::new(Decimal::from(v), Currency::default()))
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> std::result::Result<Money, E> {
+                Money::try_from(v).map_err(E::custom)
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> std::result::Result<Money, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
+
+impl JsonSchema for Money {
+    fn schema_name() -> String {
+        "Money".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let number = SchemaObject {
+            instance_type: Some(InstanceType::Number.into()),
+            ..Default::default()
+        };
+        let string = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(r"^-?[0-9]+(\.[0-9]+)?( [A-Z]{3})?$".to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "An amount in the default currency, or a string such as `1200.50 EUR`"
+                        .to_string(),
+                ),
+                ..Default::default()
+            })),
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![number.into(), string.into()]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod money_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_serialize() {
+        let usd: Money = serde_yaml::from_str("1200.1").unwrap();
+        assert_eq!(usd, Money::new(Decimal::new(12001, 1), Currency::USD));
+        assert_eq!(serde_json::to_string(&usd).unwrap(), "\"1200.1\"");
+        assert_eq!(serde_json::from_str::<Money>("\"1200.1\"").unwrap(), usd);
+
+        // more digits than a double holds
+        let exact = Money::new(Decimal::from_str("12345678901234.56789").unwrap(), Currency::USD);
+        let json = serde_json::to_string(&exact).unwrap();
+        assert_eq!(json, "\"12345678901234.56789\"");
+        assert_eq!(serde_json::from_str::<Money>(&json).unwrap(), exact);
+
+        let eur: Money = serde_yaml::from_str("\"-20.50 EUR\"").unwrap();
+        assert_eq!(eur.currency().code(), "EUR");
+        assert!(eur.is_negative());
+        assert_eq!(serde_json::to_string(&eur).unwrap(), "\"-20.5 EUR\"");
+
+        assert!(serde_yaml::from_str::<Money>("\"20 euros\"").is_err());
+        assert!("20 EUR extra".parse::<Money>().is_err());
+
+        // out of range rather than zero
+        assert!(serde_yaml::from_str::<Money>("1e300").is_err());
+        assert!(serde_yaml::from_str::<Money>(".nan").is_err());
+        assert!(Money::from_f64(f64::INFINITY, Currency::USD, Rounding::HalfUp).is_err());
+    }
+
+    #[test]
+    fn test_sums_are_exact() {
+        let dime = Money::try_from(0.1).unwrap();
+        let mut total = Money::zero(Currency::USD);
+        for _ in 0..10 {
+            total = total.checked_add(dime).unwrap();
+        }
+        assert_eq!(total.amount(), Decimal::ONE);
+    }
+
+    #[test]
+    fn test_rounding() {
+        let jpy: Money = "1000.5 JPY".parse().unwrap();
+        assert_eq!(jpy.round(Rounding::HalfUp).to_string(), "1001 JPY");
+        assert_eq!(jpy.round(Rounding::Bankers).to_string(), "1000 JPY");
+
+        let tax = Money::try_from(100.25).unwrap().scale(-0.3).unwrap().round(Rounding::HalfUp);
+        assert_eq!(tax, Money::try_from(-30.08).unwrap());
+
+        let usd = Money::try_from(1.0).unwrap();
+        assert!(usd.checked_add("1 EUR".parse().unwrap()).is_err());
+
+        let most = Money::new(Decimal::MAX, Currency::default());
+        assert!(most.checked_add(usd).is_err());
+        assert!(most.scale(2.0).is_err());
+        assert!(most.convert("EUR".parse().unwrap(), 2.0, Rounding::HalfUp).is_err());
+    }
+}
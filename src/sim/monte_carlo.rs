@@ -0,0 +1,172 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::scenario::Scenario;
+use super::terminal::Verbosity;
+use crate::Result;
+
+/// Monte Carlo settings of a `Scenario`: how many independent paths of investment returns to
+/// draw, and which percentiles of the combined balance to report.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct MonteCarlo {
+    pub samples: usize,
+    #[serde(default = "default_percentiles")]
+    pub percentiles: Vec<f64>,
+}
+
+fn default_percentiles() -> Vec<f64> {
+    vec![5.0, 25.0, 50.0, 75.0, 95.0]
+}
+
+impl MonteCarlo {
+    pub fn new(samples: usize) -> MonteCarlo {
+        MonteCarlo {
+            samples,
+            percentiles: default_percentiles(),
+        }
+    }
+}
+
+/// The combined balance at each requested percentile on one date.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PercentileBand {
+    pub date: chrono::NaiveDate,
+    pub balances: Vec<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MonteCarloResult {
+    pub samples: usize,
+    pub percentiles: Vec<f64>,
+    pub bands: Vec<PercentileBand>,
+    pub ending_balances: Vec<f64>,
+    pub minimum_balances: Vec<f64>,
+}
+
+impl MonteCarloResult {
+    /// Share of paths whose combined balance never drops below zero.
+    pub fn success_rate(&self) -> f64 {
+        if self.minimum_balances.is_empty() {
+            return 0.0;
+        }
+        self.minimum_balances.iter().filter(|b| **b >= 0.0).count() as f64
+            / self.minimum_balances.len() as f64
+    }
+}
+
+/// Runs `settings.samples` paths of the scenario in parallel.
+///
+/// Path `i` is seeded with `seed + i` when the scenario has a seed, so results are
+/// reproducible regardless of how rayon schedules the work.
+pub fn run_monte_carlo(scenario: &Scenario, settings: &MonteCarlo) -> Result<MonteCarloResult> {
+    let base_seed = scenario.seed.unwrap_or_else(rand::random);
+    let paths: Vec<Vec<(chrono::NaiveDate, f64)>> = (0..settings.samples)
+        .into_par_iter()
+        .map(|i| {
+            let mut rng = StdRng::seed_from_u64(base_seed.wrapping_add(i as u64));
+            Ok(scenario.run_with(&mut rng, Verbosity::Quiet)?.total_balances())
+        })
+        .collect::<Result<_>>()?;
+
+    let mut bands = vec![];
+    if let Some(first) = paths.first() {
+        for (t, (date, _)) in first.iter().enumerate() {
+            let mut values: Vec<f64> = paths.iter().map(|p| p[t].1).collect();
+            values.sort_by(|a, b| a.total_cmp(b));
+            bands.push(PercentileBand {
+                date: *date,
+                balances: settings
+                    .percentiles
+                    .iter()
+                    .map(|q| percentile(&values, *q))
+                    .collect(),
+            });
+        }
+    }
+
+    Ok(MonteCarloResult {
+        samples: settings.samples,
+        percentiles: settings.percentiles.clone(),
+        bands,
+        ending_balances: paths
+            .iter()
+            .map(|p| p.last().map(|b| b.1).unwrap_or(0.0))
+            .collect(),
+        minimum_balances: paths
+            .iter()
+            .map(|p| p.iter().map(|b| b.1).reduce(f64::min).unwrap_or(0.0))
+            .collect(),
+    })
+}
+
+/// Linearly interpolated percentile (0-100) of already sorted values.
+pub fn percentile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (q / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lo = rank.floor() as usize;
+    let hi = rank.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+#[cfg(test)]
+mod monte_carlo_tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let values = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(percentile(&values, 0.0), 1.0);
+        assert_eq!(percentile(&values, 50.0), 3.0);
+        assert_eq!(percentile(&values, 100.0), 5.0);
+        assert_eq!(percentile(&values, 12.5), 1.5);
+    }
+
+    #[test]
+    fn test_run_monte_carlo() {
+        let scenario = Scenario::from_yaml(
+            "
+version: 1
+name: Investing
+start_date: 2020-01-01
+end_date: 2021-01-01
+step: Monthly
+seed: 1
+accounts:
+  - name: Brokerage
+    balance: 1000.0
+    start_date: 2020-01-01
+    end_date: 2021-01-01
+    cash_flows: []
+portfolios:
+  - name: Stocks
+    account: Brokerage
+    assets:
+      - name: Equity
+        mean_return: 0.01
+        std_dev: 0.05
+    weights: [1.0]
+monte_carlo:
+  samples: 50
+",
+        )
+        .unwrap();
+        let settings = scenario.monte_carlo.clone().unwrap();
+        let a = run_monte_carlo(&scenario, &settings).unwrap();
+        let b = run_monte_carlo(&scenario, &settings).unwrap();
+
+        assert_eq!(a.ending_balances.len(), 50);
+        assert_eq!(a.bands.len(), 12);
+        assert_eq!(a.ending_balances, b.ending_balances);
+        assert_eq!(a.success_rate(), 1.0);
+
+        // percentile bands are ordered within each date
+        for band in &a.bands {
+            assert!(band.balances.windows(2).all(|w| w[0] <= w[1]));
+        }
+    }
+}
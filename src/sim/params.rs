@@ -0,0 +1,134 @@
+use serde_json::Value;
+
+use super::scenario::Scenario;
+use super::terminal::Verbosity;
+use super::SimulationResult;
+
+/// `ParameterPath` addresses a single value inside a serialized scenario.
+///
+/// Paths are dot-separated. Object fields are selected by key, and array elements are
+/// selected either by index or by the value of their `name` field, so
+/// `accounts.Checking.cash_flows.Income.amount` and `accounts.0.cash_flows.0.amount` both
+/// address the amount of the first cash flow of the first account when they are named
+/// "Checking" and "Income".
+///
+/// # Example
+///
+/// ```
+/// use budget::sim::params::ParameterPath;
+///
+/// let mut doc = serde_json::json!({"cash_flows": [{"name": "Rent", "amount": -1500.0}]});
+/// let path = ParameterPath::parse("cash_flows.Rent.amount");
+/// path.set(&mut doc, serde_json::json!(-1750.0)).unwrap();
+/// assert_eq!(path.get(&doc), Some(&serde_json::json!(-1750.0)));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterPath {
+    segments: Vec<String>,
+}
+
+impl ParameterPath {
+    pub fn parse(path: &str) -> ParameterPath {
+        ParameterPath {
+            segments: path
+                .split('.')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+
+    pub fn get<'a>(&self, doc: &'a Value) -> Option<&'a Value> {
+        let mut v = doc;
+        for s in &self.segments {
+            v = match v {
+                Value::Object(map) => map.get(s)?,
+                Value::Array(items) => &items[element_index(items, s)?],
+                _ => return None,
+            };
+        }
+        Some(v)
+    }
+
+    pub fn get_mut<'a>(&self, doc: &'a mut Value) -> Option<&'a mut Value> {
+        let mut v = doc;
+        for s in &self.segments {
+            v = match v {
+                Value::Object(map) => map.get_mut(s)?,
+                Value::Array(items) => {
+                    let i = element_index(items, s)?;
+                    &mut items[i]
+                }
+                _ => return None,
+            };
+        }
+        Some(v)
+    }
+
+    /// Replaces the value at this path. Returns an error naming the path if it does not
+    /// resolve to an existing value.
+    pub fn set(&self, doc: &mut Value, value: Value) -> Result<(), String> {
+        match self.get_mut(doc) {
+            Some(v) => {
+                *v = value;
+                Ok(())
+            }
+            None => Err(format!("Parameter path `{}` does not exist", self)),
+        }
+    }
+}
+
+impl std::fmt::Display for ParameterPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.segments.join("."))
+    }
+}
+
+/// Serializes a scenario into the document that parameter paths are resolved against, e.g.
+/// `accounts.Checking.balance`, `portfolios.Retirement.weights.0` or `variables.salary`.
+/// Fields given as expressions keep their source, so changing a variable changes them too.
+pub fn document(scenario: &Scenario) -> crate::Result<Value> {
+    scenario.to_value()
+}
+
+/// Evaluates a document built by [`document`] and runs the scenario without printing.
+pub fn simulate(doc: &Value) -> crate::Result<SimulationResult> {
+    Scenario::from_value(doc.clone())?.run(Verbosity::Quiet)
+}
+
+fn element_index(items: &[Value], segment: &str) -> Option<usize> {
+    if let Ok(i) = segment.parse::<usize>() {
+        return if i < items.len() { Some(i) } else { None };
+    }
+    items
+        .iter()
+        .position(|item| item.get("name").and_then(|n| n.as_str()) == Some(segment))
+}
+
+#[cfg(test)]
+mod params_tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_get_by_name_and_index() {
+        let doc = json!({
+            "cash_flows": [
+                {"name": "Income", "amount": 100.0},
+                {"name": "Rent", "amount": -50.0}
+            ]
+        });
+        let by_name = ParameterPath::parse("cash_flows.Rent.amount");
+        let by_index = ParameterPath::parse("cash_flows.1.amount");
+        assert_eq!(by_name.get(&doc), Some(&json!(-50.0)));
+        assert_eq!(by_index.get(&doc), Some(&json!(-50.0)));
+        assert_eq!(ParameterPath::parse("cash_flows.Car.amount").get(&doc), None);
+    }
+
+    #[test]
+    fn test_set_missing_path() {
+        let mut doc = json!({"balance": 100.0});
+        let path = ParameterPath::parse("missing.field");
+        assert!(path.set(&mut doc, json!(1.0)).is_err());
+    }
+}
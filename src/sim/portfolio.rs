@@ -1,11 +1,15 @@
 use super::cash::Account;
+use super::money::Money;
 use polars::df;
 use polars::prelude::*;
+use rand::{Rng, RngCore};
 use rand_distr::Distribution;
 use rand_distr::Normal;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::{Error, Result};
+
 /// `Asset` represents a financial asset that can be invested in.
 ///
 /// Mean return and standard deviation are used to calculate the return of the asset. These
@@ -63,43 +67,43 @@ impl Portfolio {
         Portfolio { assets, weights }
     }
 
-    fn returns_sample(&self) -> f64 {
+    fn returns_sample(&self) -> Result<f64> {
         let ai = self.assets.iter();
         let wi = self.weights.iter();
         let it = ai.zip(wi);
 
         let mut ret: f64 = 0.0;
         for (a, w) in it {
-            let normal = Normal::new(a.mean_return, a.std_dev).unwrap();
+            let normal = Normal::new(a.mean_return, a.std_dev)
+                .map_err(|e| Error::Invalid(format!("Asset {}: {}", a.name, e)))?;
             let v = normal.sample(&mut rand::thread_rng());
             ret += v * w;
         }
 
-        ret
+        Ok(ret)
     }
 
-    fn returns_vec(&self, dates: &[chrono::NaiveDate], name: &str) -> Series {
+    fn returns_vec(&self, dates: &[chrono::NaiveDate], name: &str) -> Result<Series> {
         let mut returns: Vec<f64> = vec![];
         for _ in dates.iter() {
-            returns.push(self.clone().returns_sample());
+            returns.push(self.returns_sample()?);
         }
 
-        Series::new(name, returns)
+        Ok(Series::new(name, returns))
     }
 
-    pub fn returns_frame(&self, dates: &Vec<chrono::NaiveDate>, num_samples: i64) -> DataFrame {
+    /// A frame with a `dates` column and `num_samples` columns of sampled daily returns.
+    pub fn returns_frame(&self, dates: &Vec<chrono::NaiveDate>, num_samples: i64) -> Result<DataFrame> {
         let mut df = df![
             "dates" => dates,
-        ]
-        .expect("Dataframe creation failed.");
+        ]?;
 
         for n in 0..num_samples {
-            let r = self.returns_vec(dates, &format!("Sample {}", n));
-            let e = format!("Dataframe creation failed on sample number {}.", n);
-            df = df.hstack(&[r]).unwrap_or_else(|_| { panic!("{}", e) });
+            let r = self.returns_vec(dates, &format!("Sample {}", n))?;
+            df = df.hstack(&[r])?;
         }
 
-        df
+        Ok(df)
     }
 }
 
@@ -131,7 +135,7 @@ mod portfolio_tests {
         let end_date = chrono::NaiveDate::from_ymd_opt(2018, 12, 31).unwrap();
         let dates: Vec<chrono::prelude::NaiveDate> = date_sequence(start_date, end_date);
 
-        let df = portfolio.returns_frame(&dates, 1);
+        let df = portfolio.returns_frame(&dates, 1).unwrap();
         assert_eq!(df.width(), 2);
         assert_eq!(df.height(), 365);
         println!("{:?}", df);
@@ -149,7 +153,7 @@ mod portfolio_tests {
         let end_date = chrono::NaiveDate::from_ymd_opt(2018, 12, 31).unwrap();
         let dates: Vec<chrono::prelude::NaiveDate> = date_sequence(start_date, end_date);
 
-        let df = portfolio.returns_frame(&dates, 3);
+        let df = portfolio.returns_frame(&dates, 3).unwrap();
 
         assert_eq!(df.width(), 4);
         assert_eq!(df.height(), 365);
@@ -193,29 +197,36 @@ mod portfolio_tests {
 }
 
 pub trait Invest {
-    fn invest(&mut self, portfolio: &Portfolio) -> f64;
+    fn invest(&mut self, portfolio: &Portfolio) -> Result<f64> {
+        self.invest_with(portfolio, &mut rand::thread_rng())
+    }
 
-    fn invest_asset(&mut self, asset: &Asset, weight: &f64) -> f64;
+    /// Same as `invest`, drawing returns from the given random number generator so that seeded
+    /// simulations are reproducible.
+    fn invest_with(&mut self, portfolio: &Portfolio, rng: &mut dyn RngCore) -> Result<f64>;
+
+    fn invest_asset(&mut self, asset: &Asset, weight: &f64, rng: &mut dyn RngCore) -> f64;
 }
 
 impl Invest for Account {
-    fn invest(&mut self, portfolio: &Portfolio) -> f64 {
+    fn invest_with(&mut self, portfolio: &Portfolio, rng: &mut dyn RngCore) -> Result<f64> {
         let ai = portfolio.assets.iter();
         let wi = portfolio.weights.iter();
         let it = ai.zip(wi);
 
         let mut income: f64 = 0.0;
         for (a, w) in it {
-            income += self.invest_asset(a, w);
+            income += self.invest_asset(a, w, rng);
         }
 
-        self.balance += income;
+        let income_money = Money::from_f64(income, self.balance.currency(), self.rounding)?;
+        self.balance = self.balance.checked_add(income_money)?;
 
-        income
+        Ok(income)
     }
 
-    fn invest_asset(&mut self, asset: &Asset, weight: &f64) -> f64 {
-        (weight * self.balance) * (asset.mean_return + (asset.std_dev * rand::random::<f64>()))
+    fn invest_asset(&mut self, asset: &Asset, weight: &f64, rng: &mut dyn RngCore) -> f64 {
+        (weight * self.balance.to_f64()) * (asset.mean_return + (asset.std_dev * rng.gen::<f64>()))
     }
 }
 
@@ -227,22 +238,22 @@ mod invest_tests {
     fn test_invest() {
         let mut account = Account::new(
             "test".to_string(),
-            1000.0,
+            Money::try_from(1000.0).unwrap(),
             vec![],
             chrono::NaiveDate::from_ymd_opt(2018, 1, 1).unwrap(),
             chrono::NaiveDate::from_ymd_opt(2018, 12, 31).unwrap(),
         );
         let portfolio =
             Portfolio::new(vec![Asset::new("Asset 1".to_string(), 0.1, 0.0)], vec![1.0]);
-        account.invest(&portfolio);
-        assert_eq!(account.balance, 1100.0);
+        account.invest(&portfolio).unwrap();
+        assert_eq!(account.balance, Money::try_from(1100.0).unwrap());
     }
 
     #[test]
     fn test_invest_two_assets() {
         let mut account = Account::new(
             "test".to_string(),
-            1000.0,
+            Money::try_from(1000.0).unwrap(),
             vec![],
             chrono::NaiveDate::from_ymd_opt(2018, 1, 1).unwrap(),
             chrono::NaiveDate::from_ymd_opt(2018, 12, 31).unwrap(),
@@ -254,7 +265,30 @@ mod invest_tests {
             ],
             vec![0.5, 0.5],
         );
-        account.invest(&portfolio);
-        assert_eq!(account.balance, 1150.0);
+        account.invest(&portfolio).unwrap();
+        assert_eq!(account.balance, Money::try_from(1150.0).unwrap());
+    }
+
+    #[test]
+    fn test_invest_with_seed() {
+        use rand::SeedableRng;
+
+        let account = Account::new(
+            "test".to_string(),
+            Money::try_from(1000.0).unwrap(),
+            vec![],
+            chrono::NaiveDate::from_ymd_opt(2018, 1, 1).unwrap(),
+            chrono::NaiveDate::from_ymd_opt(2018, 12, 31).unwrap(),
+        );
+        let portfolio =
+            Portfolio::new(vec![Asset::new("Asset 1".to_string(), 0.1, 0.2)], vec![1.0]);
+
+        let mut a = account.clone();
+        let mut b = account.clone();
+        a.invest_with(&portfolio, &mut rand::rngs::StdRng::seed_from_u64(7))
+            .unwrap();
+        b.invest_with(&portfolio, &mut rand::rngs::StdRng::seed_from_u64(7))
+            .unwrap();
+        assert_eq!(a.balance, b.balance);
     }
 }
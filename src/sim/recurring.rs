@@ -0,0 +1,444 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::cash::{Account, CashFlow, Frequency};
+use super::ledger::Transaction;
+use super::money::{Money, Rounding};
+use crate::Result;
+
+/// How often a detected cash flow repeats.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub enum Periodicity {
+    Frequency(Frequency),
+    /// Every so many days, such as every ten days, which no [`Frequency`] expresses. Draft
+    /// accounts write these as their monthly equivalent.
+    Days(u32),
+}
+
+impl Periodicity {
+    /// Average length of one period in days.
+    pub fn days(&self) -> f64 {
+        match self {
+            Periodicity::Frequency(f) => 365.25 * f.fraction(),
+            Periodicity::Days(d) => *d as f64,
+        }
+    }
+}
+
+impl std::fmt::Display for Periodicity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Periodicity::Frequency(Frequency::MonthStart) => write!(f, "monthly"),
+            Periodicity::Frequency(Frequency::MonthEnd) => write!(f, "monthly at month end"),
+            Periodicity::Frequency(Frequency::SemiMonthly) => write!(f, "twice a month"),
+            Periodicity::Frequency(Frequency::Annually) => write!(f, "yearly"),
+            Periodicity::Frequency(Frequency::Once) => write!(f, "once"),
+            Periodicity::Frequency(Frequency::Weekly) => write!(f, "weekly"),
+            Periodicity::Frequency(Frequency::BiWeekly) => write!(f, "every two weeks"),
+            Periodicity::Days(d) => write!(f, "every {} days", d),
+        }
+    }
+}
+
+/// Settings of [`detect`].
+#[derive(Debug, Clone)]
+pub struct Detection {
+    /// Transactions to the same payee are the same cash flow when their amounts are within
+    /// this fraction of each other.
+    pub amount_tolerance: f64,
+    pub min_occurrences: usize,
+    pub min_confidence: f64,
+}
+
+impl Default for Detection {
+    fn default() -> Detection {
+        Detection {
+            amount_tolerance: 0.1,
+            min_occurrences: 3,
+            min_confidence: 0.5,
+        }
+    }
+}
+
+/// A cash flow found in a transaction history.
+#[derive(Serialize, Debug, Clone)]
+pub struct Recurrence {
+    pub payee: String,
+    pub periodicity: Periodicity,
+    /// The median amount.
+    pub amount: Money,
+    pub minimum: Money,
+    pub maximum: Money,
+    pub occurrences: usize,
+    pub first_date: NaiveDate,
+    pub last_date: NaiveDate,
+    /// Whether the last payment is recent enough that the cash flow is still going.
+    pub active: bool,
+    /// Between 0 and 1, from how regular the dates are, how stable the amount is and how
+    /// often it was seen.
+    pub confidence: f64,
+}
+
+impl Recurrence {
+    /// The suggested cash flow. Recurrences in days become a monthly flow of the same total.
+    pub fn cash_flow(&self, rounding: Rounding) -> Result<CashFlow> {
+        let (frequency, amount) = match &self.periodicity {
+            Periodicity::Frequency(f) => (f.clone(), self.amount),
+            Periodicity::Days(_) => (
+                Frequency::MonthStart,
+                self.amount
+                    .scale(365.25 / 12.0 / self.periodicity.days())?
+                    .round(rounding),
+            ),
+        };
+        // annual and weekly flows recur from their start date
+        let start_date = match frequency.needs_start_date() {
+            true => Some(self.first_date),
+            false => None,
+        };
+        Ok(CashFlow::new(
+            Some(self.payee.clone()),
+            amount,
+            Some(frequency),
+            start_date,
+            None,
+            None,
+        ))
+    }
+}
+
+/// Finds the cash flows that repeat in `transactions`, most confident first.
+///
+/// Transactions are grouped by payee, ignoring case, punctuation and words with digits such
+/// as store numbers, then by amount within `settings.amount_tolerance`. The typical gap
+/// between the dates of each group gives its periodicity.
+pub fn detect(transactions: &[Transaction], settings: &Detection) -> Vec<Recurrence> {
+    let end_of_history = match transactions.iter().map(|t| t.date).max() {
+        Some(d) => d,
+        None => return vec![],
+    };
+
+    let mut by_payee: BTreeMap<(String, bool), Vec<&Transaction>> = BTreeMap::new();
+    for t in transactions {
+        by_payee
+            .entry((normalize_payee(&t.payee), t.amount.is_negative()))
+            .or_default()
+            .push(t);
+    }
+
+    let mut recurrences = vec![];
+    for group in by_payee.into_values() {
+        for cluster in amount_clusters(group, settings.amount_tolerance) {
+            if cluster.len() < settings.min_occurrences {
+                continue;
+            }
+            if let Some(r) = recurrence(cluster, end_of_history) {
+                if r.confidence >= settings.min_confidence {
+                    recurrences.push(r);
+                }
+            }
+        }
+    }
+    recurrences.sort_by(|a, b| {
+        b.confidence
+            .total_cmp(&a.confidence)
+            .then_with(|| a.payee.cmp(&b.payee))
+    });
+    recurrences
+}
+
+/// Lowercase words of a payee without digits, so `POS 1234 GROCERY MART #88` and
+/// `GROCERY MART #91` are the same payee.
+pub fn normalize_payee(payee: &str) -> String {
+    let words: Vec<String> = payee
+        .split_whitespace()
+        .filter(|w| !w.chars().any(|c| c.is_ascii_digit()))
+        .map(|w| {
+            w.chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .filter(|w| !w.is_empty())
+        .collect();
+    if words.is_empty() {
+        payee.trim().to_lowercase()
+    } else {
+        words.join(" ")
+    }
+}
+
+// Splits transactions into runs of amounts within `tolerance` of the smallest in the run.
+fn amount_clusters(mut group: Vec<&Transaction>, tolerance: f64) -> Vec<Vec<&Transaction>> {
+    group.sort_by_key(|t| t.amount.amount().abs());
+    let mut clusters: Vec<Vec<&Transaction>> = vec![];
+    for t in group {
+        let amount = t.amount.to_f64().abs();
+        match clusters.last_mut() {
+            Some(c) if amount - c[0].amount.to_f64().abs() <= tolerance * amount => c.push(t),
+            _ => clusters.push(vec![t]),
+        }
+    }
+    clusters
+}
+
+fn recurrence(mut cluster: Vec<&Transaction>, end_of_history: NaiveDate) -> Option<Recurrence> {
+    if cluster.len() < 2 {
+        return None;
+    }
+    cluster.sort_by_key(|t| t.date);
+    let gaps: Vec<i64> = cluster
+        .windows(2)
+        .map(|w| (w[1].date - w[0].date).num_days())
+        .collect();
+    let mut sorted_gaps = gaps.clone();
+    sorted_gaps.sort();
+    let median_gap = sorted_gaps[sorted_gaps.len() / 2];
+
+    let periodicity = match median_gap {
+        0 => return None,
+        6..=8 => Periodicity::Frequency(Frequency::Weekly),
+        // biweekly pay is 14 days apart, semi-monthly pay 13 to 17
+        12..=18 if gaps.iter().filter(|g| **g == 14).count() * 4 >= gaps.len() * 3 => Periodicity::Frequency(Frequency::BiWeekly),
+        12..=18 => Periodicity::Frequency(Frequency::SemiMonthly),
+        26..=35 => {
+            let mut days: Vec<u32> = cluster.iter().map(|t| t.date.day()).collect();
+            days.sort();
+            if days[days.len() / 2] >= 25 {
+                Periodicity::Frequency(Frequency::MonthEnd)
+            } else {
+                Periodicity::Frequency(Frequency::MonthStart)
+            }
+        }
+        330..=400 => Periodicity::Frequency(Frequency::Annually),
+        d => Periodicity::Days(d as u32),
+    };
+
+    let period = periodicity.days();
+    let tolerance = (period * 0.1).max(2.0);
+    let regularity = gaps
+        .iter()
+        .filter(|g| (**g as f64 - period).abs() <= tolerance)
+        .count() as f64
+        / gaps.len() as f64;
+
+    let amounts: Vec<f64> = cluster.iter().map(|t| t.amount.to_f64()).collect();
+    let mean = amounts.iter().sum::<f64>() / amounts.len() as f64;
+    let variance =
+        amounts.iter().map(|a| (a - mean).powi(2)).sum::<f64>() / amounts.len() as f64;
+    let stability = (1.0 - variance.sqrt() / mean.abs()).clamp(0.0, 1.0);
+
+    let n = cluster.len() as f64;
+    let confidence = (regularity * stability * n / (n + 1.0) * 100.0).round() / 100.0;
+
+    let mut by_amount: Vec<Decimal> = cluster.iter().map(|t| t.amount.amount()).collect();
+    by_amount.sort();
+    let currency = cluster[0].amount.currency();
+    let median = Money::new(by_amount[by_amount.len() / 2], currency);
+    let (minimum, maximum) = (
+        Money::new(by_amount[0], currency),
+        Money::new(by_amount[by_amount.len() - 1], currency),
+    );
+
+    let mut names: HashMap<&str, usize> = HashMap::new();
+    for t in &cluster {
+        *names.entry(t.payee.trim()).or_insert(0) += 1;
+    }
+    let payee = names
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+        .map(|(name, _)| name.to_string())
+        .unwrap_or_default();
+
+    let last_date = cluster[cluster.len() - 1].date;
+    Some(Recurrence {
+        payee,
+        periodicity,
+        amount: median,
+        minimum,
+        maximum,
+        occurrences: cluster.len(),
+        first_date: cluster[0].date,
+        last_date,
+        active: ((end_of_history - last_date).num_days() as f64) <= period * 1.5 + tolerance,
+        confidence,
+    })
+}
+
+/// A draft account with a cash flow for every active recurrence, starting the day after
+/// `end_of_history` and running for a year. The balance is left at zero to be filled in.
+pub fn draft_account(
+    name: &str,
+    recurrences: &[Recurrence],
+    end_of_history: NaiveDate,
+) -> Result<Account> {
+    let start_date = end_of_history + Duration::days(1);
+    let currency = recurrences
+        .first()
+        .map(|r| r.amount.currency())
+        .unwrap_or_default();
+    let mut account = Account::new(
+        name.to_string(),
+        Money::zero(currency),
+        vec![],
+        start_date,
+        start_date.with_year(start_date.year() + 1).unwrap_or(start_date + Duration::days(365)),
+    );
+    let mut names = HashSet::new();
+    for r in recurrences.iter().filter(|r| r.active) {
+        let mut cash_flow = r.cash_flow(account.rounding)?;
+        if !names.insert(r.payee.clone()) {
+            cash_flow.set_name(format!("{} {}", r.payee, r.amount));
+        }
+        account.add_cash_flow(cash_flow);
+    }
+    Ok(account)
+}
+
+/// The draft account as YAML, with each cash flow preceded by a comment giving its
+/// confidence and the payments it was detected from.
+pub fn draft_yaml(account: &Account, recurrences: &[Recurrence]) -> Result<String> {
+    let mut header = account.clone();
+    header.cash_flows = vec![];
+    let mut flows = String::new();
+    for (cash_flow, r) in account
+        .cash_flows
+        .iter()
+        .zip(recurrences.iter().filter(|r| r.active))
+    {
+        flows.push_str(&format!(
+            "# confidence {:.2}: {} payments {} from {} to {}, {} to {}",
+            r.confidence,
+            r.occurrences,
+            r.periodicity,
+            r.first_date,
+            r.last_date,
+            r.minimum,
+            r.maximum
+        ));
+        if let Periodicity::Days(_) = r.periodicity {
+            flows.push_str(", written as the monthly equivalent");
+        }
+        flows.push('\n');
+        flows.push_str(&serde_yaml::to_string(&vec![cash_flow])?);
+    }
+    let yaml = serde_yaml::to_string(&header)?;
+    Ok(format!(
+        "# Draft detected from past transactions: review the cash flows and set the balance\n{}",
+        yaml.replace("cash_flows: []\n", &format!("cash_flows:\n{}", flows))
+    ))
+}
+
+#[cfg(test)]
+mod recurring_tests {
+    use super::*;
+    use crate::sim::import::{import_file, CsvMapping};
+    use crate::sim::money::Currency;
+    use std::path::Path;
+
+    fn transaction(date: NaiveDate, amount: f64, payee: &str) -> Transaction {
+        Transaction {
+            id: String::new(),
+            date,
+            amount: Money::try_from(amount).unwrap(),
+            payee: payee.to_string(),
+            memo: None,
+            category: None,
+        }
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_normalize_payee() {
+        assert_eq!(normalize_payee("POS 1234 GROCERY MART #88"), "pos grocery mart");
+        assert_eq!(normalize_payee("Grocery Mart #91"), "grocery mart");
+        assert_eq!(normalize_payee("7-Eleven"), "7-eleven");
+    }
+
+    #[test]
+    fn test_detect() {
+        let mut transactions = vec![];
+        for m in 1..=6 {
+            transactions.push(transaction(date(2020, m, 1), -1500.0, "Rent"));
+            transactions.push(transaction(date(2020, m, 2), -9.99, "Music"));
+        }
+        for w in 0..12 {
+            let day = date(2020, 1, 3) + Duration::days(14 * w);
+            transactions.push(transaction(day, 2000.0, "Payroll"));
+        }
+        for (i, m) in [1, 2, 5].iter().enumerate() {
+            transactions.push(transaction(date(2020, *m, 10 + i as u32), -30.0, "Cafe"));
+        }
+        transactions.push(transaction(date(2020, 3, 1), -9.99, "Music"));
+        transactions.push(transaction(date(2020, 4, 1), -250.0, "Rent"));
+
+        let found = detect(&transactions, &Detection::default());
+        let find = |payee: &str| found.iter().find(|r| r.payee == payee).unwrap();
+        let rent = find("Rent");
+        assert_eq!(rent.periodicity, Periodicity::Frequency(Frequency::MonthStart));
+        assert_eq!(rent.occurrences, 6);
+        assert_eq!(rent.confidence, 0.86);
+        assert_eq!(
+            find("Payroll").periodicity,
+            Periodicity::Frequency(Frequency::BiWeekly)
+        );
+        assert!(found.iter().all(|r| r.payee != "Cafe"));
+
+        let account = draft_account("Checking", &found, date(2020, 6, 30)).unwrap();
+        let payroll = account
+            .cash_flows
+            .iter()
+            .find(|c| c.name.as_deref() == Some("Payroll"))
+            .unwrap();
+        assert_eq!(payroll.frequency, Frequency::BiWeekly);
+        assert_eq!(payroll.amount, Money::try_from(2000.0).unwrap());
+        assert_eq!(payroll.start_date, Some(date(2020, 1, 3)));
+    }
+
+    #[test]
+    fn test_example() {
+        let dir = Path::new("./scenarios/examples/bank");
+        let mapping: CsvMapping = serde_yaml::from_str(
+            &std::fs::read_to_string(dir.join("checking_mapping.yaml")).unwrap(),
+        )
+        .unwrap();
+        let transactions =
+            import_file(&dir.join("history.csv"), None, Some(&mapping), Currency::USD).unwrap();
+        let found = detect(&transactions, &Detection::default());
+        let periodicity = |payee: &str| {
+            found
+                .iter()
+                .find(|r| r.payee.starts_with(payee))
+                .map(|r| (r.periodicity.clone(), r.active))
+        };
+        assert_eq!(
+            periodicity("CITY APARTMENTS"),
+            Some((Periodicity::Frequency(Frequency::MonthStart), true))
+        );
+        assert_eq!(
+            periodicity("ACME CORP"),
+            Some((Periodicity::Frequency(Frequency::SemiMonthly), true))
+        );
+        assert_eq!(
+            periodicity("GROCERY MART"),
+            Some((Periodicity::Frequency(Frequency::Weekly), true))
+        );
+        assert_eq!(
+            periodicity("IRON GYM"),
+            Some((Periodicity::Frequency(Frequency::MonthStart), false))
+        );
+        assert_eq!(periodicity("CAR INSURANCE"), None);
+
+        let account = draft_account("history", &found, date(2020, 12, 31)).unwrap();
+        let yaml = draft_yaml(&account, &found).unwrap();
+        assert!(yaml.contains("# confidence"));
+        let parsed: Account = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed.cash_flows.len(), account.cash_flows.len());
+        assert!(parsed.check().is_ok());
+    }
+}
@@ -0,0 +1,240 @@
+use chrono::NaiveDate;
+use rand::rngs::StdRng;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::money::Money;
+use super::scenario::{Scenario, Step};
+use super::terminal::Verbosity;
+use crate::{Error, Result};
+
+/// `Reforecast` re-anchors a scenario on the balances actually observed on a date.
+///
+/// The scenario is simulated from the day after `as_of`, with each listed account starting
+/// from its observed balance and every other account from its projected one. Cash flows are
+/// kept as they are, so only their payments after `as_of` count.
+///
+/// # Example
+///
+/// ```yaml
+/// reforecast:
+///   as_of: 2020-03-31
+///   balances:
+///     Checking: 5234.10
+/// ```
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct Reforecast {
+    /// The date the balances were observed on, at the end of the day.
+    pub as_of: NaiveDate,
+    /// Observed balance of each account, by name.
+    #[serde(default)]
+    pub balances: BTreeMap<String, Money>,
+}
+
+/// The gap between an account's projected and observed balance when a scenario is
+/// re-anchored.
+#[derive(Serialize, Clone, Debug)]
+pub struct Reconciliation {
+    pub date: NaiveDate,
+    pub account_name: String,
+    pub projected: Money,
+    pub actual: Money,
+    /// Actual minus projected.
+    pub difference: Money,
+}
+
+/// The scenario re-anchored on the observed balances, without its `reforecast`, and a
+/// reconciliation entry for every observed balance.
+///
+/// The projection is the scenario as written, simulated through `as_of` with the same
+/// random draws a full run would start with.
+pub fn anchor(
+    scenario: &Scenario,
+    reforecast: &Reforecast,
+    rng: &mut StdRng,
+) -> Result<(Scenario, Vec<Reconciliation>)> {
+    let as_of = reforecast.as_of;
+    let start_date = as_of
+        .succ_opt()
+        .ok_or_else(|| Error::Invalid(format!("{} is out of range", as_of)))?;
+
+    let mut projection = scenario.clone();
+    projection.reforecast = None;
+    projection.monte_carlo = None;
+    projection.step = Step::Daily;
+    projection.end_date = start_date.min(scenario.end_date);
+    let projected = projection.run_with(rng, Verbosity::Quiet)?;
+
+    let mut anchored = scenario.clone();
+    anchored.reforecast = None;
+    anchored.start_date = start_date;
+    let mut reconciliations = vec![];
+    for account in &mut anchored.accounts {
+        // the balance at the end of `as_of`, or the opening balance of accounts starting later
+        let projected_balance = projected
+            .balances
+            .iter()
+            .rev()
+            .find(|b| b.account.name == account.name && b.date <= as_of)
+            .map(|b| b.balance)
+            .unwrap_or(account.balance);
+        let balance = match reforecast.balances.get(&account.name) {
+            Some(actual) => {
+                if actual.currency() != account.balance.currency() {
+                    return Err(Error::Invalid(format!(
+                        "The actual balance of {} is in {} but the account is in {}",
+                        account.name,
+                        actual.currency(),
+                        account.balance.currency()
+                    )));
+                }
+                reconciliations.push(Reconciliation {
+                    date: as_of,
+                    account_name: account.name.clone(),
+                    projected: projected_balance,
+                    actual: *actual,
+                    difference: actual.checked_add(-projected_balance)?,
+                });
+                *actual
+            }
+            None => projected_balance,
+        };
+        if account.start_date <= as_of {
+            account.balance = balance;
+            account.start_date = start_date;
+        }
+    }
+    if let Some(name) = reforecast
+        .balances
+        .keys()
+        .find(|name| !anchored.accounts.iter().any(|a| a.name == **name))
+    {
+        return Err(Error::Invalid(format!(
+            "No account named {} to reforecast",
+            name
+        )));
+    }
+    Ok((anchored, reconciliations))
+}
+
+#[cfg(test)]
+mod reforecast_tests {
+    use super::*;
+    use crate::sim::ledger::Ledger;
+
+    const SCENARIO: &str = "
+version: 1
+name: Plan
+start_date: 2020-01-01
+end_date: 2020-07-01
+step: Monthly
+seed: 1
+accounts:
+  - name: Checking
+    balance: 1000.0
+    start_date: 2020-01-01
+    end_date: 2020-07-01
+    cash_flows:
+      - name: Pay
+        amount: 3000.0
+        frequency: MonthStart
+        start_date: null
+        end_date: null
+        tax_rate: 0.0
+      - name: Rent
+        amount: -2000.0
+        frequency: MonthEnd
+        start_date: null
+        end_date: null
+        tax_rate: 0.0
+  - name: Savings
+    balance: 500.0
+    start_date: 2020-01-01
+    end_date: 2020-07-01
+    cash_flows: []
+";
+
+    fn date(m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2020, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_reforecast() {
+        let yaml = format!(
+            "{}reforecast:\n  as_of: 2020-03-31\n  balances:\n    Checking: 3500.0\n",
+            SCENARIO
+        );
+        let scenario = Scenario::from_yaml(&yaml).unwrap();
+        let results = scenario.run(Verbosity::Quiet).unwrap();
+
+        // 1000 + 3 * 3000 - 3 * 2000 projected, 3500 observed
+        let r = &results.reconciliations;
+        assert_eq!(r.len(), 1);
+        assert_eq!(r[0].projected, Money::try_from(4000.0).unwrap());
+        assert_eq!(r[0].difference, Money::try_from(-500.0).unwrap());
+
+        let first = results.balances.iter().map(|b| b.date).min().unwrap();
+        assert_eq!(first, date(4, 1));
+        let checking: Vec<f64> = results
+            .balances
+            .iter()
+            .filter(|b| b.account.name == "Checking")
+            .map(|b| b.balance.to_f64())
+            .collect();
+        // the pay on April 1st comes after the observed balance
+        assert_eq!(checking, vec![6500.0, 7500.0, 8500.0]);
+        let savings = results
+            .balances
+            .iter()
+            .find(|b| b.account.name == "Savings");
+        assert_eq!(savings.unwrap().balance, Money::try_from(500.0).unwrap());
+    }
+
+    #[test]
+    fn test_unknown_account() {
+        let yaml = format!(
+            "{}reforecast:\n  as_of: 2020-03-31\n  balances:\n    Brokerage: 1.0\n",
+            SCENARIO
+        );
+        let errors = crate::sim::validate::validate_str(&yaml).err().unwrap();
+        assert_eq!(errors[0].path, "reforecast.balances.Brokerage");
+    }
+
+    #[test]
+    fn test_ledger_balance() {
+        let scenario = Scenario::from_yaml(SCENARIO).unwrap();
+        let account = &scenario.accounts[0];
+        let mut ledger = Ledger::new(account);
+        let mut transactions: Vec<_> = [(1, 2, 3000.0), (1, 31, -2000.0), (2, 1, 2900.0)]
+            .iter()
+            .map(|(m, d, amount)| crate::sim::ledger::Transaction {
+                id: String::new(),
+                date: date(*m, *d),
+                amount: Money::try_from(*amount).unwrap(),
+                payee: "Bank".to_string(),
+                memo: None,
+                category: None,
+            })
+            .collect();
+        crate::sim::ledger::transaction_ids(&mut transactions);
+        ledger.import(transactions).unwrap();
+        assert_eq!(
+            ledger.balance_at(account, date(1, 31)).unwrap(),
+            Money::try_from(2000.0).unwrap()
+        );
+        assert_eq!(
+            ledger.balance_at(account, date(2, 1)).unwrap(),
+            Money::try_from(4900.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_example() {
+        let path = std::path::Path::new("./scenarios/examples/default_scenario_reforecast.yaml");
+        let scenario = crate::sim::validate::validate_file(path).ok().unwrap();
+        let results = scenario.run(Verbosity::Quiet).unwrap();
+        assert_eq!(results.reconciliations[0].difference, Money::try_from(-250.0).unwrap());
+        assert!(results.balances.iter().all(|b| b.date > date(3, 31)));
+    }
+}
@@ -0,0 +1,539 @@
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+use thousands::Separable;
+
+use super::monte_carlo::{percentile, MonteCarloResult};
+use super::rollup::{rollup, RollupKey};
+use super::scenario::Scenario;
+use super::statement::statements;
+use super::variance::Period;
+use super::SimulationResult;
+use crate::Result;
+
+const WIDTH: f64 = 760.0;
+const HEIGHT: f64 = 320.0;
+// room for the axis labels on the left and bottom, and the legend on top
+const LEFT: f64 = 90.0;
+const BOTTOM: f64 = 30.0;
+const TOP: f64 = 30.0;
+const COLORS: [&str; 6] = [
+    "#4472c4", "#ed7d31", "#70ad47", "#ffc000", "#7030a0", "#a5a5a5",
+];
+
+const STYLE: &str = "body{font-family:sans-serif;max-width:820px;margin:2em auto;color:#222}\
+table{border-collapse:collapse;margin:1em 0}th,td{padding:4px 10px;border-bottom:1px solid #ddd}\
+td.n{text-align:right;font-variant-numeric:tabular-nums}.neg{color:#c00000}\
+svg text{font-size:11px;fill:#444}h2{margin-top:2em}";
+
+/// Renders a simulation as a single HTML page: summary metrics per account, a chart of every
+/// account's balance, a fan chart of the Monte Carlo percentiles when given, payment totals
+/// by category and the scenario's assumptions. Charts are inline SVG and the page loads
+/// nothing else, so it can be emailed and opened offline.
+pub fn render(
+    scenario: &Scenario,
+    results: &SimulationResult,
+    monte_carlo: Option<&MonteCarloResult>,
+) -> Result<String> {
+    let mut html = String::new();
+    let title = escape(&scenario.name);
+    html += &format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title>\
+         <style>{}</style></head><body>\n<h1>{}</h1>\n<p>{} to {}</p>\n",
+        title, STYLE, title, scenario.start_date, scenario.end_date
+    );
+
+    html += "<h2>Summary</h2>\n";
+    html += &summary_table(results)?;
+    if let Some(mc) = monte_carlo {
+        let mut ending = mc.ending_balances.clone();
+        ending.sort_by(|a, b| a.total_cmp(b));
+        let percentiles: Vec<String> = mc
+            .percentiles
+            .iter()
+            .map(|q| format!("P{} {}", q, money(percentile(&ending, *q))))
+            .collect();
+        html += &format!(
+            "<p>Over {} Monte Carlo paths the combined ending balance is {}, and {:.1}% of \
+             paths never fall below zero.</p>\n",
+            mc.samples,
+            percentiles.join(", "),
+            mc.success_rate() * 100.0
+        );
+    }
+
+    html += "<h2>Balances</h2>\n";
+    let mut balances: Vec<(String, Vec<(NaiveDate, f64)>)> = vec![];
+    for b in results.balances.iter() {
+        let name = format!("{} ({})", b.account.name, b.balance.currency());
+        match balances.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, points)) => points.push((b.date, b.balance.to_f64())),
+            None => balances.push((name, vec![(b.date, b.balance.to_f64())])),
+        }
+    }
+    html += &line_chart(&balances);
+
+    if let Some(mc) = monte_carlo.filter(|mc| !mc.bands.is_empty()) {
+        html += "<h2>Monte Carlo</h2>\n";
+        html += &fan_chart(mc);
+    }
+
+    html += "<h2>Categories</h2>\n";
+    html += &categories(results)?;
+
+    html += "<h2>Assumptions</h2>\n";
+    html += &assumptions(scenario)?;
+    html += "</body></html>\n";
+    Ok(html)
+}
+
+/// Renders the report into `file`, see [`render`].
+pub fn write_report(
+    scenario: &Scenario,
+    results: &SimulationResult,
+    monte_carlo: Option<&MonteCarloResult>,
+    file: &str,
+) -> Result<()> {
+    std::fs::write(file, render(scenario, results, monte_carlo)?)?;
+    Ok(())
+}
+
+// every account's totals over the whole simulation, from its annual statements
+fn summary_table(results: &SimulationResult) -> Result<String> {
+    let rows = statements(results, Period::Year)?;
+    let mut html = table_head(&[
+        "Account",
+        "Opening",
+        "Closing",
+        "Lowest",
+        "Inflows",
+        "Outflows",
+        "Taxes",
+        "Growth",
+        "Savings Rate",
+    ]);
+    let mut accounts: Vec<&str> = rows.iter().map(|r| r.account_id.as_str()).collect();
+    accounts.dedup();
+    for id in accounts {
+        let rows: Vec<_> = rows.iter().filter(|r| r.account_id == id).collect();
+        let (first, last) = (rows[0], rows[rows.len() - 1]);
+        let total = |f: &dyn Fn(&super::statement::StatementRow) -> f64| -> f64 {
+            rows.iter().map(|r| f(r)).sum()
+        };
+        let lowest = results
+            .balances
+            .iter()
+            .filter(|b| b.account.id == id)
+            .map(|b| b.balance.to_f64())
+            .fold(f64::INFINITY, f64::min);
+        let inflows = total(&|r| r.inflows.to_f64());
+        let taxes = total(&|r| r.taxes.to_f64());
+        let outflows = total(&|r| r.outflows.to_f64());
+        let income = inflows + taxes;
+        let savings_rate = match income > 0.0 {
+            true => format!("{:.1}%", (income + outflows) / income * 100.0),
+            false => String::new(),
+        };
+        html += &format!(
+            "<tr><td>{} ({})</td>",
+            escape(&first.account_name),
+            first.opening.currency()
+        );
+        for v in [
+            first.opening.to_f64(),
+            last.closing.to_f64(),
+            lowest,
+            inflows,
+            outflows,
+            taxes,
+            total(&|r| r.growth.to_f64()),
+        ] {
+            html += &number_cell(v);
+        }
+        html += &format!("<td class=\"n\">{}</td></tr>\n", savings_rate);
+    }
+    html += "</table>\n";
+    Ok(html)
+}
+
+// totals of every category over the whole simulation, and a bar for each top-level one
+fn categories(results: &SimulationResult) -> Result<String> {
+    let mut totals: BTreeMap<(String, String), (usize, f64)> = BTreeMap::new();
+    for r in rollup(&results.payments, RollupKey::Category, Period::Year)? {
+        let key = (r.key.clone(), r.total.currency().code().to_string());
+        totals.entry(key).or_insert((r.depth, 0.0)).1 += r.total.to_f64();
+    }
+    let bars: Vec<(String, f64)> = totals
+        .iter()
+        .filter(|(_, (depth, _))| *depth == 0)
+        .map(|((key, currency), (_, total))| (format!("{} ({})", key, currency), *total))
+        .collect();
+    let mut html = bar_chart(&bars);
+    html += &table_head(&["Category", "Currency", "Total"]);
+    for ((key, currency), (depth, total)) in &totals {
+        let name = key.rsplit(':').next().unwrap_or_default();
+        html += &format!(
+            "<tr><td style=\"padding-left:{}em\">{}</td><td>{}</td>{}</tr>\n",
+            0.6 + 1.5 * *depth as f64,
+            escape(name),
+            currency,
+            number_cell(*total)
+        );
+    }
+    html += "</table>\n";
+    Ok(html)
+}
+
+// variables, accounts, cash flows and portfolios as they were given
+fn assumptions(scenario: &Scenario) -> Result<String> {
+    let doc = scenario.to_value()?;
+    let text = |v: &serde_json::Value| match v {
+        serde_json::Value::String(s) => escape(s),
+        serde_json::Value::Null => String::new(),
+        v => escape(&v.to_string()),
+    };
+    let mut html = String::new();
+    if let Some(variables) = doc["variables"].as_object().filter(|v| !v.is_empty()) {
+        html += &table_head(&["Variable", "Value"]);
+        for (name, value) in variables {
+            html += &format!(
+                "<tr><td>{}</td><td>{}</td></tr>\n",
+                escape(name),
+                text(value)
+            );
+        }
+        html += "</table>\n";
+    }
+
+    let empty = vec![];
+    let accounts = doc["accounts"].as_array().unwrap_or(&empty);
+    html += &table_head(&["Account", "Opening Balance", "Start Date", "End Date"]);
+    for a in accounts {
+        html += &format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            text(&a["name"]),
+            text(&a["balance"]),
+            text(&a["start_date"]),
+            text(&a["end_date"])
+        );
+    }
+    html += "</table>\n";
+
+    html += &table_head(&[
+        "Account",
+        "Cash Flow",
+        "Amount",
+        "Frequency",
+        "Start Date",
+        "End Date",
+        "Tax Rate",
+        "Category",
+    ]);
+    for a in accounts {
+        for f in a["cash_flows"].as_array().unwrap_or(&empty) {
+            html += "<tr>";
+            for v in [
+                &a["name"],
+                &f["name"],
+                &f["amount"],
+                &f["frequency"],
+                &f["start_date"],
+                &f["end_date"],
+                &f["tax_rate"],
+                &f["category"],
+            ] {
+                html += &format!("<td>{}</td>", text(v));
+            }
+            html += "</tr>\n";
+        }
+    }
+    html += "</table>\n";
+
+    if !scenario.portfolios.is_empty() {
+        html += &table_head(&[
+            "Portfolio",
+            "Account",
+            "Asset",
+            "Weight",
+            "Mean Return",
+            "Std Dev",
+        ]);
+        for p in &scenario.portfolios {
+            let assets = p.portfolio.assets.iter().zip(p.portfolio.weights.iter());
+            for (asset, weight) in assets {
+                html += &format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"n\">{}</td>\
+                     <td class=\"n\">{}</td><td class=\"n\">{}</td></tr>\n",
+                    escape(&p.name),
+                    escape(&p.account),
+                    escape(&asset.name),
+                    weight,
+                    asset.mean_return,
+                    asset.std_dev
+                );
+            }
+        }
+        html += "</table>\n";
+    }
+    Ok(html)
+}
+
+fn table_head(headers: &[&str]) -> String {
+    let cells: String = headers.iter().map(|h| format!("<th>{}</th>", h)).collect();
+    format!("<table>\n<tr>{}</tr>\n", cells)
+}
+
+fn number_cell(v: f64) -> String {
+    let class = if v < 0.0 { "n neg" } else { "n" };
+    format!("<td class=\"{}\">{}</td>", class, money(v))
+}
+
+fn money(v: f64) -> String {
+    format!("{:.2}", v).separate_with_commas()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Maps dates and values onto the plot area of a chart, with gridlines and labels for both
+// axes.
+struct Plot {
+    first: NaiveDate,
+    days: f64,
+    low: f64,
+    high: f64,
+}
+
+impl Plot {
+    fn new(
+        dates: impl Iterator<Item = NaiveDate> + Clone,
+        values: impl Iterator<Item = f64>,
+    ) -> Plot {
+        let first = dates.clone().min().unwrap_or_default();
+        let last = dates.max().unwrap_or_default();
+        let (low, high) = values.fold((0.0_f64, 0.0_f64), |(l, h), v| (l.min(v), h.max(v)));
+        let step = tick_step(high - low);
+        Plot {
+            first,
+            days: ((last - first).num_days() as f64).max(1.0),
+            low: (low / step).floor() * step,
+            high: ((high / step).ceil() * step).max(low + step),
+        }
+    }
+
+    fn x(&self, d: NaiveDate) -> f64 {
+        LEFT + (d - self.first).num_days() as f64 / self.days * (WIDTH - LEFT - 10.0)
+    }
+
+    fn y(&self, v: f64) -> f64 {
+        TOP + (self.high - v) / (self.high - self.low) * (HEIGHT - TOP - BOTTOM)
+    }
+
+    // the opening svg tag with gridlines at round values and six evenly spaced dates
+    fn axes(&self) -> String {
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+             viewBox=\"0 0 {0} {1}\">\n",
+            WIDTH, HEIGHT
+        );
+        let step = tick_step(self.high - self.low);
+        let mut v = self.low;
+        while v <= self.high + step / 2.0 {
+            let y = self.y(v);
+            svg += &format!(
+                "<line x1=\"{}\" y1=\"{y:.1}\" x2=\"{}\" y2=\"{y:.1}\" stroke=\"{}\"/>\
+                 <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\n",
+                LEFT,
+                WIDTH - 10.0,
+                if v == 0.0 { "#888" } else { "#e5e5e5" },
+                LEFT - 6.0,
+                y + 4.0,
+                format!("{:.0}", v).separate_with_commas(),
+            );
+            v += step;
+        }
+        for i in 0..6 {
+            let d = self.first + chrono::Duration::days((self.days * i as f64 / 5.0) as i64);
+            svg += &format!(
+                "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                self.x(d),
+                HEIGHT - 10.0,
+                d.format("%Y-%m")
+            );
+        }
+        svg
+    }
+}
+
+// 1, 2 or 5 times a power of ten giving about five gridlines
+fn tick_step(range: f64) -> f64 {
+    if range <= 0.0 {
+        return 1.0;
+    }
+    let rough = range / 5.0;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|s| *s >= rough)
+        .unwrap_or(10.0 * magnitude);
+    step.max(1.0)
+}
+
+fn legend(names: &[&str]) -> String {
+    let mut svg = String::new();
+    let mut x = LEFT;
+    for (i, name) in names.iter().enumerate() {
+        svg += &format!(
+            "<rect x=\"{:.1}\" y=\"8\" width=\"12\" height=\"12\" fill=\"{}\"/>\
+             <text x=\"{:.1}\" y=\"18\">{}</text>\n",
+            x,
+            COLORS[i % COLORS.len()],
+            x + 16.0,
+            escape(name)
+        );
+        x += 28.0 + 7.0 * name.chars().count() as f64;
+    }
+    svg
+}
+
+fn line_chart(series: &[(String, Vec<(NaiveDate, f64)>)]) -> String {
+    let points = series.iter().flat_map(|(_, p)| p.iter());
+    let plot = Plot::new(points.clone().map(|p| p.0), points.map(|p| p.1));
+    let mut svg = plot.axes();
+    for (i, (_, points)) in series.iter().enumerate() {
+        let path: Vec<String> = points
+            .iter()
+            .map(|(d, v)| format!("{:.1},{:.1}", plot.x(*d), plot.y(*v)))
+            .collect();
+        svg += &format!(
+            "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"2\" points=\"{}\"/>\n",
+            COLORS[i % COLORS.len()],
+            path.join(" ")
+        );
+    }
+    let names: Vec<&str> = series.iter().map(|(n, _)| n.as_str()).collect();
+    svg += &legend(&names);
+    svg + "</svg>\n"
+}
+
+// a shaded band between each pair of neighbouring percentiles, darker towards the middle,
+// and a line through the middle percentile
+fn fan_chart(mc: &MonteCarloResult) -> String {
+    let values = mc.bands.iter().flat_map(|b| b.balances.iter().copied());
+    let plot = Plot::new(mc.bands.iter().map(|b| b.date), values);
+    let mut svg = plot.axes();
+    let n = mc.percentiles.len();
+    for i in 1..n {
+        let lower = mc.bands.iter().map(|b| (b.date, b.balances[i - 1]));
+        let upper = mc.bands.iter().rev().map(|b| (b.date, b.balances[i]));
+        let path: Vec<String> = lower
+            .chain(upper)
+            .map(|(d, v)| format!("{:.1},{:.1}", plot.x(d), plot.y(v)))
+            .collect();
+        let inner = i.min(n - i) as f64;
+        svg += &format!(
+            "<polygon fill=\"{}\" fill-opacity=\"{:.2}\" points=\"{}\"/>\n",
+            COLORS[0],
+            0.15 + 0.2 * inner,
+            path.join(" ")
+        );
+    }
+    let middle = n / 2;
+    let path: Vec<String> = mc
+        .bands
+        .iter()
+        .map(|b| format!("{:.1},{:.1}", plot.x(b.date), plot.y(b.balances[middle])))
+        .collect();
+    svg += &format!(
+        "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"2\" points=\"{}\"/>\n",
+        COLORS[0],
+        path.join(" ")
+    );
+    let label = format!(
+        "P{} to P{}, line at P{}",
+        mc.percentiles[0],
+        mc.percentiles[n - 1],
+        mc.percentiles[middle]
+    );
+    svg += &legend(&[label.as_str()]);
+    svg + "</svg>\n"
+}
+
+// a horizontal bar per total, inflows to the right of zero in green and outflows to the left
+// in red
+fn bar_chart(bars: &[(String, f64)]) -> String {
+    let row = 22.0;
+    let height = row * bars.len() as f64 + 10.0;
+    let largest = bars.iter().map(|(_, v)| v.abs()).fold(1.0, f64::max);
+    let zero = 200.0 + (WIDTH - 210.0) / 2.0;
+    let scale = (WIDTH - 210.0) / 2.0 / largest;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+         viewBox=\"0 0 {0} {1}\">\n",
+        WIDTH, height
+    );
+    for (i, (name, total)) in bars.iter().enumerate() {
+        let y = 5.0 + row * i as f64;
+        let width = total.abs() * scale;
+        let x = if *total < 0.0 { zero - width } else { zero };
+        svg += &format!(
+            "<text x=\"190\" y=\"{:.1}\" text-anchor=\"end\">{}</text>\
+             <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{}\" fill=\"{}\">\
+             <title>{}</title></rect>\n",
+            y + 14.0,
+            escape(name),
+            x,
+            y + 3.0,
+            width,
+            row - 6.0,
+            if *total < 0.0 { "#c00000" } else { "#70ad47" },
+            money(*total)
+        );
+    }
+    svg += &format!(
+        "<line x1=\"{zero:.1}\" y1=\"0\" x2=\"{zero:.1}\" y2=\"{height}\" stroke=\"#888\"/>\n"
+    );
+    svg + "</svg>\n"
+}
+
+#[cfg(test)]
+mod report_tests {
+    use super::*;
+    use crate::sim::monte_carlo::run_monte_carlo;
+    use crate::sim::terminal::Verbosity;
+
+    #[test]
+    fn test_render() {
+        let path = std::path::Path::new("./scenarios/examples/default_scenario.yaml");
+        let mut scenario = Scenario::from_file(path).unwrap();
+        scenario.name = "Smith & <Jones>".to_string();
+        let results = scenario.run(Verbosity::Quiet).unwrap();
+        let mut settings = scenario.monte_carlo.clone().unwrap();
+        settings.samples = 10;
+        let mc = run_monte_carlo(&scenario, &settings).unwrap();
+
+        let html = render(&scenario, &results, Some(&mc)).unwrap();
+        assert!(html.contains("<h1>Smith &amp; &lt;Jones&gt;</h1>"));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("<link") && !html.contains(" src="));
+        // balances, the fan and the categories
+        assert_eq!(html.matches("<svg").count(), 3);
+        assert!(html.contains("Checking (USD)"));
+        assert!(html.contains("${salary} / 24"));
+        assert!(html.contains("-43,200.00"));
+
+        let html = render(&scenario, &results, None).unwrap();
+        assert_eq!(html.matches("<svg").count(), 2);
+    }
+
+    #[test]
+    fn test_tick_step() {
+        assert_eq!(tick_step(100_000.0), 20_000.0);
+        assert_eq!(tick_step(4_500.0), 1_000.0);
+        assert_eq!(tick_step(0.0), 1.0);
+    }
+}
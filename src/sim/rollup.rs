@@ -0,0 +1,168 @@
+use chrono::NaiveDate;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::columns::PaymentTable;
+use super::money::{Currency, Money};
+use super::variance::Period;
+use crate::{Error, Result};
+
+/// What payments are totalled by in a [`rollup`].
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq)]
+pub enum RollupKey {
+    /// Every level of the category, so `Housing:Rent` also counts towards `Housing`.
+    #[default]
+    Category,
+    /// Every tag, so a payment with two tags counts towards both.
+    Tag,
+}
+
+impl std::str::FromStr for RollupKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<RollupKey> {
+        match s.to_lowercase().as_str() {
+            "category" | "categories" => Ok(RollupKey::Category),
+            "tag" | "tags" => Ok(RollupKey::Tag),
+            _ => Err(Error::Invalid(format!(
+                "`{}` is not a rollup, expected Category or Tag",
+                s
+            ))),
+        }
+    }
+}
+
+/// Total of the payments in one category, or with one tag, in one period.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RollupRow {
+    pub period: String,
+    pub start_date: NaiveDate,
+    pub key: String,
+    /// Number of parent categories above `key`, 0 for tags.
+    pub depth: usize,
+    pub total: Money,
+    pub payments: usize,
+}
+
+/// `Housing`, `Housing:Rent` and `Housing:Rent:Deposit` for `Housing:Rent:Deposit`.
+pub fn ancestors(category: &str) -> impl Iterator<Item = &str> {
+    category
+        .match_indices(':')
+        .map(move |(i, _)| &category[..i])
+        .chain(std::iter::once(category))
+}
+
+/// Totals payments by category or tag in every period, ordered by period and then as a
+/// category tree. Payments in different currencies are totalled separately.
+pub fn rollup(payments: &PaymentTable, key: RollupKey, period: Period) -> Result<Vec<RollupRow>> {
+    let mut totals: BTreeMap<(NaiveDate, Vec<&str>, Currency), (Money, usize)> = BTreeMap::new();
+    for p in payments.iter() {
+        let keys: Vec<&str> = match key {
+            RollupKey::Category => ancestors(p.category()).collect(),
+            RollupKey::Tag => p.tags().iter().map(|t| t.as_str()).collect(),
+        };
+        for k in keys {
+            let currency = p.amount.currency();
+            let segments = k.split(':').collect();
+            let total = totals
+                .entry((period.start(p.date), segments, currency))
+                .or_insert((Money::zero(currency), 0));
+            total.0 = total.0.checked_add(p.amount)?;
+            total.1 += 1;
+        }
+    }
+    Ok(totals
+        .into_iter()
+        .map(|((start_date, segments, _), (total, payments))| RollupRow {
+            period: period.label(start_date),
+            start_date,
+            key: segments.join(":"),
+            depth: match key {
+                RollupKey::Category => segments.len() - 1,
+                RollupKey::Tag => 0,
+            },
+            total,
+            payments,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod rollup_tests {
+    use super::*;
+    use crate::sim::cash::{Account, CashFlow, Frequency};
+
+    fn date(m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2020, m, d).unwrap()
+    }
+
+    fn flow(name: &str, amount: f64, category: &str, tags: &[&str]) -> CashFlow {
+        let mut flow = CashFlow::new(
+            Some(name.to_string()),
+            Money::try_from(amount).unwrap(),
+            Some(Frequency::MonthStart),
+            None,
+            None,
+            None,
+        );
+        flow.category = Some(category.to_string());
+        flow.tags = tags.iter().map(|t| t.to_string()).collect();
+        flow
+    }
+
+    fn payments() -> PaymentTable {
+        let mut account = Account::new(
+            "Checking".to_string(),
+            Money::try_from(0.0).unwrap(),
+            vec![
+                flow("Rent", -1500.0, "Housing:Rent", &["fixed"]),
+                flow("Power", -80.0, "Housing:Utilities", &["fixed", "energy"]),
+                flow("Takeout", -120.0, "Food:Dining Out", &[]),
+                flow("Groceries", -300.0, "Food", &[]),
+            ],
+            date(1, 1),
+            date(4, 1),
+        );
+        account.payments(date(1, 1), date(3, 31)).unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn test_ancestors() {
+        let a: Vec<&str> = ancestors("Housing:Rent:Deposit").collect();
+        assert_eq!(a, vec!["Housing", "Housing:Rent", "Housing:Rent:Deposit"]);
+        assert_eq!(ancestors("Food").collect::<Vec<&str>>(), vec!["Food"]);
+    }
+
+    #[test]
+    fn test_category_rollup() {
+        let rows = rollup(&payments(), RollupKey::Category, Period::Quarter).unwrap();
+        let summary: Vec<(&str, usize, f64, usize)> = rows
+            .iter()
+            .map(|r| (r.key.as_str(), r.depth, r.total.to_f64(), r.payments))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Food", 0, -1260.0, 6),
+                ("Food:Dining Out", 1, -360.0, 3),
+                ("Housing", 0, -4740.0, 6),
+                ("Housing:Rent", 1, -4500.0, 3),
+                ("Housing:Utilities", 1, -240.0, 3),
+            ]
+        );
+        assert!(rows.iter().all(|r| r.period == "2020-Q1"));
+    }
+
+    #[test]
+    fn test_tag_rollup() {
+        let rows = rollup(&payments(), RollupKey::Tag, Period::Month).unwrap();
+        assert_eq!(rows.len(), 6);
+        let january: Vec<(&str, f64)> = rows
+            .iter()
+            .filter(|r| r.period == "2020-01")
+            .map(|r| (r.key.as_str(), r.total.to_f64()))
+            .collect();
+        assert_eq!(january, vec![("energy", -80.0), ("fixed", -1580.0)]);
+    }
+}
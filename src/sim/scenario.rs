@@ -0,0 +1,426 @@
+use chrono::Datelike;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::cash::{Account, Frequency};
+use super::expr;
+use super::fx::FxRate;
+use super::money::Currency;
+use super::monte_carlo::MonteCarlo;
+use super::params::ParameterPath;
+use super::portfolio::Portfolio;
+use super::reforecast::Reforecast;
+use super::terminal::Verbosity;
+use super::validate;
+use super::SimulationResult;
+use crate::{Error, Result};
+
+/// The scenario file format version understood by this build.
+pub const SCENARIO_VERSION: u32 = 1;
+
+/// How often balances are recorded in the simulation results. Cash flows and investment
+/// income are always applied on their own dates; the step only thins out the balance series.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema, Default)]
+pub enum Step {
+    #[default]
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Step {
+    /// Whether a balance is recorded on `d`. Monthly steps fall on the start date's day of the
+    /// month, or the last day of months too short to have it.
+    pub fn includes(&self, d: &chrono::NaiveDate, start_date: &chrono::NaiveDate) -> bool {
+        match self {
+            Step::Daily => true,
+            Step::Weekly => (*d - *start_date).num_days() % 7 == 0,
+            Step::Monthly => {
+                let months = (d.year() - start_date.year()) * 12 + d.month() as i32
+                    - start_date.month() as i32;
+                months >= 0
+                    && start_date.checked_add_months(chrono::Months::new(months as u32)) == Some(*d)
+            }
+        }
+    }
+}
+
+/// A `Portfolio` that the balance of one of the scenario's accounts is invested in.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+pub struct ScenarioPortfolio {
+    pub name: String,
+    /// Name of the account whose balance is invested in this portfolio.
+    pub account: String,
+    #[serde(flatten)]
+    pub portfolio: Portfolio,
+}
+
+/// `Scenario` is a complete, reproducible description of a simulation.
+///
+/// It declares the accounts and their cash flows, the portfolios attached to them and every
+/// simulation setting, so one file is enough to rerun a forecast. Each account is simulated
+/// between the later of its own and the scenario's start date and the earlier of the two end
+/// dates.
+///
+/// # Example
+///
+/// ```
+/// use budget::sim::scenario::Scenario;
+/// use budget::sim::terminal::Verbosity;
+///
+/// let scenario = Scenario::from_yaml("
+/// version: 1
+/// name: Example
+/// start_date: 2020-01-01
+/// end_date: 2020-12-31
+/// seed: 42
+/// accounts:
+///   - name: Checking
+///     balance: 100.0
+///     start_date: 2020-01-01
+///     end_date: 2020-12-31
+///     cash_flows: []
+/// ").unwrap();
+/// let results = scenario.run(Verbosity::Quiet).unwrap();
+/// ```
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+pub struct Scenario {
+    pub version: u32,
+    pub name: String,
+    /// Named values that cash flow amounts, dates and other fields can reference as
+    /// `${name}` in expressions such as `${salary} / 24` or `${retirement_date} + 1y`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, serde_json::Value>,
+    pub start_date: chrono::NaiveDate,
+    pub end_date: chrono::NaiveDate,
+    #[serde(default)]
+    pub step: Step,
+    pub accounts: Vec<Account>,
+    #[serde(default)]
+    pub portfolios: Vec<ScenarioPortfolio>,
+    #[serde(default = "default_rebalance_frequency")]
+    pub rebalance_frequency: Frequency,
+    /// Currency that consolidated balances are reported in. Defaults to the currency of the
+    /// first account.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reporting_currency: Option<Currency>,
+    /// Rates converting each other currency into the reporting currency.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fx: BTreeMap<Currency, FxRate>,
+    /// Seed for investment returns. Without one every run draws fresh returns.
+    pub seed: Option<u64>,
+    pub monte_carlo: Option<MonteCarlo>,
+    /// Balances observed part way through, which the simulation restarts from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reforecast: Option<Reforecast>,
+    /// Fields that were given as expressions, so they can be re-evaluated when a variable
+    /// changes.
+    #[serde(skip)]
+    expressions: Vec<(ParameterPath, String)>,
+}
+
+fn default_rebalance_frequency() -> Frequency {
+    Frequency::MonthStart
+}
+
+impl Scenario {
+    /// Wraps a single account, and optionally the portfolio it is invested in, in a scenario
+    /// covering the account's own dates. This is how `--config` and `--portfolio` files are run.
+    pub fn from_account(account: Account, portfolio: Option<Portfolio>) -> Scenario {
+        let portfolios = portfolio
+            .into_iter()
+            .map(|p| ScenarioPortfolio {
+                name: format!("{} Portfolio", account.name),
+                account: account.name.clone(),
+                portfolio: p,
+            })
+            .collect();
+        Scenario {
+            version: SCENARIO_VERSION,
+            name: account.name.clone(),
+            variables: BTreeMap::new(),
+            start_date: account.start_date,
+            end_date: account.end_date,
+            step: Step::Daily,
+            accounts: vec![account],
+            portfolios,
+            rebalance_frequency: default_rebalance_frequency(),
+            reporting_currency: None,
+            fx: BTreeMap::new(),
+            seed: None,
+            monte_carlo: None,
+            reforecast: None,
+            expressions: vec![],
+        }
+    }
+
+    pub fn from_yaml(s: &str) -> Result<Scenario> {
+        Scenario::from_value(serde_yaml::from_str(s)?)
+    }
+
+    pub fn from_json(s: &str) -> Result<Scenario> {
+        Scenario::from_value(serde_json::from_str(s)?)
+    }
+
+    /// Reads a scenario file, resolving its `extends` chain first (see [`super::inherit`]).
+    pub fn from_file(path: &std::path::Path) -> Result<Scenario> {
+        Scenario::from_value(super::inherit::resolve(path)?).map_err(|e| match e {
+            Error::Validation(mut errors) => {
+                for e in &mut errors {
+                    e.file = Some(path.display().to_string());
+                }
+                Error::Validation(errors)
+            }
+            e => e,
+        })
+    }
+
+    /// Builds a scenario from a parsed document, evaluating its expressions first. Every
+    /// expression error is reported. See [`super::validate`] for checks that the scenario also
+    /// makes sense.
+    pub fn from_value(doc: serde_json::Value) -> Result<Scenario> {
+        Ok(validate::parse(doc)?)
+    }
+
+    pub(crate) fn with_expressions(mut self, expressions: Vec<(ParameterPath, String)>) -> Scenario {
+        self.expressions = expressions;
+        self
+    }
+
+    /// The scenario as a document with expressions in place of their values, the inverse of
+    /// [`Scenario::from_value`].
+    pub fn to_value(&self) -> Result<serde_json::Value> {
+        let mut doc = serde_json::to_value(self)?;
+        for (path, source) in &self.expressions {
+            path.set(&mut doc, serde_json::Value::String(source.clone()))
+                .map_err(Error::Invalid)?;
+        }
+        Ok(doc)
+    }
+
+    /// Re-evaluates the scenario with some variables replaced, e.g. from `--set salary=130000`.
+    pub fn with_variables(
+        &self,
+        overrides: &BTreeMap<String, serde_json::Value>,
+    ) -> Result<Scenario> {
+        let mut doc = self.to_value()?;
+        for (name, value) in overrides {
+            if !self.variables.contains_key(name) {
+                return Err(Error::Invalid(format!("Unknown variable `{}`", name)));
+            }
+            doc[expr::VARIABLES][name] = value.clone();
+        }
+        Scenario::from_value(doc)
+    }
+
+    pub fn reporting_currency(&self) -> Currency {
+        self.reporting_currency.unwrap_or_else(|| {
+            self.accounts
+                .first()
+                .map(|a| a.balance.currency())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Portfolios attached to the named account.
+    pub fn portfolios_for(&self, account: &str) -> Vec<&ScenarioPortfolio> {
+        self.portfolios
+            .iter()
+            .filter(|p| p.account == account)
+            .collect()
+    }
+
+    /// Simulates every account once, seeding investment returns (and stochastic FX rates) with
+    /// `seed` when set. Balances are also consolidated into the reporting currency. Prints as
+    /// much as `verbosity` asks for, see [`Verbosity`].
+    pub fn run(&self, verbosity: Verbosity) -> Result<SimulationResult> {
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let results = self.run_with(&mut rng, verbosity)?;
+        if verbosity >= Verbosity::Summary {
+            super::terminal::print_summary(&results)?;
+        }
+        Ok(results)
+    }
+
+    pub(crate) fn run_with(
+        &self,
+        rng: &mut StdRng,
+        verbosity: Verbosity,
+    ) -> Result<SimulationResult> {
+        if let Some(reforecast) = &self.reforecast {
+            let (anchored, reconciliations) = super::reforecast::anchor(self, reforecast, rng)?;
+            let mut results = anchored.run_with(rng, verbosity)?;
+            results.reconciliations = reconciliations;
+            return Ok(results);
+        }
+        let mut results = SimulationResult::new();
+        for account in &self.accounts {
+            let mut account = account.clone();
+            account.start_date = account.start_date.max(self.start_date);
+            account.end_date = account.end_date.min(self.end_date);
+            let portfolios = self.portfolios_for(&account.name);
+            results.extend(super::simulate_account(
+                account,
+                &portfolios,
+                &self.rebalance_frequency,
+                &self.step,
+                rng,
+                verbosity,
+            )?);
+        }
+        super::fx::consolidate(&mut results, self, rng)?;
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod scenario_tests {
+    use super::*;
+    use crate::sim::money::Money;
+
+    const SCENARIO: &str = "
+version: 1
+name: Household
+start_date: 2020-01-01
+end_date: 2020-12-31
+step: Monthly
+seed: 7
+accounts:
+  - name: Checking
+    balance: 1000.0
+    start_date: 2019-01-01
+    end_date: 2030-01-01
+    cash_flows:
+      - name: Income
+        amount: 100.0
+        frequency: MonthStart
+        start_date: null
+        end_date: null
+        tax_rate: 0.0
+  - name: Brokerage
+    balance: 5000.0
+    start_date: 2020-01-01
+    end_date: 2020-12-31
+    cash_flows: []
+portfolios:
+  - name: Stocks
+    account: Brokerage
+    assets:
+      - name: Equity
+        mean_return: 0.07
+        std_dev: 0.15
+    weights: [1.0]
+";
+
+    #[test]
+    fn test_from_yaml() {
+        let scenario = Scenario::from_yaml(SCENARIO).unwrap();
+        assert_eq!(scenario.accounts.len(), 2);
+        assert_eq!(scenario.rebalance_frequency, Frequency::MonthStart);
+        assert_eq!(scenario.portfolios_for("Brokerage").len(), 1);
+        assert_eq!(scenario.portfolios_for("Checking").len(), 0);
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let yaml = SCENARIO.replace("version: 1", "version: 2");
+        assert!(Scenario::from_yaml(&yaml).is_err());
+    }
+
+    #[test]
+    fn test_run_clips_to_horizon_and_steps() {
+        let scenario = Scenario::from_yaml(SCENARIO).unwrap();
+        let results = scenario.run(Verbosity::Quiet).unwrap();
+        let checking: Vec<_> = results
+            .balances
+            .iter()
+            .filter(|b| b.account.name == "Checking")
+            .collect();
+        assert_eq!(checking.len(), 12);
+        assert_eq!(checking[0].date, scenario.start_date);
+        assert_eq!(checking[11].balance, Money::try_from(2200.0).unwrap());
+    }
+
+    #[test]
+    fn test_monthly_steps_clamp_to_month_end() {
+        let date = |m, d| chrono::NaiveDate::from_ymd_opt(2020, m, d).unwrap();
+        let start = date(1, 31);
+        let dates: Vec<chrono::NaiveDate> = start
+            .iter_days()
+            .take(366)
+            .filter(|d| Step::Monthly.includes(d, &start))
+            .collect();
+        assert_eq!(dates.len(), 12);
+        assert_eq!(dates[1..4], [date(2, 29), date(3, 31), date(4, 30)]);
+    }
+
+    #[test]
+    fn test_payments_have_ids_and_kinds() {
+        use crate::sim::cash::PaymentKind;
+        let results = Scenario::from_yaml(SCENARIO).unwrap().run(Verbosity::Quiet).unwrap();
+        let income = results
+            .payments
+            .iter()
+            .find(|p| p.kind == PaymentKind::Principal)
+            .unwrap();
+        assert_eq!(income.flow_id(), Some("checking.income"));
+        let interest: Vec<_> = results
+            .payments
+            .iter()
+            .filter(|p| p.kind == PaymentKind::Interest)
+            .collect();
+        assert_eq!(interest.len(), 12);
+        assert!(interest
+            .iter()
+            .all(|p| p.flow_id() == Some("brokerage.stocks")
+                && p.account_id() == Some("brokerage")));
+        assert!(results.balances.iter().any(|b| b.account.id == "checking"));
+    }
+
+    #[test]
+    fn test_seed_is_reproducible() {
+        let scenario = Scenario::from_yaml(SCENARIO).unwrap();
+        let a = scenario.run(Verbosity::Quiet).unwrap();
+        let b = scenario.run(Verbosity::Quiet).unwrap();
+        assert_eq!(a.ending_balance(), b.ending_balance());
+    }
+
+    #[test]
+    fn test_variables() {
+        let yaml = SCENARIO
+            .replace("seed: 7\n", "seed: 7\nvariables:\n  income: 1200\n")
+            .replace("amount: 100.0", "amount: ${income} / 12");
+        let scenario = Scenario::from_yaml(&yaml).unwrap();
+        assert_eq!(scenario.accounts[0].cash_flows[0].amount, Money::try_from(100.0).unwrap());
+        assert_eq!(
+            scenario.to_value().unwrap()["accounts"][0]["cash_flows"][0]["amount"],
+            serde_json::json!("${income} / 12")
+        );
+
+        let mut overrides = BTreeMap::new();
+        overrides.insert("income".to_string(), serde_json::json!(2400.0));
+        let raised = scenario.with_variables(&overrides).unwrap();
+        assert_eq!(raised.accounts[0].cash_flows[0].amount, Money::try_from(200.0).unwrap());
+
+        overrides.insert("bonus".to_string(), serde_json::json!(1.0));
+        assert!(scenario.with_variables(&overrides).is_err());
+    }
+
+    #[test]
+    fn test_from_account() {
+        let config =
+            std::fs::read_to_string("./scenarios/examples/default_account.yaml").unwrap();
+        let account: Account = serde_yaml::from_str(&config).unwrap();
+        let scenario = Scenario::from_account(account.clone(), None);
+        let a = scenario.run(Verbosity::Quiet).unwrap();
+        let b = super::super::run_simulation(account, None, Verbosity::Quiet).unwrap();
+        assert_eq!(a.balances.len(), b.balances.len());
+        assert_eq!(a.ending_balance(), b.ending_balance());
+    }
+}
@@ -0,0 +1,409 @@
+use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thousands::Separable;
+
+use super::expr::is_expression;
+use super::money::{Currency, Money};
+use super::params::{document, simulate, ParameterPath};
+use super::scenario::Scenario;
+use super::SimulationResult;
+use crate::{Error, Result};
+
+/// An output of a simulation that sensitivity analysis can track.
+///
+/// `DepletionDate` is measured as the number of simulated days before the balance first drops
+/// below zero. A scenario that never runs out of money scores the full length of the horizon.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+pub enum Metric {
+    EndingBalance,
+    MinimumBalance,
+    DepletionDate,
+}
+
+impl Metric {
+    pub fn all() -> Vec<Metric> {
+        vec![
+            Metric::EndingBalance,
+            Metric::MinimumBalance,
+            Metric::DepletionDate,
+        ]
+    }
+
+    pub fn evaluate(&self, result: &SimulationResult) -> f64 {
+        match self {
+            Metric::EndingBalance => result.ending_balance(),
+            Metric::MinimumBalance => result.minimum_balance(),
+            Metric::DepletionDate => {
+                let totals = result.total_balances();
+                match (totals.first(), totals.last(), result.depletion_date()) {
+                    (Some(first), _, Some(depleted)) => (depleted - first.0).num_days() as f64,
+                    (Some(first), Some(last), None) => (last.0 - first.0).num_days() as f64 + 1.0,
+                    _ => 0.0,
+                }
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "EndingBalance" => Ok(Metric::EndingBalance),
+            "MinimumBalance" => Ok(Metric::MinimumBalance),
+            "DepletionDate" => Ok(Metric::DepletionDate),
+            _ => Err(format!("Unknown metric `{}`", s)),
+        }
+    }
+}
+
+/// One bar of a tornado chart: how far a metric moves when a single input is pushed down and
+/// up by the perturbation.
+#[derive(Serialize, Debug, Clone)]
+pub struct TornadoRow {
+    pub parameter: String,
+    pub metric: Metric,
+    pub base_input: f64,
+    pub low_input: f64,
+    pub high_input: f64,
+    pub base_output: f64,
+    pub low_output: f64,
+    pub high_output: f64,
+    pub swing: f64,
+}
+
+/// Lists the numeric inputs that sensitivity analysis perturbs: every numeric variable, every
+/// cash flow amount that is not an expression, in whatever currency, and each asset's mean
+/// return, standard deviation and portfolio weight.
+pub fn parameters(scenario: &Scenario) -> Result<Vec<ParameterPath>> {
+    let doc = document(scenario)?;
+    let mut paths = vec![];
+    for (name, value) in &scenario.variables {
+        if value.is_number() {
+            paths.push(ParameterPath::parse(&format!("variables.{}", name)));
+        }
+    }
+    let account_names: Vec<Option<String>> =
+        scenario.accounts.iter().map(|a| Some(a.name.clone())).collect();
+    for (i, account) in scenario.accounts.iter().enumerate() {
+        let account_key = element_key(&account_names, i);
+        let flow_names: Vec<Option<String>> =
+            account.cash_flows.iter().map(|c| c.name.clone()).collect();
+        for j in 0..account.cash_flows.len() {
+            let path = ParameterPath::parse(&format!(
+                "accounts.{}.cash_flows.{}.amount",
+                account_key,
+                element_key(&flow_names, j)
+            ));
+            // an expression moves with the variables it uses
+            match path.get(&doc).and_then(|v| v.as_str()) {
+                Some(s) if is_expression(s) => {}
+                _ => paths.push(path),
+            }
+        }
+    }
+
+    let portfolio_names: Vec<Option<String>> =
+        scenario.portfolios.iter().map(|p| Some(p.name.clone())).collect();
+    for (i, p) in scenario.portfolios.iter().enumerate() {
+        let portfolio_key = element_key(&portfolio_names, i);
+        let asset_names: Vec<Option<String>> = p
+            .portfolio
+            .assets
+            .iter()
+            .map(|a| Some(a.name.clone()))
+            .collect();
+        for j in 0..p.portfolio.assets.len() {
+            let key = element_key(&asset_names, j);
+            for field in ["mean_return", "std_dev"] {
+                paths.push(ParameterPath::parse(&format!(
+                    "portfolios.{}.assets.{}.{}",
+                    portfolio_key, key, field
+                )));
+            }
+        }
+        for j in 0..p.portfolio.weights.len() {
+            paths.push(ParameterPath::parse(&format!(
+                "portfolios.{}.weights.{}",
+                portfolio_key, j
+            )));
+        }
+    }
+    Ok(paths)
+}
+
+// Names are friendlier in reports, but only usable as path segments when they resolve uniquely.
+fn element_key(names: &[Option<String>], index: usize) -> String {
+    match &names[index] {
+        Some(n)
+            if !n.is_empty()
+                && !n.contains('.')
+                && n.parse::<usize>().is_err()
+                && names.iter().filter(|o| o.as_ref() == Some(n)).count() == 1 =>
+        {
+            n.clone()
+        }
+        _ => index.to_string(),
+    }
+}
+
+// A number, or an amount such as `"-900 EUR"` as its number and currency.
+fn input(value: &Value) -> Option<(f64, Option<Currency>)> {
+    match value {
+        Value::Number(n) => Some((n.as_f64()?, None)),
+        Value::String(s) => {
+            let money: Money = s.parse().ok()?;
+            Some((money.to_f64(), Some(money.currency())))
+        }
+        _ => None,
+    }
+}
+
+fn perturbed(
+    doc: &Value,
+    path: &ParameterPath,
+    value: f64,
+    currency: Option<Currency>,
+) -> Result<Value> {
+    let value = match currency {
+        Some(currency) => serde_json::to_value(Money::new(Money::try_from(value)?.amount(), currency))?,
+        None => serde_json::json!(value),
+    };
+    let mut doc = doc.clone();
+    path.set(&mut doc, value).map_err(Error::Invalid)?;
+    Ok(doc)
+}
+
+/// Runs a one-at-a-time sensitivity analysis.
+///
+/// Each parameter returned by [`parameters`] is scaled by `1 - perturbation` and
+/// `1 + perturbation` (so `0.1` means ±10%) while every other input is held at its base value.
+/// The simulations run in parallel and share one seed, so investment returns are identical
+/// across runs and only the perturbed input differs. Rows are grouped by metric in the order
+/// given and, within a metric, ranked by swing so the most influential assumptions come first.
+///
+/// Fails, naming the parameter, if one isn't a number or amount or a perturbed run fails.
+pub fn sensitivity(
+    scenario: &Scenario,
+    perturbation: f64,
+    metrics: &[Metric],
+) -> Result<Vec<TornadoRow>> {
+    let mut scenario = scenario.clone();
+    scenario.seed = Some(scenario.seed.unwrap_or_else(rand::random));
+
+    let base_doc = document(&scenario)?;
+    let base = simulate(&base_doc)?;
+
+    let runs: Vec<(ParameterPath, f64, f64, f64, SimulationResult, SimulationResult)> =
+        parameters(&scenario)?
+            .into_par_iter()
+            .map(|path| {
+                let value = path.get(&base_doc).and_then(input);
+                let Some((base_input, currency)) = value else {
+                    return Err(Error::Invalid(format!("{} is not a number or amount", path)));
+                };
+                let low_input = base_input * (1.0 - perturbation);
+                let high_input = base_input * (1.0 + perturbation);
+                let run = |input: f64| {
+                    simulate(&perturbed(&base_doc, &path, input, currency)?)
+                        .map_err(|e| Error::Invalid(format!("{} at {}: {}", path, input, e)))
+                };
+                let (low, high) = (run(low_input)?, run(high_input)?);
+                Ok((path, base_input, low_input, high_input, low, high))
+            })
+            .collect::<Result<_>>()?;
+
+    let mut rows = vec![];
+    for metric in metrics {
+        let base_output = metric.evaluate(&base);
+        let mut metric_rows: Vec<TornadoRow> = runs
+            .iter()
+            .map(|(path, base_input, low_input, high_input, low, high)| {
+                let low_output = metric.evaluate(low);
+                let high_output = metric.evaluate(high);
+                TornadoRow {
+                    parameter: path.to_string(),
+                    metric: *metric,
+                    base_input: *base_input,
+                    low_input: *low_input,
+                    high_input: *high_input,
+                    base_output,
+                    low_output,
+                    high_output,
+                    swing: (high_output - low_output).abs(),
+                }
+            })
+            .collect();
+        metric_rows.sort_by(|a, b| b.swing.total_cmp(&a.swing));
+        rows.append(&mut metric_rows);
+    }
+    Ok(rows)
+}
+
+pub fn print_tornado(rows: &[TornadoRow]) {
+    let width = rows
+        .iter()
+        .map(|r| r.parameter.len())
+        .max()
+        .unwrap_or(0)
+        .max("Parameter".len());
+
+    let mut metric: Option<Metric> = None;
+    for r in rows {
+        if metric != Some(r.metric) {
+            metric = Some(r.metric);
+            println!("\n{:?} (base {})", r.metric, format_number(r.base_output));
+            println!(
+                "{:<width$}  {:>16}  {:>16}  {:>16}",
+                "Parameter",
+                "Low",
+                "High",
+                "Swing",
+                width = width
+            );
+        }
+        println!(
+            "{:<width$}  {:>16}  {:>16}  {:>16}",
+            r.parameter,
+            format_number(r.low_output - r.base_output),
+            format_number(r.high_output - r.base_output),
+            format_number(r.swing),
+            width = width
+        );
+    }
+}
+
+fn format_number(v: f64) -> String {
+    format!("{:.2}", v).separate_with_commas()
+}
+
+#[cfg(test)]
+mod sensitivity_tests {
+    use super::*;
+    use crate::sim::cash::Account;
+    use crate::sim::portfolio::{Asset, Portfolio};
+    use crate::sim::testing;
+
+    fn scenario(portfolio: Option<Portfolio>) -> Scenario {
+        Scenario::from_account(account(), portfolio)
+    }
+
+    fn account() -> Account {
+        testing::account(1000.0, &[("Income", 100.0), ("Rent", -400.0)])
+    }
+
+    #[test]
+    fn test_parameters() {
+        let portfolio = Portfolio::new(vec![Asset::new("Stocks".to_string(), 0.1, 0.0)], vec![1.0]);
+        let paths: Vec<String> = parameters(&scenario(Some(portfolio)))
+            .unwrap()
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "accounts.Test Account.cash_flows.Income.amount",
+                "accounts.Test Account.cash_flows.Rent.amount",
+                "portfolios.Test Account Portfolio.assets.Stocks.mean_return",
+                "portfolios.Test Account Portfolio.assets.Stocks.std_dev",
+                "portfolios.Test Account Portfolio.weights.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tornado_ranking() {
+        let rows = sensitivity(&scenario(None), 0.1, &[Metric::EndingBalance]).unwrap();
+        assert_eq!(rows.len(), 2);
+
+        // rent is four times larger than income, so it should dominate the ending balance
+        assert_eq!(rows[0].parameter, "accounts.Test Account.cash_flows.Rent.amount");
+        assert!((rows[0].swing - 960.0).abs() < 1e-6);
+        assert_eq!(rows[1].parameter, "accounts.Test Account.cash_flows.Income.amount");
+        assert!((rows[1].swing - 240.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_depletion_metric() {
+        let rows = sensitivity(&scenario(None), 0.5, &[Metric::DepletionDate]).unwrap();
+        let rent = rows
+            .iter()
+            .find(|r| r.parameter == "accounts.Test Account.cash_flows.Rent.amount")
+            .unwrap();
+        // more rent runs the account dry sooner
+        assert!(rent.high_output < rent.low_output);
+    }
+
+    #[test]
+    fn test_runs_share_returns() {
+        // volatile returns and no seed, yet an unchanged input gives the base outputs
+        let portfolio = Portfolio::new(vec![Asset::new("Stocks".to_string(), 0.01, 0.2)], vec![1.0]);
+        let scenario = scenario(Some(portfolio));
+        assert_eq!(scenario.seed, None);
+        let rows = sensitivity(&scenario, 0.0, &[Metric::EndingBalance]).unwrap();
+        assert_eq!(rows.len(), 5);
+        for r in rows {
+            assert_eq!(r.low_output, r.base_output, "{}", r.parameter);
+            assert_eq!(r.high_output, r.base_output, "{}", r.parameter);
+        }
+    }
+
+    #[test]
+    fn test_amounts_in_other_currencies() {
+        let path = std::path::Path::new("./scenarios/examples/multi_currency.yaml");
+        let scenario = Scenario::from_file(path).unwrap();
+        let rows = sensitivity(&scenario, 0.1, &[Metric::EndingBalance]).unwrap();
+        let rent = rows
+            .iter()
+            .find(|r| r.parameter == "accounts.Girokonto.cash_flows.Rent.amount")
+            .unwrap();
+        assert_eq!(rent.base_input, -900.0);
+        assert!((rent.high_input + 990.0).abs() < 1e-9);
+        // more rent in euros leaves less in dollars
+        assert!(rent.high_output < rent.base_output);
+        assert_eq!(rows.len(), 3);
+    }
+
+    #[test]
+    fn test_inputs() {
+        let eur: Currency = "EUR".parse().unwrap();
+        assert_eq!(input(&serde_json::json!(-900.5)), Some((-900.5, None)));
+        assert_eq!(input(&serde_json::json!("-900 EUR")), Some((-900.0, Some(eur))));
+        assert_eq!(input(&serde_json::json!("${rent} * 2")), None);
+
+        let doc = serde_json::json!({"amount": "-900 EUR"});
+        let path = ParameterPath::parse("amount");
+        let doc = perturbed(&doc, &path, -990.0, Some(eur)).unwrap();
+        assert_eq!(doc["amount"], serde_json::json!("-990 EUR"));
+        assert!(perturbed(&doc, &ParameterPath::parse("balance"), 1.0, None).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_names_use_index() {
+        let names = vec![Some("Rent".to_string()), Some("Rent".to_string()), None];
+        assert_eq!(element_key(&names, 0), "0");
+        assert_eq!(element_key(&names, 2), "2");
+        let names = vec![Some("Rent".to_string()), Some("Car".to_string())];
+        assert_eq!(element_key(&names, 1), "Car");
+
+        // both of two flows with the same name are perturbed, not the first one twice
+        let mut account = account();
+        account.cash_flows[0].name = Some("Rent".to_string());
+        let paths: Vec<String> = parameters(&Scenario::from_account(account, None))
+            .unwrap()
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "accounts.Test Account.cash_flows.0.amount",
+                "accounts.Test Account.cash_flows.1.amount",
+            ]
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use chrono::NaiveDate;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::cash::PaymentKind;
+use super::money::{Currency, Money};
+use super::variance::Period;
+use super::SimulationResult;
+use crate::Result;
+
+/// What one account received, paid and earned in one period.
+///
+/// `closing` is `opening` plus every other column but `savings_rate`, and is the next
+/// period's `opening`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct StatementRow {
+    pub period: String,
+    pub start_date: NaiveDate,
+    pub account_id: String,
+    pub account_name: String,
+    pub opening: Money,
+    /// Cash flow payments into the account, before tax.
+    pub inflows: Money,
+    /// Cash flow payments out of the account.
+    pub outflows: Money,
+    pub taxes: Money,
+    /// Returns of the portfolios the account is invested in.
+    pub growth: Money,
+    pub transfers: Money,
+    pub closing: Money,
+    /// The share of income after tax that was not spent, if there was any income.
+    pub savings_rate: Option<f64>,
+}
+
+// everything an account did in one period
+struct Totals {
+    inflows: Money,
+    outflows: Money,
+    taxes: Money,
+    growth: Money,
+    transfers: Money,
+}
+
+impl Totals {
+    fn zero(currency: Currency) -> Totals {
+        let zero = Money::zero(currency);
+        Totals {
+            inflows: zero,
+            outflows: zero,
+            taxes: zero,
+            growth: zero,
+            transfers: zero,
+        }
+    }
+
+    fn net(&self) -> Result<Money> {
+        self.inflows
+            .checked_add(self.outflows)?
+            .checked_add(self.taxes)?
+            .checked_add(self.growth)?
+            .checked_add(self.transfers)
+    }
+}
+
+/// A statement for every account in every period from its first recorded balance to its
+/// last payment or balance, in account and then period order.
+///
+/// An account opens at its first recorded balance less the cash flows paid on or before
+/// that day. Investment returns are added to the balance the day after they are recorded,
+/// so a statement closes with the returns of its last day included.
+pub fn statements(results: &SimulationResult, period: Period) -> Result<Vec<StatementRow>> {
+    // (id, name, first date, opening balance) in the order accounts were simulated
+    let mut accounts: Vec<(&str, &str, NaiveDate, Money)> = vec![];
+    for b in results.balances.iter() {
+        if !accounts.iter().any(|a| a.0 == b.account.id) {
+            accounts.push((&b.account.id, &b.account.name, b.date, b.balance));
+        }
+    }
+
+    let mut totals: BTreeMap<(&str, NaiveDate), Totals> = BTreeMap::new();
+    let mut last: BTreeMap<&str, NaiveDate> = BTreeMap::new();
+    for p in results.payments.iter() {
+        let Some(account) = p.account else { continue };
+        let i = match accounts.iter().position(|a| a.0 == account.id) {
+            Some(i) => i,
+            None => {
+                // payments of an account whose balance was never recorded open at zero
+                let zero = Money::zero(p.amount.currency());
+                accounts.push((&account.id, &account.name, p.date, zero));
+                accounts.len() - 1
+            }
+        };
+        let (id, _, first, opening) = &mut accounts[i];
+        if p.date <= *first && p.kind != PaymentKind::Interest {
+            *opening = opening.checked_add(-p.amount)?;
+        }
+        let t = totals
+            .entry((*id, period.start(p.date)))
+            .or_insert_with(|| Totals::zero(p.amount.currency()));
+        let column = match p.kind {
+            PaymentKind::Tax => &mut t.taxes,
+            PaymentKind::Interest => &mut t.growth,
+            PaymentKind::Transfer => &mut t.transfers,
+            PaymentKind::Principal if p.amount.is_negative() => &mut t.outflows,
+            PaymentKind::Principal => &mut t.inflows,
+        };
+        *column = column.checked_add(p.amount)?;
+        let end = last.entry(*id).or_insert(p.date);
+        *end = p.date.max(*end);
+    }
+    for b in results.balances.iter() {
+        let end = last.entry(&b.account.id).or_insert(b.date);
+        *end = b.date.max(*end);
+    }
+
+    let mut rows = vec![];
+    for (id, name, first, opening) in accounts {
+        let empty = Totals::zero(opening.currency());
+        let mut balance = opening;
+        let mut start = period.start(first);
+        while start <= last[id] {
+            let t = totals.get(&(id, start)).unwrap_or(&empty);
+            let closing = balance.checked_add(t.net()?)?;
+            let income = t.inflows.checked_add(t.taxes)?;
+            let saved = income.checked_add(t.outflows)?;
+            rows.push(StatementRow {
+                period: period.label(start),
+                start_date: start,
+                account_id: id.to_string(),
+                account_name: name.to_string(),
+                opening: balance,
+                inflows: t.inflows,
+                outflows: t.outflows,
+                taxes: t.taxes,
+                growth: t.growth,
+                transfers: t.transfers,
+                closing,
+                savings_rate: (income.amount() > Decimal::ZERO).then(|| {
+                    (saved.amount() / income.amount())
+                        .to_f64()
+                        .unwrap_or_default()
+                }),
+            });
+            balance = closing;
+            start = period.next(start);
+        }
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod statement_tests {
+    use super::*;
+    use crate::sim::scenario::Scenario;
+    use crate::sim::terminal::Verbosity;
+
+    const SCENARIO: &str = "
+version: 1
+name: Statements
+start_date: 2020-01-01
+end_date: 2021-01-01
+step: Monthly
+seed: 3
+accounts:
+  - name: Checking
+    balance: 1000.0
+    start_date: 2020-01-01
+    end_date: 2021-01-01
+    cash_flows:
+      - name: Pay
+        amount: 4000.0
+        frequency: MonthStart
+        start_date: null
+        end_date: null
+        tax_rate: 0.25
+      - name: Rent
+        amount: -2000.0
+        frequency: MonthEnd
+        start_date: null
+        end_date: null
+        tax_rate: 0.0
+  - name: Brokerage
+    balance: 10000.0
+    start_date: 2020-01-01
+    end_date: 2021-01-01
+    cash_flows: []
+portfolios:
+  - name: Stocks
+    account: Brokerage
+    assets:
+      - name: Equity
+        mean_return: 0.005
+        std_dev: 0.01
+    weights: [1.0]
+";
+
+    fn results() -> SimulationResult {
+        Scenario::from_yaml(SCENARIO).unwrap().run(Verbosity::Quiet).unwrap()
+    }
+
+    #[test]
+    fn test_monthly_statements() {
+        let rows = statements(&results(), Period::Month).unwrap();
+        let checking: Vec<&StatementRow> =
+            rows.iter().filter(|r| r.account_id == "checking").collect();
+        assert_eq!(checking.len(), 12);
+        let january = checking[0];
+        assert_eq!(january.opening, Money::try_from(1000.0).unwrap());
+        assert_eq!(january.inflows, Money::try_from(4000.0).unwrap());
+        assert_eq!(january.taxes, Money::try_from(-1000.0).unwrap());
+        assert_eq!(january.outflows, Money::try_from(-2000.0).unwrap());
+        assert_eq!(january.closing, Money::try_from(2000.0).unwrap());
+        // 1000 of the 3000 after tax is saved
+        assert!((january.savings_rate.unwrap() - 1.0 / 3.0).abs() < 1e-9);
+        assert!(checking.windows(2).all(|w| w[0].closing == w[1].opening));
+    }
+
+    #[test]
+    fn test_growth_matches_balances() {
+        let results = results();
+        let rows = statements(&results, Period::Year).unwrap();
+        let brokerage = rows.iter().find(|r| r.account_id == "brokerage").unwrap();
+        assert_eq!(brokerage.opening, Money::try_from(10000.0).unwrap());
+        assert!(brokerage.growth.to_f64() > 0.0);
+        assert_eq!(brokerage.savings_rate, None);
+        // the last recorded balance is before the December returns are added
+        let december = results
+            .payments
+            .iter()
+            .rev()
+            .find(|p| p.account_id() == Some("brokerage"))
+            .unwrap();
+        let last = results
+            .balances
+            .iter()
+            .rev()
+            .find(|b| b.account.id == "brokerage")
+            .unwrap();
+        assert_eq!(
+            brokerage.closing,
+            last.balance.checked_add(december.amount).unwrap()
+        );
+    }
+}
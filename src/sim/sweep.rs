@@ -0,0 +1,254 @@
+use polars::prelude::*;
+use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::params::{document, simulate, ParameterPath};
+use super::scenario::Scenario;
+use crate::{Error, Result};
+
+/// `SweepSpec` describes a grid of scenario variants.
+///
+/// Each parameter lists the values it takes, either explicitly or as an inclusive numeric
+/// range. The sweep runs the Cartesian product of all parameters.
+///
+/// # Example
+///
+/// ```yaml
+/// parameters:
+///   - path: accounts.Checking.cash_flows.Salary.end_date
+///     values: [2045-06-01, 2048-06-01]
+///   - path: accounts.Checking.cash_flows.Spending.amount
+///     range: { start: -5000, end: -3000, step: 1000 }
+///   - path: portfolios.Retirement.weights.0
+///     values: [0.4, 0.6, 0.8]
+/// ```
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct SweepSpec {
+    pub parameters: Vec<SweepParameter>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct SweepParameter {
+    pub path: String,
+    #[serde(default)]
+    pub values: Vec<Value>,
+    pub range: Option<SweepRange>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct SweepRange {
+    pub start: f64,
+    pub end: f64,
+    pub step: f64,
+}
+
+impl SweepParameter {
+    /// Explicit values followed by the values of the range, if any. A range includes its
+    /// `end` when it is a whole number of steps from `start`, give or take rounding.
+    pub fn expand(&self) -> Result<Vec<Value>> {
+        let mut values = self.values.clone();
+        if let Some(range) = &self.range {
+            if range.step <= 0.0 {
+                return Err(Error::Invalid(format!(
+                    "{}: range step must be positive, got {}",
+                    self.path, range.step
+                )));
+            }
+            if range.end < range.start {
+                return Err(Error::Invalid(format!(
+                    "{}: range end {} is before its start {}",
+                    self.path, range.end, range.start
+                )));
+            }
+            let n = ((range.end - range.start) / range.step + 1e-9).floor() as i64;
+            for i in 0..=n {
+                values.push(serde_json::json!(range.start + range.step * i as f64));
+            }
+        }
+        if values.is_empty() {
+            return Err(Error::Invalid(format!("{}: no values to sweep", self.path)));
+        }
+        Ok(values)
+    }
+}
+
+impl SweepSpec {
+    /// Expands the spec into one list of `(path, value)` assignments per scenario variant.
+    pub fn variants(&self) -> Result<Vec<Vec<(ParameterPath, Value)>>> {
+        let mut variants: Vec<Vec<(ParameterPath, Value)>> = vec![vec![]];
+        for p in &self.parameters {
+            let path = ParameterPath::parse(&p.path);
+            let values = p.expand()?;
+            let mut expanded = vec![];
+            for v in &variants {
+                for value in &values {
+                    let mut v = v.clone();
+                    v.push((path.clone(), value.clone()));
+                    expanded.push(v);
+                }
+            }
+            variants = expanded;
+        }
+        Ok(variants)
+    }
+}
+
+/// Runs every variant of the sweep in parallel and collects one row per variant.
+///
+/// The resulting frame has one column per swept parameter, named by its path, followed by
+/// `ending_balance`, `minimum_balance` and `depletion_date`. Numeric parameters keep a
+/// numeric column; anything else (dates, names) is written as text. Every variant shares one
+/// seed so differences between rows come from the swept inputs alone.
+pub fn run_sweep(spec: &SweepSpec, scenario: &Scenario) -> Result<DataFrame> {
+    let mut scenario = scenario.clone();
+    scenario.seed = Some(scenario.seed.unwrap_or_else(rand::random));
+    let base = document(&scenario)?;
+    let variants = spec.variants()?;
+
+    let results = variants
+        .par_iter()
+        .map(|assignments| {
+            let mut doc = base.clone();
+            for (path, value) in assignments {
+                path.set(&mut doc, value.clone()).map_err(Error::Invalid)?;
+            }
+            simulate(&doc)
+                .map_err(|e| Error::Invalid(format!("{} (variant {})", e, describe(assignments))))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut columns: Vec<Series> = vec![];
+    for (i, p) in spec.parameters.iter().enumerate() {
+        let values: Vec<&Value> = variants.iter().map(|v| &v[i].1).collect();
+        if values.iter().all(|v| v.is_number()) {
+            let nums: Vec<f64> = values.iter().map(|v| v.as_f64().unwrap()).collect();
+            columns.push(Series::new(&p.path, nums));
+        } else {
+            let strs: Vec<String> = values.iter().map(|v| value_to_string(v)).collect();
+            columns.push(Series::new(&p.path, strs));
+        }
+    }
+
+    let ending: Vec<f64> = results.iter().map(|r| r.ending_balance()).collect();
+    let minimum: Vec<f64> = results.iter().map(|r| r.minimum_balance()).collect();
+    let depletion: Vec<Option<chrono::NaiveDate>> =
+        results.iter().map(|r| r.depletion_date()).collect();
+    columns.push(Series::new("ending_balance", ending));
+    columns.push(Series::new("minimum_balance", minimum));
+    columns.push(Series::new("depletion_date", depletion));
+
+    Ok(DataFrame::new(columns)?)
+}
+
+/// Writes a sweep results frame to a CSV file.
+pub fn write_csv(df: &mut DataFrame, file: &str) -> Result<()> {
+    let mut f = std::fs::File::create(file)?;
+    CsvWriter::new(&mut f).include_header(true).finish(df)?;
+    Ok(())
+}
+
+fn value_to_string(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        _ => v.to_string(),
+    }
+}
+
+fn describe(assignments: &[(ParameterPath, Value)]) -> String {
+    assignments
+        .iter()
+        .map(|(p, v)| format!("{}={}", p, value_to_string(v)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod sweep_tests {
+    use super::*;
+    use crate::sim::testing::account;
+
+    fn scenario() -> Scenario {
+        Scenario::from_account(account(0.0, &[("Income", 100.0)]), None)
+    }
+
+    #[test]
+    fn test_variants_cartesian_product() {
+        let spec: SweepSpec = serde_yaml::from_str(
+            "
+parameters:
+  - path: accounts.Test Account.cash_flows.Income.amount
+    range: { start: 100, end: 300, step: 100 }
+  - path: accounts.Test Account.cash_flows.Income.end_date
+    values: [2020-06-30, 2020-12-31]
+",
+        )
+        .unwrap();
+        let variants = spec.variants().unwrap();
+        assert_eq!(variants.len(), 6);
+        assert_eq!(variants[0][0].1, serde_json::json!(100.0));
+        assert_eq!(variants[0][1].1, serde_json::json!("2020-06-30"));
+        assert_eq!(variants[5][0].1, serde_json::json!(300.0));
+        assert_eq!(variants[5][1].1, serde_json::json!("2020-12-31"));
+    }
+
+    #[test]
+    fn test_expand_range() {
+        let parameter = |range: &str| -> SweepParameter {
+            serde_yaml::from_str(&format!("{{ path: x, range: {} }}", range)).unwrap()
+        };
+        // 0.3 / 0.1 is just under 3
+        let values = parameter("{ start: 0.0, end: 0.3, step: 0.1 }").expand().unwrap();
+        assert_eq!(values.len(), 4);
+        assert!((values[3].as_f64().unwrap() - 0.3).abs() < 1e-9);
+        assert_eq!(parameter("{ start: 1, end: 1, step: 1 }").expand().unwrap().len(), 1);
+
+        for range in [
+            "{ start: 0, end: 1, step: 0 }",
+            "{ start: 0, end: 1, step: -0.5 }",
+            "{ start: 1, end: 0, step: 0.5 }",
+        ] {
+            assert!(parameter(range).expand().is_err(), "{}", range);
+        }
+        let empty: SweepParameter = serde_yaml::from_str("{ path: x, values: [] }").unwrap();
+        let err = empty.expand().unwrap_err().to_string();
+        assert!(err.contains("x: no values"), "{}", err);
+    }
+
+    #[test]
+    fn test_run_sweep() {
+        let spec: SweepSpec = serde_yaml::from_str(
+            "
+parameters:
+  - path: accounts.Test Account.cash_flows.Income.amount
+    values: [100, 200]
+  - path: accounts.Test Account.cash_flows.Income.end_date
+    values: [2020-06-30, 2020-12-31]
+",
+        )
+        .unwrap();
+        let df = run_sweep(&spec, &scenario()).unwrap();
+        assert_eq!(df.height(), 4);
+        assert_eq!(df.width(), 5);
+
+        let ending: Vec<Option<f64>> = df["ending_balance"].f64().unwrap().into_iter().collect();
+        assert_eq!(
+            ending,
+            vec![Some(600.0), Some(1200.0), Some(1200.0), Some(2400.0)]
+        );
+    }
+
+    #[test]
+    fn test_run_sweep_bad_path() {
+        let spec: SweepSpec = serde_yaml::from_str(
+            "
+parameters:
+  - path: accounts.Test Account.cash_flows.Rent.amount
+    values: [100]
+",
+        )
+        .unwrap();
+        assert!(run_sweep(&spec, &scenario()).is_err());
+    }
+}
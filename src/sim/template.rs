@@ -0,0 +1,766 @@
+use calamine::{open_workbook_auto, Data, DataType, Reader};
+use chrono::NaiveDate;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use xlsxwriter::prelude::*;
+
+use super::excel::column_name;
+use super::money::{Currency, Money};
+use super::scenario::{Scenario, SCENARIO_VERSION};
+use super::validate::{validate_document, ValidationError};
+use crate::Result;
+
+/// Sheet names of the template, in the order [`write_template`] writes them.
+pub const SHEETS: [&str; 6] = [
+    "Scenario",
+    "Variables",
+    "Accounts",
+    "Cash Flows",
+    "Portfolio",
+    "FX Rates",
+];
+
+// (header, field) of every column of each sheet, headers matched case-insensitively
+const SETTINGS: [(&str, &str); 2] = [("Setting", "setting"), ("Value", "value")];
+const SCENARIO_SETTINGS: [(&str, &str); 7] = [
+    ("Name", "name"),
+    ("Start Date", "start_date"),
+    ("End Date", "end_date"),
+    ("Step", "step"),
+    ("Rebalance Frequency", "rebalance_frequency"),
+    ("Reporting Currency", "reporting_currency"),
+    ("Seed", "seed"),
+];
+const VARIABLES: [(&str, &str); 2] = [("Name", "name"), ("Value", "value")];
+const ACCOUNTS: [(&str, &str); 6] = [
+    ("Name", "name"),
+    ("Id", "id"),
+    ("Balance", "balance"),
+    ("Currency", "currency"),
+    ("Start Date", "start_date"),
+    ("End Date", "end_date"),
+];
+const CASH_FLOWS: [(&str, &str); 10] = [
+    ("Account", "account"),
+    ("Name", "name"),
+    ("Id", "id"),
+    ("Amount", "amount"),
+    ("Frequency", "frequency"),
+    ("Start Date", "start_date"),
+    ("End Date", "end_date"),
+    ("Tax Rate", "tax_rate"),
+    ("Category", "category"),
+    ("Tags", "tags"),
+];
+const PORTFOLIO: [(&str, &str); 6] = [
+    ("Portfolio", "name"),
+    ("Account", "account"),
+    ("Asset", "asset"),
+    ("Mean Return", "mean_return"),
+    ("Std Dev", "std_dev"),
+    ("Weight", "weight"),
+];
+const FX_RATES: [(&str, &str); 6] = [
+    ("Currency", "currency"),
+    ("Date", "date"),
+    ("Rate", "rate"),
+    ("Drift", "drift"),
+    ("Volatility", "volatility"),
+    ("File", "file"),
+];
+
+// a row of a sheet, with each value and the cell it was read from by field
+type Row = BTreeMap<&'static str, (Value, String)>;
+
+/// Reads a scenario from a workbook following the template that [`write_template`] writes,
+/// and validates it like a scenario file.
+///
+/// Every sheet has a header row, and its columns can be in any order:
+///
+/// - `Scenario`: `Setting` and `Value` rows for the `Name`, `Start Date`, `End Date`, `Step`,
+///   `Rebalance Frequency`, `Reporting Currency` and `Seed`. The sheet is optional; the name
+///   defaults to the file's and the dates to the earliest and latest of the accounts.
+/// - `Variables`: a `Name` and `Value` per variable, optional.
+/// - `Accounts`: `Name`, `Id`, `Balance`, `Currency`, `Start Date` and `End Date`, one row
+///   per account.
+/// - `Cash Flows`: `Account` (its name), `Name`, `Id`, `Amount`, `Frequency`, `Start Date`,
+///   `End Date`, `Tax Rate` (0 when blank), `Category` and `Tags` (comma separated).
+/// - `Portfolio`: `Portfolio`, `Account`, `Asset`, `Mean Return`, `Std Dev` and `Weight`,
+///   one row per asset. Rows with the same portfolio name form one portfolio, named after
+///   its account when blank. Optional.
+/// - `FX Rates`: `Currency`, `Rate`, `Drift`, `Volatility` and `File` of each rate into the
+///   reporting currency, or a `Currency`, `Date` and `Rate` row per date of a series.
+///   Optional.
+///
+/// Errors name the sheet and cell they were found in, such as `Cash Flows!H3`.
+pub fn read_template(path: &Path) -> std::result::Result<Scenario, Vec<ValidationError>> {
+    let file = path.display().to_string();
+    let error = |path: String, message: String| ValidationError {
+        file: Some(file.clone()),
+        line: None,
+        path,
+        message,
+    };
+    let mut workbook =
+        open_workbook_auto(path).map_err(|e| vec![error(String::new(), e.to_string())])?;
+    let mut errors = vec![];
+    // scenario paths and the cells they were read from
+    let mut cells: Vec<(String, String)> = vec![];
+
+    let mut sheet = |name: &str, columns: &[(&'static str, &'static str)]| -> Vec<Row> {
+        if !workbook.sheet_names().iter().any(|s| s == name) {
+            return vec![];
+        }
+        match workbook.worksheet_range(name) {
+            Ok(range) => read_rows(name, &range, columns, &mut errors),
+            Err(e) => {
+                errors.push(error(name.to_string(), e.to_string()));
+                vec![]
+            }
+        }
+    };
+    let settings = sheet(SHEETS[0], &SETTINGS);
+    let variables = sheet(SHEETS[1], &VARIABLES);
+    let account_rows = sheet(SHEETS[2], &ACCOUNTS);
+    let cash_flow_rows = sheet(SHEETS[3], &CASH_FLOWS);
+    let portfolio_rows = sheet(SHEETS[4], &PORTFOLIO);
+    let fx_rows = sheet(SHEETS[5], &FX_RATES);
+    if account_rows.is_empty() {
+        errors.push(error(
+            SHEETS[2].to_string(),
+            "needs at least one account".to_string(),
+        ));
+    }
+    let mut errors: Vec<ValidationError> = errors
+        .into_iter()
+        .map(|mut e| {
+            e.file = Some(file.clone());
+            e
+        })
+        .collect();
+
+    let mut doc = Map::new();
+    doc.insert("version".to_string(), json!(SCENARIO_VERSION));
+    if let Some(stem) = path.file_stem() {
+        doc.insert("name".to_string(), json!(stem.to_string_lossy()));
+    }
+    for row in &settings {
+        let Some((setting, cell)) = row.get("setting") else {
+            continue;
+        };
+        let label = setting.as_str().unwrap_or_default();
+        match SCENARIO_SETTINGS
+            .iter()
+            .find(|(h, _)| h.eq_ignore_ascii_case(label.trim()))
+        {
+            Some((_, field)) => {
+                if let Some((value, value_cell)) = row.get("value") {
+                    doc.insert(field.to_string(), value.clone());
+                    cells.push((field.to_string(), value_cell.clone()));
+                }
+            }
+            None => errors.push(error(
+                cell.clone(),
+                format!(
+                    "unknown setting `{}`, expected one of {}",
+                    label,
+                    headers(&SCENARIO_SETTINGS)
+                ),
+            )),
+        }
+    }
+
+    let mut vars = Map::new();
+    for row in &variables {
+        if let (Some((name, _)), Some((value, cell))) = (row.get("name"), row.get("value")) {
+            let name = name
+                .as_str()
+                .map(|n| n.to_string())
+                .unwrap_or(name.to_string());
+            cells.push((format!("variables.{}", name), cell.clone()));
+            vars.insert(name, value.clone());
+        }
+    }
+    if !vars.is_empty() {
+        doc.insert("variables".to_string(), Value::Object(vars));
+    }
+
+    // accounts by name, with their currency for amounts without one
+    let mut accounts: Vec<(Map<String, Value>, Option<String>)> = vec![];
+    for (i, row) in account_rows.iter().enumerate() {
+        let currency = row
+            .get("currency")
+            .and_then(|(c, _)| c.as_str())
+            .map(|c| c.to_string());
+        let key = row_key(row, "name", i);
+        let mut account = Map::new();
+        for (field, (value, cell)) in row {
+            let value = match *field {
+                "currency" => continue,
+                "balance" => with_currency(value, currency.as_deref()),
+                _ => value.clone(),
+            };
+            cells.push((format!("accounts.{}.{}", key, field), cell.clone()));
+            account.insert(field.to_string(), value);
+        }
+        cells.push((format!("accounts.{}", key), first_cell(row)));
+        account.insert("cash_flows".to_string(), json!([]));
+        accounts.push((account, currency));
+    }
+
+    for row in &cash_flow_rows {
+        let Some((name, cell)) = row.get("account") else {
+            errors.push(error(
+                first_cell(row),
+                "a cash flow needs an Account".to_string(),
+            ));
+            continue;
+        };
+        let name = name
+            .as_str()
+            .map(|n| n.to_string())
+            .unwrap_or(name.to_string());
+        let Some((account, currency)) = accounts
+            .iter_mut()
+            .find(|(a, _)| a.get("name") == Some(&json!(name)))
+        else {
+            errors.push(error(
+                cell.clone(),
+                format!("`{}` is not an account on the {} sheet", name, SHEETS[2]),
+            ));
+            continue;
+        };
+        let flows = account["cash_flows"].as_array_mut().unwrap();
+        let key = format!(
+            "accounts.{}.cash_flows.{}",
+            name,
+            row_key(row, "name", flows.len())
+        );
+        let mut flow = Map::new();
+        flow.insert("tax_rate".to_string(), json!(0.0));
+        for (field, (value, cell)) in row {
+            let value = match *field {
+                "account" => continue,
+                "amount" => with_currency(value, currency.as_deref()),
+                "tags" => {
+                    let tags = value
+                        .as_str()
+                        .map(|t| t.to_string())
+                        .unwrap_or(value.to_string());
+                    tags.split(',')
+                        .map(|t| json!(t.trim()))
+                        .filter(|t| t != "")
+                        .collect()
+                }
+                _ => value.clone(),
+            };
+            cells.push((format!("{}.{}", key, field), cell.clone()));
+            flow.insert(field.to_string(), value);
+        }
+        cells.push((key, first_cell(row)));
+        flows.push(Value::Object(flow));
+    }
+    let accounts: Vec<Map<String, Value>> = accounts.into_iter().map(|(a, _)| a).collect();
+    // without settings the scenario spans its accounts
+    let dates = |field: &str| {
+        accounts
+            .iter()
+            .filter_map(|a| a.get(field)?.as_str())
+            .collect::<Vec<_>>()
+    };
+    if !doc.contains_key("start_date") {
+        if let Some(start) = dates("start_date").into_iter().min() {
+            doc.insert("start_date".to_string(), json!(start));
+        }
+    }
+    if !doc.contains_key("end_date") {
+        if let Some(end) = dates("end_date").into_iter().max() {
+            doc.insert("end_date".to_string(), json!(end));
+        }
+    }
+    doc.insert("accounts".to_string(), json!(accounts));
+
+    let mut portfolios: Vec<Map<String, Value>> = vec![];
+    for row in &portfolio_rows {
+        let account = row
+            .get("account")
+            .map(|(a, _)| a.clone())
+            .unwrap_or(Value::Null);
+        let name = match row.get("name") {
+            Some((name, _)) => name.clone(),
+            None => json!(format!(
+                "{} Portfolio",
+                account.as_str().unwrap_or_default()
+            )),
+        };
+        let key = format!("portfolios.{}", name.as_str().unwrap_or_default());
+        let i = match portfolios.iter().position(|p| p["name"] == name) {
+            Some(i) => i,
+            None => {
+                portfolios.push(Map::from_iter([
+                    ("name".to_string(), name.clone()),
+                    ("account".to_string(), account),
+                    ("assets".to_string(), json!([])),
+                    ("weights".to_string(), json!([])),
+                ]));
+                cells.push((key.clone(), first_cell(row)));
+                if let Some((_, cell)) = row.get("account") {
+                    cells.push((format!("{}.account", key), cell.clone()));
+                }
+                if let Some((_, cell)) = row.get("weight") {
+                    cells.push((format!("{}.weights", key), cell.clone()));
+                }
+                portfolios.len() - 1
+            }
+        };
+        let portfolio = &mut portfolios[i];
+        let n = portfolio["assets"].as_array().unwrap().len();
+        let asset_key = format!("{}.assets.{}", key, row_key(row, "asset", n));
+        let mut asset = Map::new();
+        for (field, (value, cell)) in row {
+            match *field {
+                "asset" => asset.insert("name".to_string(), value.clone()),
+                "mean_return" | "std_dev" => asset.insert(field.to_string(), value.clone()),
+                _ => continue,
+            };
+            let field = if *field == "asset" { "name" } else { field };
+            cells.push((format!("{}.{}", asset_key, field), cell.clone()));
+        }
+        cells.push((asset_key, first_cell(row)));
+        portfolio["assets"]
+            .as_array_mut()
+            .unwrap()
+            .push(Value::Object(asset));
+        if let Some((weight, cell)) = row.get("weight") {
+            cells.push((format!("{}.weights.{}", key, n), cell.clone()));
+            portfolio["weights"]
+                .as_array_mut()
+                .unwrap()
+                .push(weight.clone());
+        }
+    }
+    if !portfolios.is_empty() {
+        doc.insert("portfolios".to_string(), json!(portfolios));
+    }
+
+    let mut fx = Map::new();
+    for row in &fx_rows {
+        let Some((Value::String(currency), _)) = row.get("currency") else {
+            errors.push(error(
+                first_cell(row),
+                "an FX rate needs a Currency".to_string(),
+            ));
+            continue;
+        };
+        let key = format!("fx.{}", currency);
+        let rate = fx.entry(currency.clone()).or_insert_with(|| json!({}));
+        match (row.get("date"), row.get("rate")) {
+            (Some((date, _)), Some((value, cell))) => {
+                let date = date.as_str().unwrap_or_default();
+                cells.push((format!("{}.series.{}", key, date), cell.clone()));
+                rate["series"][date] = value.clone();
+            }
+            _ => {
+                for (field, (value, cell)) in row {
+                    if *field != "currency" && *field != "date" {
+                        cells.push((format!("{}.{}", key, field), cell.clone()));
+                        rate[*field] = value.clone();
+                    }
+                }
+            }
+        }
+        cells.push((key, first_cell(row)));
+    }
+    if !fx.is_empty() {
+        doc.insert("fx".to_string(), Value::Object(fx));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    validate_document(Value::Object(doc)).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|mut e| {
+                // the cell of the field, or of the row it is in
+                let found = cells
+                    .iter()
+                    .filter(|(p, _)| e.path == *p || e.path.starts_with(&format!("{}.", p)))
+                    .max_by_key(|(p, _)| p.len());
+                e.path = match found {
+                    Some((p, cell)) if *p == e.path => cell.clone(),
+                    Some((_, cell)) => format!("{} ({})", cell, e.path),
+                    None => e.path,
+                };
+                e.file = Some(file.clone());
+                e
+            })
+            .collect()
+    })
+}
+
+// The rows below the first row of a sheet, its headers, skipping blank rows. Blank cells are
+// left out of a row.
+fn read_rows(
+    sheet: &str,
+    range: &calamine::Range<Data>,
+    columns: &[(&'static str, &'static str)],
+    errors: &mut Vec<ValidationError>,
+) -> Vec<Row> {
+    let Some((first_row, first_col)) = range.start() else {
+        return vec![];
+    };
+    let cell = |row: usize, col: usize| {
+        let col = column_name((first_col as usize + col) as u16);
+        format!("{}!{}{}", sheet, col, first_row as usize + row + 1)
+    };
+    let mut rows = range.rows();
+    let mut fields = vec![];
+    for (col, header) in rows.next().unwrap_or_default().iter().enumerate() {
+        let header = header.to_string();
+        if header.trim().is_empty() {
+            fields.push(None);
+            continue;
+        }
+        match columns
+            .iter()
+            .find(|(h, _)| h.eq_ignore_ascii_case(header.trim()))
+        {
+            Some((_, field)) => fields.push(Some(*field)),
+            None => {
+                errors.push(ValidationError {
+                    file: None,
+                    line: None,
+                    path: cell(0, col),
+                    message: format!(
+                        "unknown column `{}`, expected one of {}",
+                        header,
+                        headers(columns)
+                    ),
+                });
+                fields.push(None);
+            }
+        }
+    }
+
+    let mut table = vec![];
+    for (i, data) in rows.enumerate() {
+        let mut row = Row::new();
+        for (col, (data, field)) in data.iter().zip(fields.iter()).enumerate() {
+            let Some(field) = field else { continue };
+            match cell_value(data) {
+                Ok(Some(value)) => {
+                    row.insert(*field, (value, cell(i + 1, col)));
+                }
+                Ok(None) => {}
+                Err(message) => errors.push(ValidationError {
+                    file: None,
+                    line: None,
+                    path: cell(i + 1, col),
+                    message,
+                }),
+            }
+        }
+        if !row.is_empty() {
+            table.push(row);
+        }
+    }
+    table
+}
+
+// Dates become `YYYY-MM-DD` strings and whole numbers integers, so seeds and dates read the
+// same as in a scenario file.
+fn cell_value(data: &Data) -> std::result::Result<Option<Value>, String> {
+    Ok(match data {
+        Data::Empty => None,
+        Data::String(s) if s.trim().is_empty() => None,
+        Data::String(s) => Some(json!(s.trim())),
+        Data::Int(i) => Some(json!(i)),
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => Some(json!(*f as i64)),
+        Data::Float(f) => Some(json!(f)),
+        Data::Bool(b) => Some(json!(b)),
+        Data::DateTime(_) => data.as_date().map(|d| json!(d.to_string())),
+        Data::DateTimeIso(s) => Some(json!(s.get(..10).unwrap_or(s))),
+        Data::DurationIso(s) => Some(json!(s)),
+        Data::Error(e) => return Err(format!("the cell has the error {}", e)),
+    })
+}
+
+// An amount of the account's currency, as `1200.5 EUR`, unless it has one or is an expression
+fn with_currency(amount: &Value, currency: Option<&str>) -> Value {
+    match (amount, currency) {
+        (Value::Number(n), Some(c)) => json!(format!("{} {}", n, c)),
+        _ => amount.clone(),
+    }
+}
+
+// how validation errors name an item: by its name, else its index
+fn row_key(row: &Row, field: &str, index: usize) -> String {
+    match row.get(field) {
+        Some((Value::String(name), _)) => name.clone(),
+        _ => index.to_string(),
+    }
+}
+
+fn first_cell(row: &Row) -> String {
+    let cells = row.values().map(|(_, cell)| cell);
+    // the leftmost column, then the shortest name
+    cells
+        .min_by_key(|c| {
+            let col: String = c
+                .rsplit('!')
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .filter(|c| c.is_ascii_alphabetic())
+                .collect();
+            (col.len(), col)
+        })
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn headers(columns: &[(&str, &str)]) -> String {
+    columns
+        .iter()
+        .map(|(h, _)| *h)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Writes a scenario as a workbook that [`read_template`] reads back, keeping expressions
+/// such as `${salary} / 24` as text. Monte Carlo settings and reforecasts are not part of the
+/// template.
+pub fn write_template(scenario: &Scenario, file: &str) -> Result<()> {
+    let workbook = Workbook::new(file)?;
+    let mut date_format = Format::new();
+    date_format.set_num_format("yyyy-mm-dd");
+    let doc = scenario.to_value()?;
+    let empty = vec![];
+    let items = |v: &Value| v.as_array().unwrap_or(&empty).clone();
+
+    let mut rows: Vec<Vec<Value>> = vec![];
+    for (header, field) in SCENARIO_SETTINGS {
+        if !doc[field].is_null() {
+            rows.push(vec![json!(header), doc[field].clone()]);
+        }
+    }
+    write_sheet(&workbook, SHEETS[0], &SETTINGS, &rows, &date_format)?;
+
+    let variables = doc["variables"].as_object().cloned().unwrap_or_default();
+    let rows: Vec<Vec<Value>> = variables
+        .into_iter()
+        .map(|(k, v)| vec![json!(k), v])
+        .collect();
+    write_sheet(&workbook, SHEETS[1], &VARIABLES, &rows, &date_format)?;
+
+    let mut accounts = vec![];
+    let mut cash_flows = vec![];
+    for a in items(&doc["accounts"]) {
+        let (balance, currency) = split_currency(&a["balance"]);
+        accounts.push(
+            ACCOUNTS
+                .iter()
+                .map(|(_, field)| match *field {
+                    "balance" => balance.clone(),
+                    "currency" => currency.clone().map_or(Value::Null, |c| json!(c)),
+                    _ => a[field].clone(),
+                })
+                .collect(),
+        );
+        for f in items(&a["cash_flows"]) {
+            cash_flows.push(
+                CASH_FLOWS
+                    .iter()
+                    .map(|(_, field)| match *field {
+                        "account" => a["name"].clone(),
+                        "amount" => split_currency(&f["amount"]).0,
+                        "tags" => {
+                            let tags: Vec<String> = items(&f["tags"])
+                                .iter()
+                                .filter_map(|t| Some(t.as_str()?.to_string()))
+                                .collect();
+                            json!(tags.join(", "))
+                        }
+                        _ => f[field].clone(),
+                    })
+                    .collect(),
+            );
+        }
+    }
+    write_sheet(&workbook, SHEETS[2], &ACCOUNTS, &accounts, &date_format)?;
+    write_sheet(&workbook, SHEETS[3], &CASH_FLOWS, &cash_flows, &date_format)?;
+
+    let mut assets = vec![];
+    for p in items(&doc["portfolios"]) {
+        for (asset, weight) in items(&p["assets"]).iter().zip(items(&p["weights"])) {
+            assets.push(vec![
+                p["name"].clone(),
+                p["account"].clone(),
+                asset["name"].clone(),
+                asset["mean_return"].clone(),
+                asset["std_dev"].clone(),
+                weight,
+            ]);
+        }
+    }
+    write_sheet(&workbook, SHEETS[4], &PORTFOLIO, &assets, &date_format)?;
+
+    let mut rates = vec![];
+    for (currency, rate) in doc["fx"].as_object().cloned().unwrap_or_default() {
+        let currency = json!(currency);
+        if rate.get("rate").is_some() || rate.get("file").is_some() {
+            let fields = FX_RATES.iter().map(|(_, field)| match *field {
+                "currency" => currency.clone(),
+                "date" => Value::Null,
+                _ => rate[field].clone(),
+            });
+            rates.push(fields.collect());
+        }
+        for (date, value) in rate["series"].as_object().cloned().unwrap_or_default() {
+            rates.push(vec![currency.clone(), json!(date), value]);
+        }
+    }
+    write_sheet(&workbook, SHEETS[5], &FX_RATES, &rates, &date_format)?;
+
+    workbook.close()?;
+    Ok(())
+}
+
+fn write_sheet(
+    workbook: &Workbook,
+    name: &str,
+    columns: &[(&str, &str)],
+    rows: &[Vec<Value>],
+    date_format: &Format,
+) -> Result<()> {
+    let mut sheet = workbook.add_worksheet(Some(name))?;
+    for (col, (header, _)) in (0..).zip(columns.iter()) {
+        sheet.write_string(0, col, header, None)?;
+    }
+    for (row, values) in (1..).zip(rows.iter()) {
+        for (col, value) in (0..).zip(values.iter()) {
+            match value {
+                Value::Number(n) => {
+                    sheet.write_number(row, col, n.as_f64().unwrap_or_default(), None)?
+                }
+                Value::Bool(b) => sheet.write_boolean(row, col, *b, None)?,
+                Value::String(s) => match s.parse::<NaiveDate>() {
+                    Ok(d) => sheet.write_datetime(row, col, &d.into(), Some(date_format))?,
+                    Err(_) => sheet.write_string(row, col, s, None)?,
+                },
+                Value::Null => {}
+                v => sheet.write_string(row, col, &v.to_string(), None)?,
+            }
+        }
+    }
+    sheet.set_column(0, columns.len() as u16 - 1, 16.0, None)?;
+    Ok(())
+}
+
+// `1200.5 EUR` as 1200.5 and EUR, and `1200.5` as 1200.5 in the default currency;
+// expressions are left as they are
+fn split_currency(amount: &Value) -> (Value, Option<String>) {
+    match amount.as_str().and_then(|s| s.parse::<Money>().ok()) {
+        Some(m) if m.currency() == Currency::default() => (json!(m.to_f64()), None),
+        Some(m) => (json!(m.to_f64()), Some(m.currency().code().to_string())),
+        None => (amount.clone(), None),
+    }
+}
+
+#[cfg(test)]
+mod template_tests {
+    use super::*;
+
+    fn temp_file(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("budget-{}-{}.xlsx", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = Path::new("./scenarios/examples/default_scenario.yaml");
+        let scenario = Scenario::from_file(path).unwrap();
+        let file = temp_file("template");
+        write_template(&scenario, file.to_str().unwrap()).unwrap();
+        let read = read_template(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        let (expected, actual) = (scenario.to_value().unwrap(), read.to_value().unwrap());
+        for field in ["name", "start_date", "end_date", "step", "seed"] {
+            assert_eq!(actual[field], expected[field], "{}", field);
+        }
+        // whole numbers are read back as integers
+        assert_eq!(actual["variables"]["salary"].as_f64(), Some(60000.0));
+        assert_eq!(actual["accounts"], expected["accounts"]);
+        assert_eq!(actual["portfolios"], expected["portfolios"]);
+        assert_eq!(
+            actual["accounts"][0]["cash_flows"][0]["amount"],
+            json!("${salary} / 24")
+        );
+    }
+
+    // a checking account with the given cash flows, as account, name, amount, frequency and
+    // tax rate
+    fn workbook(file: &Path, cash_flows: &[[&str; 5]]) {
+        let workbook = Workbook::new(file.to_str().unwrap()).unwrap();
+        let sheets = [
+            (
+                "Accounts",
+                ["Name", "Balance", "Start Date", "End Date", ""],
+            ),
+            (
+                "Cash Flows",
+                ["Account", "Name", "Amount", "Frequency", "Tax Rate"],
+            ),
+        ];
+        for (name, headers) in sheets {
+            let mut sheet = workbook.add_worksheet(Some(name)).unwrap();
+            let rows = match name {
+                "Accounts" => vec![["Checking", "100", "2020-01-01", "2021-01-01", ""]],
+                _ => cash_flows.to_vec(),
+            };
+            for (row, values) in (0..).zip(std::iter::once(headers).chain(rows)) {
+                for (col, v) in (0..).zip(values) {
+                    match v.parse::<f64>() {
+                        Ok(n) => sheet.write_number(row, col, n, None).unwrap(),
+                        Err(_) => sheet.write_string(row, col, v, None).unwrap(),
+                    }
+                }
+            }
+        }
+        workbook.close().unwrap();
+    }
+
+    #[test]
+    fn test_errors_name_cells() {
+        let file = temp_file("template-errors");
+        let cases = [
+            (
+                ["Checking", "Pay", "10", "Hourly", "0"],
+                "Cash Flows!D2",
+                "Hourly",
+            ),
+            (
+                ["Checking", "Pay", "10", "MonthStart", "1.5"],
+                "Cash Flows!E2",
+                "between 0 and 1",
+            ),
+            (
+                ["Savings", "Pay", "10", "MonthStart", "0"],
+                "Cash Flows!A2",
+                "not an account",
+            ),
+        ];
+        for (row, cell, message) in cases {
+            workbook(&file, &[row]);
+            let errors = read_template(&file).err().unwrap();
+            assert_eq!(errors.len(), 1, "{:?}", errors);
+            assert_eq!(errors[0].path, cell);
+            assert!(errors[0].message.contains(message), "{}", errors[0]);
+        }
+        std::fs::remove_file(&file).unwrap();
+
+        assert!(read_template(Path::new("missing.xlsx")).is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use std::io::IsTerminal;
+use thousands::Separable;
+
+use super::statement::{statements, StatementRow};
+use super::variance::Period;
+use super::SimulationResult;
+use crate::Result;
+
+// a column filled by eighths, from empty to full
+const BLOCKS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const CHART_WIDTH: usize = 60;
+const CHART_HEIGHT: usize = 8;
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+/// How much a simulation prints while it runs, see [`super::run_simulation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Verbosity {
+    /// Nothing.
+    #[default]
+    Quiet,
+    /// The [`summary`] of the results once the simulation has finished.
+    Summary,
+    /// The summary, and every balance, investment return and payment as it is simulated.
+    Daily,
+}
+
+/// A summary of a simulation for the terminal: each account's opening, closing and lowest
+/// balance with a sparkline of its balances, the combined ending and lowest balance, a
+/// chart of the combined balance and every account's monthly statement. Amounts are
+/// separated by thousands, and negative amounts are red when `color` is set.
+pub fn summary(results: &SimulationResult, color: bool) -> Result<String> {
+    let paint = |text: String, v: f64| match color && v < 0.0 {
+        true => format!("{}{}{}", RED, text, RESET),
+        false => text,
+    };
+    let mut out = String::new();
+
+    let rows = statements(results, Period::Month)?;
+    let mut accounts: Vec<&str> = rows.iter().map(|r| r.account_id.as_str()).collect();
+    accounts.dedup();
+    let width = accounts
+        .iter()
+        .filter_map(|id| rows.iter().find(|r| r.account_id == *id))
+        .map(|r| r.account_name.chars().count())
+        .fold("Account".len(), usize::max);
+    out += &format!(
+        "{:<width$}  {:>16}  {:>16}  {:>16}  Balance\n",
+        "Account", "Opening", "Closing", "Lowest"
+    );
+    for id in &accounts {
+        let account: Vec<&StatementRow> = rows.iter().filter(|r| r.account_id == *id).collect();
+        let balances: Vec<f64> = results
+            .balances
+            .iter()
+            .filter(|b| b.account.id == *id)
+            .map(|b| b.balance.to_f64())
+            .collect();
+        let opening = account[0].opening.to_f64();
+        let closing = account[account.len() - 1].closing.to_f64();
+        let lowest = balances.iter().copied().fold(opening, f64::min);
+        out += &format!("{:<width$}", account[0].account_name);
+        for v in [opening, closing, lowest] {
+            out += &format!("  {}", paint(format!("{:>16}", amount(v)), v));
+        }
+        out += &format!("  {}\n", sparkline(&balances, CHART_WIDTH / 2));
+    }
+
+    let totals = results.total_balances();
+    if let Some((date, ending)) = totals.last() {
+        let (low_date, lowest) = totals
+            .iter()
+            .fold(totals[0], |low, b| if b.1 < low.1 { *b } else { low });
+        out += &format!(
+            "\nEnding balance  {} on {}\n",
+            paint(amount(*ending), *ending),
+            date
+        );
+        out += &format!(
+            "Lowest balance  {} on {}\n",
+            paint(amount(lowest), lowest),
+            low_date
+        );
+        if let Some(depleted) = results.depletion_date() {
+            out += &paint(format!("Depleted on     {}\n", depleted), -1.0);
+        }
+        out += "\n";
+        let values: Vec<f64> = totals.iter().map(|b| b.1).collect();
+        for line in chart(&values, CHART_WIDTH, CHART_HEIGHT) {
+            out += &line;
+            out += "\n";
+        }
+        // the first and last dates under the ends of the chart
+        out += &format!(
+            "{:>16}  {}{:>w$}\n",
+            "",
+            totals[0].0,
+            date.to_string(),
+            w = CHART_WIDTH.min(values.len()).saturating_sub(10).max(11)
+        );
+    }
+
+    let headers = [
+        "Opening",
+        "Inflows",
+        "Outflows",
+        "Taxes",
+        "Growth",
+        "Transfers",
+        "Closing",
+    ];
+    out += &format!("\n{:<8}  {:<width$}", "Month", "Account");
+    for h in headers {
+        out += &format!("  {:>14}", h);
+    }
+    out += "\n";
+    for r in &rows {
+        out += &format!("{:<8}  {:<width$}", r.period, r.account_name);
+        for m in [
+            r.opening,
+            r.inflows,
+            r.outflows,
+            r.taxes,
+            r.growth,
+            r.transfers,
+            r.closing,
+        ] {
+            let v = m.to_f64();
+            out += &format!("  {}", paint(format!("{:>14}", amount(v)), v));
+        }
+        out += "\n";
+    }
+    Ok(out)
+}
+
+/// Prints the [`summary`] of a simulation, in color when standard output is a terminal and
+/// `NO_COLOR` is not set.
+pub fn print_summary(results: &SimulationResult) -> Result<()> {
+    let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    print!("{}", summary(results, color)?);
+    Ok(())
+}
+
+fn amount(v: f64) -> String {
+    format!("{:.2}", v).separate_with_commas()
+}
+
+// at most `width` values, evenly spaced and including the last one
+fn sample(values: &[f64], width: usize) -> Vec<f64> {
+    if values.len() <= width {
+        return values.to_vec();
+    }
+    (0..width)
+        .map(|i| values[i * (values.len() - 1) / (width - 1)])
+        .collect()
+}
+
+// the lowest and highest of the values and zero, so that the chart's baseline is visible
+fn range(values: &[f64]) -> (f64, f64) {
+    let (low, high) = values
+        .iter()
+        .fold((0.0_f64, 0.0_f64), |(l, h), v| (l.min(*v), h.max(*v)));
+    (low, if high > low { high } else { low + 1.0 })
+}
+
+/// The values as one line of blocks, lowest to highest.
+pub fn sparkline(values: &[f64], width: usize) -> String {
+    let values = sample(values, width);
+    let low = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let high = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|v| match high > low {
+            true => BLOCKS[1 + ((v - low) / (high - low) * 7.0).round() as usize],
+            false => BLOCKS[4],
+        })
+        .collect()
+}
+
+/// The values as a column chart `height` lines high, with the highest and lowest amounts
+/// labelled on the left.
+pub fn chart(values: &[f64], width: usize, height: usize) -> Vec<String> {
+    let values = sample(values, width);
+    let (low, high) = range(&values);
+    let eighths: Vec<usize> = values
+        .iter()
+        .map(|v| ((v - low) / (high - low) * (height * 8) as f64).round() as usize)
+        .collect();
+    (0..height)
+        .rev()
+        .map(|row| {
+            let label = match row {
+                r if r == height - 1 => amount(high),
+                0 => amount(low),
+                _ => String::new(),
+            };
+            let bars: String = eighths
+                .iter()
+                .map(|e| BLOCKS[e.saturating_sub(row * 8).min(8)])
+                .collect();
+            format!("{:>16} ┤{}", label, bars)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod terminal_tests {
+    use super::*;
+    use crate::sim::scenario::Scenario;
+
+    #[test]
+    fn test_summary() {
+        let path = std::path::Path::new("./scenarios/examples/default_scenario.yaml");
+        let results = Scenario::from_file(path)
+            .unwrap()
+            .run(Verbosity::Quiet)
+            .unwrap();
+        let text = summary(&results, false).unwrap();
+        assert!(!text.contains('\x1b'));
+        // 24 monthly statements of each account
+        assert_eq!(text.lines().filter(|l| l.starts_with("2021-")).count(), 24);
+        assert!(text.contains("2,000.00"));
+        assert!(text.contains("-1,800.00"));
+
+        let text = summary(&results, true).unwrap();
+        assert!(text.contains(&format!("{}{:>14}{}", RED, "-1,800.00", RESET)));
+    }
+
+    #[test]
+    fn test_chart() {
+        assert_eq!(sparkline(&[0.0, 1.0, 2.0], 10), "▁▅█");
+        assert_eq!(sparkline(&[5.0, 5.0], 10), "▄▄");
+        let lines = chart(&[0.0, 50.0, 100.0], 10, 2);
+        assert_eq!(lines[0], format!("{:>16} ┤  █", "100.00"));
+        assert_eq!(lines[1], format!("{:>16} ┤ ██", "0.00"));
+        assert_eq!(sample(&[1.0, 2.0, 3.0, 4.0, 5.0], 3), vec![1.0, 3.0, 5.0]);
+    }
+}
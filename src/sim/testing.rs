@@ -0,0 +1,28 @@
+//! Accounts shared by the unit tests of the analyses that run whole scenarios.
+
+use super::cash::{Account, CashFlow, Frequency};
+use super::money::Money;
+
+/// An account over 2020 with a monthly cash flow for each `(name, amount)`.
+pub(crate) fn account(balance: f64, cash_flows: &[(&str, f64)]) -> Account {
+    let cash_flows = cash_flows
+        .iter()
+        .map(|(name, amount)| {
+            CashFlow::new(
+                Some(name.to_string()),
+                Money::try_from(*amount).unwrap(),
+                Some(Frequency::MonthStart),
+                None,
+                None,
+                None,
+            )
+        })
+        .collect();
+    Account::new(
+        "Test Account".to_string(),
+        Money::try_from(balance).unwrap(),
+        cash_flows,
+        chrono::NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+        chrono::NaiveDate::from_ymd_opt(2020, 12, 31).unwrap(),
+    )
+}
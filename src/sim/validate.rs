@@ -0,0 +1,928 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::Path;
+
+use super::cash::{Account, Frequency};
+use super::expr;
+use super::fx::{FxRate, FX};
+use super::portfolio::Portfolio;
+use super::scenario::{Scenario, SCENARIO_VERSION};
+
+/// One problem found in a scenario.
+///
+/// `path` uses the same syntax as [`super::params::ParameterPath`], with names in place of
+/// indices where elements have them, e.g. `accounts.Checking.cash_flows.Income.tax_rate`.
+/// `file` and `line` are filled in when the scenario was read from a file and the field can
+/// be found in it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub path: String,
+    pub message: String,
+}
+
+impl ValidationError {
+    fn new(path: impl Into<String>, message: impl Into<String>) -> ValidationError {
+        ValidationError {
+            file: None,
+            line: None,
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+            if let Some(line) = self.line {
+                write!(f, "{}:", line)?;
+            }
+            write!(f, " ")?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Joins errors into one message, one error per line.
+pub fn describe(errors: &[ValidationError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Evaluates the expressions of a scenario document and deserializes it, without the
+/// semantic checks of [`check`]. Every expression error and every field that does not match
+/// the scenario format is reported.
+pub fn parse(mut doc: Value) -> Result<Scenario, Vec<ValidationError>> {
+    let expressions = expr::evaluate_document(&mut doc).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|e| {
+                ValidationError::new(
+                    e.path,
+                    format!("{} at column {} of `{}`", e.message, e.column, e.expression),
+                )
+            })
+            .collect::<Vec<ValidationError>>()
+    })?;
+
+    let mut scenario = deserialize(&doc)?;
+    let mut errors = vec![];
+    for (currency, fx) in &mut scenario.fx {
+        if let Err(e) = fx.load() {
+            errors.push(ValidationError::new(
+                format!("{}.{}.file", FX, currency),
+                e.to_string(),
+            ));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    if scenario.version != SCENARIO_VERSION {
+        return Err(vec![ValidationError::new(
+            "version",
+            format!(
+                "unsupported scenario version {} (expected {})",
+                scenario.version, SCENARIO_VERSION
+            ),
+        )]);
+    }
+    Ok(scenario.with_expressions(expressions))
+}
+
+/// Checks that a scenario makes sense: dates are ordered, names are unique, tax rates are
+/// between 0 and 1 and every portfolio has one weight per asset summing to 1. All problems are
+/// returned at once.
+pub fn check(scenario: &Scenario) -> Vec<ValidationError> {
+    let mut errors = vec![];
+    if scenario.end_date <= scenario.start_date {
+        errors.push(ValidationError::new(
+            "end_date",
+            format!("must be after start_date ({})", scenario.start_date),
+        ));
+    }
+
+    let mut names = HashSet::new();
+    let mut ids = HashSet::new();
+    for account in &scenario.accounts {
+        if !names.insert(account.name.as_str()) {
+            errors.push(ValidationError::new(
+                format!("accounts.{}", account.name),
+                "account name is used more than once",
+            ));
+        }
+        let path = format!("accounts.{}", account.name);
+        check_id(&account.id(), &format!("{}.id", path), &mut ids, &mut errors);
+        check_account(account, &path, &mut ids, &mut errors);
+    }
+
+    let reporting = scenario.reporting_currency();
+    for account in &scenario.accounts {
+        let currency = account.balance.currency();
+        if currency != reporting && !scenario.fx.contains_key(&currency) {
+            errors.push(ValidationError::new(
+                format!("accounts.{}.balance", account.name),
+                format!(
+                    "is in {} but there is no `{}.{}` rate into {}",
+                    currency, FX, currency, reporting
+                ),
+            ));
+        }
+    }
+    for (currency, fx) in &scenario.fx {
+        check_fx(fx, &format!("{}.{}", FX, currency), &mut errors);
+    }
+
+    let mut portfolio_names = HashSet::new();
+    for p in &scenario.portfolios {
+        let path = format!("portfolios.{}", p.name);
+        if !portfolio_names.insert(p.name.as_str()) {
+            errors.push(ValidationError::new(
+                path.clone(),
+                "portfolio name is used more than once",
+            ));
+        }
+        if !names.contains(p.account.as_str()) {
+            errors.push(ValidationError::new(
+                format!("{}.account", path),
+                format!("no account named `{}`", p.account),
+            ));
+        }
+        check_portfolio(&p.portfolio, &path, &mut errors);
+    }
+
+    if let Some(reforecast) = &scenario.reforecast {
+        if reforecast.as_of < scenario.start_date || reforecast.as_of >= scenario.end_date {
+            errors.push(ValidationError::new(
+                "reforecast.as_of",
+                format!(
+                    "{} is not between start_date ({}) and end_date ({})",
+                    reforecast.as_of, scenario.start_date, scenario.end_date
+                ),
+            ));
+        }
+        for (name, balance) in &reforecast.balances {
+            let path = format!("reforecast.balances.{}", name);
+            match scenario.accounts.iter().find(|a| a.name == *name) {
+                None => errors.push(ValidationError::new(
+                    path,
+                    format!("no account named `{}`", name),
+                )),
+                Some(a) if a.balance.currency() != balance.currency() => {
+                    errors.push(ValidationError::new(
+                        path,
+                        format!(
+                            "is in {} but the account balance is in {}",
+                            balance.currency(),
+                            a.balance.currency()
+                        ),
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    if let Some(mc) = &scenario.monte_carlo {
+        if mc.samples == 0 {
+            errors.push(ValidationError::new(
+                "monte_carlo.samples",
+                "must be at least 1",
+            ));
+        }
+        for (i, q) in mc.percentiles.iter().enumerate() {
+            if !(0.0..=100.0).contains(q) {
+                errors.push(ValidationError::new(
+                    format!("monte_carlo.percentiles.{}", i),
+                    format!("{} is not between 0 and 100", q),
+                ));
+            }
+        }
+    }
+    errors
+}
+
+// ids must be single words, unique among the scenario's accounts and cash flows
+fn check_id(id: &str, path: &str, ids: &mut HashSet<String>, errors: &mut Vec<ValidationError>) {
+    if id.is_empty() || id.contains(char::is_whitespace) {
+        errors.push(ValidationError::new(
+            path,
+            format!("`{}` is not an id, ids are single words", id),
+        ));
+    } else if !ids.insert(id.to_string()) {
+        errors.push(ValidationError::new(
+            path,
+            format!("`{}` is used more than once", id),
+        ));
+    }
+}
+
+fn check_account(
+    account: &Account,
+    path: &str,
+    ids: &mut HashSet<String>,
+    errors: &mut Vec<ValidationError>,
+) {
+    if account.end_date <= account.start_date {
+        errors.push(ValidationError::new(
+            format!("{}.end_date", path),
+            format!("must be after start_date ({})", account.start_date),
+        ));
+    }
+
+    let mut names = HashSet::new();
+    for (i, flow) in account.cash_flows.iter().enumerate() {
+        let path = match &flow.name {
+            Some(name) => format!("{}.cash_flows.{}", path, name),
+            None => format!("{}.cash_flows.{}", path, i),
+        };
+        if let Some(name) = &flow.name {
+            if !names.insert(name.as_str()) {
+                errors.push(ValidationError::new(
+                    path.clone(),
+                    "cash flow name is used more than once in this account",
+                ));
+            }
+        }
+        if let Some(id) = &flow.id {
+            check_id(id, &format!("{}.id", path), ids, errors);
+        }
+        if !(0.0..=1.0).contains(&flow.tax_rate) {
+            errors.push(ValidationError::new(
+                format!("{}.tax_rate", path),
+                format!("{} is not between 0 and 1", flow.tax_rate),
+            ));
+        }
+        if let (Some(start), Some(end)) = (flow.start_date, flow.end_date) {
+            if end < start {
+                errors.push(ValidationError::new(
+                    format!("{}.end_date", path),
+                    format!("must not be before start_date ({})", start),
+                ));
+            }
+        }
+        if let Some(category) = &flow.category {
+            if category.split(':').any(|c| c.trim().is_empty()) {
+                errors.push(ValidationError::new(
+                    format!("{}.category", path),
+                    format!("`{}` has an empty level", category),
+                ));
+            }
+        }
+        for (i, tag) in flow.tags.iter().enumerate() {
+            if tag.is_empty() || tag.contains(char::is_whitespace) {
+                errors.push(ValidationError::new(
+                    format!("{}.tags.{}", path, i),
+                    format!("`{}` is not a tag, tags are single words", tag),
+                ));
+            }
+        }
+        if flow.frequency.needs_start_date() && flow.start_date.is_none() {
+            let frequency = match flow.frequency {
+                Frequency::Weekly => "weekly",
+                Frequency::BiWeekly => "biweekly",
+                _ => "annual",
+            };
+            errors.push(ValidationError::new(
+                format!("{}.start_date", path),
+                format!("{} cash flows need a start_date", frequency),
+            ));
+        }
+        if flow.amount.currency() != account.balance.currency() {
+            errors.push(ValidationError::new(
+                format!("{}.amount", path),
+                format!(
+                    "is in {} but the account balance is in {}",
+                    flow.amount.currency(),
+                    account.balance.currency()
+                ),
+            ));
+        }
+    }
+}
+
+fn check_fx(fx: &FxRate, path: &str, errors: &mut Vec<ValidationError>) {
+    let sources = [fx.rate.is_some(), !fx.series.is_empty(), fx.file.is_some()];
+    if sources.iter().filter(|s| **s).count() != 1 {
+        errors.push(ValidationError::new(
+            path,
+            "needs exactly one of `rate`, `series` or `file`",
+        ));
+    }
+    if fx.rate.map(|r| r <= 0.0).unwrap_or(false) || fx.series.values().any(|r| *r <= 0.0) {
+        errors.push(ValidationError::new(path, "rates must be positive"));
+    }
+    if fx.volatility < 0.0 {
+        errors.push(ValidationError::new(
+            format!("{}.volatility", path),
+            "must not be negative",
+        ));
+    }
+    if (fx.volatility != 0.0 || fx.drift != 0.0) && fx.rate.is_none() {
+        errors.push(ValidationError::new(
+            path,
+            "`drift` and `volatility` need a starting `rate`",
+        ));
+    }
+}
+
+fn check_portfolio(portfolio: &Portfolio, path: &str, errors: &mut Vec<ValidationError>) {
+    if portfolio.weights.len() != portfolio.assets.len() {
+        errors.push(ValidationError::new(
+            format!("{}.weights", path),
+            format!(
+                "has {} weights for {} assets",
+                portfolio.weights.len(),
+                portfolio.assets.len()
+            ),
+        ));
+    }
+    let total: f64 = portfolio.weights.iter().sum();
+    if (total - 1.0).abs() > 1e-6 {
+        errors.push(ValidationError::new(
+            format!("{}.weights", path),
+            format!("weights sum to {}, not 1", total),
+        ));
+    }
+
+    let mut names = HashSet::new();
+    for asset in &portfolio.assets {
+        let path = format!("{}.assets.{}", path, asset.name);
+        if !names.insert(asset.name.as_str()) {
+            errors.push(ValidationError::new(
+                path.clone(),
+                "asset name is used more than once in this portfolio",
+            ));
+        }
+        if asset.std_dev < 0.0 {
+            errors.push(ValidationError::new(
+                format!("{}.std_dev", path),
+                "must not be negative",
+            ));
+        }
+    }
+}
+
+/// Parses and checks a scenario given as YAML or JSON text, e.g. an API request body.
+pub fn validate_str(source: &str) -> Result<Scenario, Vec<ValidationError>> {
+    let doc: Value = serde_yaml::from_str(source).map_err(|e| {
+        vec![ValidationError {
+            file: None,
+            line: e.location().map(|l| l.line()),
+            path: String::new(),
+            message: e.to_string(),
+        }]
+    })?;
+    validate_document(doc)
+}
+
+/// Parses and checks a scenario document, see [`parse`] and [`check`].
+pub fn validate_document(doc: Value) -> Result<Scenario, Vec<ValidationError>> {
+    let scenario = parse(doc)?;
+    let errors = check(&scenario);
+    if errors.is_empty() {
+        Ok(scenario)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Reads, parses and checks a scenario file, resolving `extends` first. Each error is
+/// attributed to the file (the variant or one of its bases) and line that set the field.
+pub fn validate_file(path: &Path) -> Result<Scenario, Vec<ValidationError>> {
+    let file_error = |message: String| {
+        vec![ValidationError {
+            file: Some(path.display().to_string()),
+            line: None,
+            path: String::new(),
+            message,
+        }]
+    };
+    let doc = super::inherit::resolve(path).map_err(|e| file_error(e.to_string()))?;
+    let files = super::inherit::files(path).map_err(|e| file_error(e.to_string()))?;
+    let sources: Vec<(String, String)> = files
+        .iter()
+        .filter_map(|f| Some((f.display().to_string(), std::fs::read_to_string(f).ok()?)))
+        .collect();
+
+    validate_document(doc).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|mut e| {
+                let mut best: Option<(usize, &str, usize)> = None;
+                for (file, source) in &sources {
+                    if let Some((depth, line)) = locate(source, &e.path) {
+                        if best.map(|b| depth > b.0).unwrap_or(true) {
+                            best = Some((depth, file, line));
+                        }
+                    }
+                }
+                e.file = Some(path.display().to_string());
+                if let Some((_, file, line)) = best {
+                    e.file = Some(file.to_string());
+                    e.line = Some(line);
+                }
+                e
+            })
+            .collect()
+    })
+}
+
+/// Reads and checks a single account config, wrapping it in a scenario like
+/// [`Scenario::from_account`]. Paths in the errors are relative to the account.
+pub fn validate_account_file(
+    path: &Path,
+    portfolio: Option<Portfolio>,
+) -> Result<Scenario, Vec<ValidationError>> {
+    let file = path.display().to_string();
+    let error = |line: Option<usize>, path: String, message: String| {
+        vec![ValidationError {
+            file: Some(file.clone()),
+            line,
+            path,
+            message,
+        }]
+    };
+    let source = std::fs::read_to_string(path).map_err(|e| error(None, String::new(), e.to_string()))?;
+    let doc: Value = serde_yaml::from_str(&source).map_err(|e| {
+        error(e.location().map(|l| l.line()), String::new(), e.to_string())
+    })?;
+    let account: Account = serde_path_to_error::deserialize(&doc).map_err(|e| {
+        let p = label(&doc, &e.path().to_string());
+        error(locate(&source, &p).map(|l| l.1), p, e.inner().to_string())
+    })?;
+
+    let scenario = Scenario::from_account(account, portfolio);
+    let prefix = format!("accounts.{}.", scenario.accounts[0].name);
+    let errors: Vec<ValidationError> = check(&scenario)
+        .into_iter()
+        .map(|mut e| {
+            if let Some(p) = e.path.strip_prefix(&prefix) {
+                e.path = p.to_string();
+            }
+            e.file = Some(file.clone());
+            e.line = locate(&source, &e.path).map(|l| l.1);
+            e
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(scenario)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Converts a serde path such as `accounts[0].cash_flows[1].amount` into a parameter path,
+/// naming elements that have a `name`.
+#[derive(Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+// Deserializes a scenario, and when a field doesn't match the format tries again without it
+// to find the next one. A field that is missing, or was left out and is required, leaves out
+// the object holding it instead. Paths are tracked in the original document, so indices stay
+// right when list items are left out.
+fn deserialize(doc: &Value) -> Result<Scenario, Vec<ValidationError>> {
+    let mut probe = doc.clone();
+    let mut removed: Vec<Vec<Segment>> = vec![];
+    let mut errors = vec![];
+    loop {
+        let e = match serde_path_to_error::deserialize::<_, Scenario>(&probe) {
+            Ok(scenario) if errors.is_empty() => return Ok(scenario),
+            Ok(_) => return Err(errors),
+            Err(e) => e,
+        };
+        let mut probe_path = vec![];
+        for segment in e.path().iter() {
+            probe_path.push(match segment {
+                serde_path_to_error::Segment::Seq { index } => Segment::Index(*index),
+                serde_path_to_error::Segment::Map { key } => Segment::Key(key.clone()),
+                serde_path_to_error::Segment::Enum { variant } => Segment::Key(variant.clone()),
+                serde_path_to_error::Segment::Unknown => break,
+            });
+        }
+        let path = original(&probe_path, &removed);
+
+        let message = e.inner().to_string();
+        let missing = message
+            .strip_prefix("missing field `")
+            .and_then(|m| m.strip_suffix('`'));
+        let left_out = missing.is_some_and(|field| {
+            let mut field_path = path.clone();
+            field_path.push(Segment::Key(field.to_string()));
+            removed.contains(&field_path)
+        });
+        if !left_out {
+            errors.push(ValidationError::new(label(doc, &path_string(&path)), message));
+        }
+        if !remove(&mut probe, &probe_path) {
+            return Err(errors);
+        }
+        removed.push(path);
+    }
+}
+
+// The path in the original document of a path in the document with `removed` left out.
+fn original(path: &[Segment], removed: &[Vec<Segment>]) -> Vec<Segment> {
+    let mut result: Vec<Segment> = vec![];
+    for segment in path {
+        let segment = match segment {
+            Segment::Index(i) => {
+                let mut before: Vec<usize> = removed
+                    .iter()
+                    .filter(|r| r.len() == result.len() + 1 && r[..result.len()] == result[..])
+                    .filter_map(|r| match r.last() {
+                        Some(Segment::Index(j)) => Some(*j),
+                        _ => None,
+                    })
+                    .collect();
+                before.sort();
+                let mut i = *i;
+                for j in before {
+                    if j <= i {
+                        i += 1;
+                    }
+                }
+                Segment::Index(i)
+            }
+            key => key.clone(),
+        };
+        result.push(segment);
+    }
+    result
+}
+
+// Removes the value at `path`, returning whether there was one.
+fn remove(doc: &mut Value, path: &[Segment]) -> bool {
+    let Some((last, parents)) = path.split_last() else {
+        return false;
+    };
+    let mut v = doc;
+    for segment in parents {
+        let next = match segment {
+            Segment::Key(k) => v.get_mut(k.as_str()),
+            Segment::Index(i) => v.get_mut(*i),
+        };
+        match next {
+            Some(next) => v = next,
+            None => return false,
+        }
+    }
+    match (last, v) {
+        (Segment::Key(k), Value::Object(map)) => map.remove(k).is_some(),
+        (Segment::Index(i), Value::Array(items)) if *i < items.len() => {
+            items.remove(*i);
+            true
+        }
+        _ => false,
+    }
+}
+
+// The path as serde_path_to_error writes it, e.g. `accounts[0].balance`.
+fn path_string(path: &[Segment]) -> String {
+    let mut s = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(k) if s.is_empty() => s += k,
+            Segment::Key(k) => s += &format!(".{}", k),
+            Segment::Index(i) => s += &format!("[{}]", i),
+        }
+    }
+    if s.is_empty() {
+        s += ".";
+    }
+    s
+}
+
+fn label(doc: &Value, serde_path: &str) -> String {
+    if serde_path == "." {
+        return String::new();
+    }
+    let mut v = Some(doc);
+    let mut segments = vec![];
+    for part in serde_path.split(['.', '[']) {
+        if part.is_empty() {
+            continue;
+        }
+        match part.strip_suffix(']').and_then(|i| i.parse::<usize>().ok()) {
+            Some(i) => {
+                let item = v.and_then(|v| v.get(i));
+                let name = item.and_then(|v| v.get("name")).and_then(|n| n.as_str());
+                segments.push(name.map(|n| n.to_string()).unwrap_or(i.to_string()));
+                v = item;
+            }
+            None => {
+                segments.push(part.to_string());
+                v = v.and_then(|v| v.get(part));
+            }
+        }
+    }
+    segments.join(".")
+}
+
+struct Node {
+    key: Option<String>,
+    value: String,
+    line: usize,
+    column: usize,
+    item: bool,
+    children: Vec<usize>,
+}
+
+/// Finds the line (1-based) of the field at `path` in block style YAML, along with how many
+/// segments of the path were found. When only a prefix of the path exists in the file the
+/// line of the deepest match is returned, e.g. the `weights:` line for `weights.1`.
+pub fn locate(source: &str, path: &str) -> Option<(usize, usize)> {
+    // nodes[0] is the document root
+    let mut nodes = vec![Node {
+        key: None,
+        value: String::new(),
+        line: 0,
+        column: 0,
+        item: false,
+        children: vec![],
+    }];
+    let mut stack: Vec<usize> = vec![0];
+
+    for (n, text) in source.lines().enumerate() {
+        let trimmed = text.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("---") {
+            continue;
+        }
+        let mut column = text.len() - trimmed.len() + 1;
+        let mut rest = trimmed;
+
+        while let Some(r) = rest.strip_prefix('-').filter(|r| r.is_empty() || r.starts_with(' ')) {
+            while stack.len() > 1 {
+                let top = &nodes[*stack.last().unwrap()];
+                if top.column > column || (top.column == column && top.item) {
+                    stack.pop();
+                } else {
+                    break;
+                }
+            }
+            let r2 = r.trim_start();
+            push(&mut nodes, &mut stack, None, String::new(), n + 1, column, true);
+            column += rest.len() - r2.len();
+            rest = r2;
+        }
+
+        let key = rest
+            .split_once(':')
+            .filter(|(_, v)| v.is_empty() || v.starts_with(' '));
+        if let Some((k, v)) = key {
+            while stack.len() > 1 {
+                let top = &nodes[*stack.last().unwrap()];
+                if top.column >= column {
+                    stack.pop();
+                } else {
+                    break;
+                }
+            }
+            let k = k.trim().trim_matches('"').trim_matches('\'').to_string();
+            let v = v.trim().trim_matches('"').trim_matches('\'').to_string();
+            push(&mut nodes, &mut stack, Some(k), v, n + 1, column, false);
+        }
+    }
+
+    let mut current = 0;
+    let mut found = (0, 0);
+    for (depth, segment) in path.split('.').filter(|s| !s.is_empty()).enumerate() {
+        let children = &nodes[current].children;
+        let items: Vec<usize> = children.iter().copied().filter(|c| nodes[*c].item).collect();
+        let next = if items.is_empty() {
+            children
+                .iter()
+                .copied()
+                .find(|c| nodes[*c].key.as_deref() == Some(segment))
+        } else {
+            items
+                .iter()
+                .copied()
+                .find(|i| {
+                    nodes[*i].children.iter().any(|c| {
+                        nodes[*c].key.as_deref() == Some("name") && nodes[*c].value == segment
+                    })
+                })
+                .or_else(|| segment.parse::<usize>().ok().and_then(|i| items.get(i).copied()))
+        };
+        match next {
+            Some(n) => {
+                current = n;
+                found = (depth + 1, nodes[n].line);
+            }
+            None => break,
+        }
+    }
+    if found.0 == 0 {
+        None
+    } else {
+        Some(found)
+    }
+}
+
+fn push(
+    nodes: &mut Vec<Node>,
+    stack: &mut Vec<usize>,
+    key: Option<String>,
+    value: String,
+    line: usize,
+    column: usize,
+    item: bool,
+) {
+    let parent = *stack.last().unwrap();
+    nodes.push(Node {
+        key,
+        value,
+        line,
+        column,
+        item,
+        children: vec![],
+    });
+    let id = nodes.len() - 1;
+    nodes[parent].children.push(id);
+    stack.push(id);
+}
+
+#[cfg(test)]
+mod validate_tests {
+    use super::*;
+
+    const SCENARIO: &str = "version: 1
+name: Broken
+start_date: 2021-01-01
+end_date: 2020-01-01
+accounts:
+  - name: Checking
+    balance: 100.0
+    start_date: 2020-01-01
+    end_date: 2021-01-01
+    cash_flows:
+      - name: Income
+        amount: 100.0
+        frequency: MonthStart
+        start_date: null
+        end_date: null
+        tax_rate: 1.5
+      - name: Income
+        amount: 10.0
+        frequency: Annually
+        start_date: null
+        end_date: null
+        tax_rate: 0.0
+portfolios:
+  - name: Stocks
+    account: Savings
+    assets:
+      - name: Equity
+        mean_return: 0.07
+        std_dev: 0.15
+    weights: [0.5, 0.4]
+";
+
+    #[test]
+    fn test_all_errors_at_once() {
+        let errors = validate_str(SCENARIO).err().unwrap();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "end_date: must be after start_date (2021-01-01)",
+                "accounts.Checking.cash_flows.Income.tax_rate: 1.5 is not between 0 and 1",
+                "accounts.Checking.cash_flows.Income: cash flow name is used more than once in this account",
+                "accounts.Checking.cash_flows.Income.start_date: annual cash flows need a start_date",
+                "portfolios.Stocks.account: no account named `Savings`",
+                "portfolios.Stocks.weights: has 2 weights for 1 assets",
+                "portfolios.Stocks.weights: weights sum to 0.9, not 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_errors_have_paths() {
+        let yaml = SCENARIO.replace("amount: 10.0", "amount: [10.0]");
+        let errors = validate_str(&yaml).err().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "accounts.Checking.cash_flows.Income.amount");
+        assert!(errors[0].message.starts_with("invalid type"));
+
+        let errors = validate_str("version: 1\nname: a: b\nseed: 1\n").err().unwrap();
+        assert_eq!(errors[0].line, Some(2));
+    }
+
+    #[test]
+    fn test_every_type_error() {
+        let yaml = SCENARIO
+            .replace("balance: 100.0", "balance: lots")
+            .replace("amount: 100.0", "amount: [100.0]")
+            .replace("frequency: Annually", "frequency: Hourly")
+            .replace("std_dev: 0.15", "std_dev: high")
+            + "seed: many\n";
+        let errors = validate_str(&yaml).err().unwrap();
+        let paths: Vec<&str> = errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "accounts.Checking.balance",
+                "accounts.Checking.cash_flows.Income.amount",
+                // the second flow, found after the first was left out
+                "accounts.Checking.cash_flows.Income.frequency",
+                "portfolios.Stocks",
+                "seed",
+            ]
+        );
+        // a required field left out is not reported again as missing
+        assert!(!errors.iter().any(|e| e.message.starts_with("missing field")));
+
+        let yaml = SCENARIO.replace("    balance: 100.0\n", "");
+        let errors = validate_str(&yaml).err().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "accounts.Checking: missing field `balance`");
+    }
+
+    #[test]
+    fn test_ids_are_unique() {
+        let yaml = SCENARIO
+            .replace("  - name: Checking\n", "  - name: Checking\n    id: main\n")
+            .replace("      - name: Income\n", "      - id: main\n        name: Income\n");
+        let errors = validate_str(&yaml).err().unwrap();
+        assert!(errors
+            .iter()
+            .any(|e| e.to_string() == "accounts.Checking.cash_flows.Income.id: `main` is used more than once"));
+    }
+
+    #[test]
+    fn test_currencies_must_match() {
+        let yaml = SCENARIO.replace("amount: 100.0", "amount: 100.00 EUR");
+        let errors = validate_str(&yaml).err().unwrap();
+        assert!(errors.iter().any(|e| e.to_string()
+            == "accounts.Checking.cash_flows.Income.amount: is in EUR but the account balance is in USD"));
+    }
+
+    #[test]
+    fn test_fx_rates() {
+        let yaml = "version: 1
+name: Abroad
+start_date: 2020-01-01
+end_date: 2021-01-01
+fx:
+  GBP:
+    rate: 1.3
+    series:
+      2020-01-01: 1.3
+  CHF:
+    series:
+      2020-01-01: 1.0
+    volatility: 0.1
+accounts:
+  - name: Checking
+    balance: 100.0
+    start_date: 2020-01-01
+    end_date: 2021-01-01
+    cash_flows: []
+  - name: Girokonto
+    balance: 100 EUR
+    start_date: 2020-01-01
+    end_date: 2021-01-01
+    cash_flows: []
+";
+        let errors = validate_str(yaml).err().unwrap();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "accounts.Girokonto.balance: is in EUR but there is no `fx.EUR` rate into USD",
+                "fx.CHF: `drift` and `volatility` need a starting `rate`",
+                "fx.GBP: needs exactly one of `rate`, `series` or `file`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_locate() {
+        assert_eq!(locate(SCENARIO, "end_date"), Some((1, 4)));
+        assert_eq!(locate(SCENARIO, "accounts.Checking.cash_flows.Income.tax_rate"), Some((5, 16)));
+        assert_eq!(locate(SCENARIO, "accounts.Checking.cash_flows.1.frequency"), Some((5, 19)));
+        assert_eq!(locate(SCENARIO, "portfolios.Stocks.weights.1"), Some((3, 30)));
+        assert_eq!(locate(SCENARIO, "missing"), None);
+    }
+
+    #[test]
+    fn test_validate_file() {
+        let scenario = validate_file(Path::new("./scenarios/examples/default_scenario_raise.yaml"));
+        assert!(scenario.is_ok());
+    }
+}
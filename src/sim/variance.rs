@@ -0,0 +1,406 @@
+use chrono::{Datelike, NaiveDate};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::columns::PaymentTable;
+use super::ledger::Transaction;
+use super::money::{Currency, Money};
+use super::recurring::normalize_payee;
+use crate::{Error, Result};
+
+/// Length of the periods planned and actual amounts are totalled over.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Period {
+    #[default]
+    Month,
+    Quarter,
+    Year,
+}
+
+impl Period {
+    /// First day of the period containing `date`.
+    pub fn start(&self, date: NaiveDate) -> NaiveDate {
+        let month = match self {
+            Period::Month => date.month(),
+            Period::Quarter => (date.month() - 1) / 3 * 3 + 1,
+            Period::Year => 1,
+        };
+        NaiveDate::from_ymd_opt(date.year(), month, 1).unwrap()
+    }
+
+    /// First day of the period after the one starting on `start`.
+    pub fn next(&self, start: NaiveDate) -> NaiveDate {
+        let months = match self {
+            Period::Month => 1,
+            Period::Quarter => 3,
+            Period::Year => 12,
+        };
+        start + chrono::Months::new(months)
+    }
+
+    /// `2020-01`, `2020-Q1` or `2020` for the period starting on `start`.
+    pub fn label(&self, start: NaiveDate) -> String {
+        match self {
+            Period::Month => start.format("%Y-%m").to_string(),
+            Period::Quarter => format!("{}-Q{}", start.year(), (start.month() - 1) / 3 + 1),
+            Period::Year => start.year().to_string(),
+        }
+    }
+}
+
+impl std::str::FromStr for Period {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Period> {
+        match s.to_lowercase().as_str() {
+            "month" | "monthly" => Ok(Period::Month),
+            "quarter" | "quarterly" => Ok(Period::Quarter),
+            "year" | "yearly" | "annually" => Ok(Period::Year),
+            _ => Err(Error::Invalid(format!(
+                "`{}` is not a period, expected Month, Quarter or Year",
+                s
+            ))),
+        }
+    }
+}
+
+/// Planned and actual totals of one category in one period.
+#[derive(Serialize, Clone, Debug)]
+pub struct VarianceRow {
+    pub category: String,
+    pub period: String,
+    pub start_date: NaiveDate,
+    pub planned: Money,
+    pub actual: Money,
+    /// Actual minus planned, so positive when there is more cash than planned: income above
+    /// plan or spending below it.
+    pub variance: Money,
+    /// The category's variance in this and all earlier periods.
+    pub cumulative_variance: Money,
+}
+
+/// `VarianceReport` lines up a simulation's planned payments with actual transactions.
+#[derive(Serialize, Clone, Debug)]
+pub struct VarianceReport {
+    pub period: Period,
+    pub currency: Currency,
+    /// Every category in every period from the first payment or transaction to the last, by
+    /// category and date.
+    pub rows: Vec<VarianceRow>,
+    /// Actual transactions that belong to no planned category.
+    pub unmatched: Vec<Transaction>,
+}
+
+/// Totals `payments` and `transactions` by category and period.
+///
+/// A payment's category is its cash flow's [`category`](super::cash::CashFlow::category).
+/// A transaction belongs to the planned category its own category names, or else to the
+/// cash flow whose name is part of its payee (ignoring case, punctuation and numbers).
+pub fn budget_vs_actual(
+    payments: &PaymentTable,
+    transactions: &[Transaction],
+    period: Period,
+) -> Result<VarianceReport> {
+    let currency = payments
+        .iter()
+        .map(|p| p.amount.currency())
+        .chain(transactions.iter().map(|t| t.amount.currency()))
+        .next()
+        .unwrap_or_default();
+    let check = |amount: &Money, what: String| {
+        if amount.currency() != currency {
+            return Err(Error::Invalid(format!(
+                "{} is in {} but the report is in {}",
+                what,
+                amount.currency(),
+                currency
+            )));
+        }
+        Ok(())
+    };
+
+    let mut totals: BTreeMap<(String, NaiveDate), (Money, Money)> = BTreeMap::new();
+    let zero = (Money::zero(currency), Money::zero(currency));
+    let mut payees: Vec<(String, String)> = vec![];
+    for p in payments.iter() {
+        check(
+            &p.amount,
+            format!("Payment {} on {}", p.category(), p.date),
+        )?;
+        let category = p.category().to_string();
+        let total = totals
+            .entry((category.clone(), period.start(p.date)))
+            .or_insert(zero);
+        total.0 = total.0.checked_add(p.amount)?;
+        if let Some(name) = &p.cash_flow().name {
+            payees.push((normalize_payee(name), category));
+        }
+    }
+    let categories: BTreeSet<String> = totals.keys().map(|k| k.0.clone()).collect();
+
+    let mut unmatched = vec![];
+    for t in transactions {
+        check(&t.amount, format!("Transaction {} on {}", t.payee, t.date))?;
+        let payee = normalize_payee(&t.payee);
+        let category = t
+            .category
+            .as_ref()
+            .and_then(|c| categories.iter().find(|p| p.eq_ignore_ascii_case(c)))
+            .cloned()
+            .or_else(|| {
+                payees
+                    .iter()
+                    .find(|(name, _)| !name.is_empty() && payee.contains(name.as_str()))
+                    .map(|(_, category)| category.clone())
+            });
+        match category {
+            Some(category) => {
+                let total = totals
+                    .entry((category, period.start(t.date)))
+                    .or_insert(zero);
+                total.1 = total.1.checked_add(t.amount)?;
+            }
+            None => unmatched.push(t.clone()),
+        }
+    }
+
+    let mut rows = vec![];
+    let (first, last) = match (
+        totals.keys().map(|k| k.1).min(),
+        totals.keys().map(|k| k.1).max(),
+    ) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            return Ok(VarianceReport {
+                period,
+                currency,
+                rows,
+                unmatched,
+            })
+        }
+    };
+    for category in &categories {
+        let mut cumulative = Money::zero(currency);
+        let mut start = first;
+        while start <= last {
+            let (planned, actual) = totals
+                .get(&(category.clone(), start))
+                .copied()
+                .unwrap_or(zero);
+            let variance = actual.checked_add(-planned)?;
+            cumulative = cumulative.checked_add(variance)?;
+            rows.push(VarianceRow {
+                category: category.clone(),
+                period: period.label(start),
+                start_date: start,
+                planned,
+                actual,
+                variance,
+                cumulative_variance: cumulative,
+            });
+            start = period.next(start);
+        }
+    }
+    Ok(VarianceReport {
+        period,
+        currency,
+        rows,
+        unmatched,
+    })
+}
+
+impl VarianceReport {
+    /// Writes the rows as CSV, followed by the unmatched transactions with `Unmatched` as
+    /// their category.
+    pub fn write_csv(&self, file: &str) -> Result<()> {
+        let invalid = |e: csv::Error| Error::Invalid(format!("{}: {}", file, e));
+        let mut writer = csv::Writer::from_path(file).map_err(invalid)?;
+        writer
+            .write_record([
+                "Category",
+                "Period",
+                "Planned",
+                "Actual",
+                "Variance",
+                "Cumulative Variance",
+            ])
+            .map_err(invalid)?;
+        for r in &self.rows {
+            writer
+                .write_record([
+                    r.category.clone(),
+                    r.period.clone(),
+                    number(r.planned),
+                    number(r.actual),
+                    number(r.variance),
+                    number(r.cumulative_variance),
+                ])
+                .map_err(invalid)?;
+        }
+        for t in &self.unmatched {
+            writer
+                .write_record([
+                    "Unmatched".to_string(),
+                    format!("{} {}", t.date, t.payee),
+                    String::new(),
+                    number(t.amount),
+                    String::new(),
+                    String::new(),
+                ])
+                .map_err(invalid)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+// an amount with the currency's decimal places, and without its code
+fn number(amount: Money) -> String {
+    format!(
+        "{:.*}",
+        amount.currency().minor_units() as usize,
+        amount.amount()
+    )
+}
+
+#[cfg(test)]
+mod variance_tests {
+    use super::*;
+    use crate::sim::cash::{Account, CashFlow, Frequency};
+    use crate::sim::import::{import_file, CsvMapping};
+    use std::path::Path;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn transaction(
+        date: NaiveDate,
+        amount: f64,
+        payee: &str,
+        category: Option<&str>,
+    ) -> Transaction {
+        Transaction {
+            id: String::new(),
+            date,
+            amount: Money::try_from(amount).unwrap(),
+            payee: payee.to_string(),
+            memo: None,
+            category: category.map(|c| c.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_periods() {
+        let d = date(2020, 8, 17);
+        assert_eq!(Period::Quarter.start(d), date(2020, 7, 1));
+        assert_eq!(Period::Quarter.label(Period::Quarter.start(d)), "2020-Q3");
+        assert_eq!(Period::Year.next(date(2020, 1, 1)), date(2021, 1, 1));
+        assert_eq!("quarterly".parse::<Period>().unwrap(), Period::Quarter);
+    }
+
+    #[test]
+    fn test_budget_vs_actual() {
+        let mut rent = CashFlow::new(
+            Some("City Apartments".to_string()),
+            Money::try_from(-1500.0).unwrap(),
+            Some(Frequency::MonthStart),
+            None,
+            None,
+            None,
+        );
+        rent.category = Some("Housing".to_string());
+        let pay = CashFlow::new(
+            Some("Payroll".to_string()),
+            Money::try_from(3000.0).unwrap(),
+            Some(Frequency::MonthEnd),
+            None,
+            None,
+            Some(0.2),
+        );
+        let mut account = Account::new(
+            "Checking".to_string(),
+            Money::try_from(0.0).unwrap(),
+            vec![rent, pay],
+            date(2020, 1, 1),
+            date(2020, 3, 1),
+        );
+        let payments = account
+            .payments(date(2020, 1, 1), date(2020, 2, 29))
+            .unwrap()
+            .into_iter()
+            .collect::<PaymentTable>();
+        let transactions = vec![
+            transaction(date(2020, 1, 1), -1500.0, "CITY APARTMENTS #12", None),
+            transaction(date(2020, 1, 31), 2400.0, "ACME PAYROLL", None),
+            transaction(date(2020, 2, 3), -1550.0, "Landlord", Some("housing")),
+            transaction(date(2020, 2, 10), -40.0, "Cafe", None),
+        ];
+        let report = budget_vs_actual(&payments, &transactions, Period::Month).unwrap();
+        let row = |category: &str, period: &str| {
+            report
+                .rows
+                .iter()
+                .find(|r| r.category == category && r.period == period)
+                .map(|r| {
+                    (
+                        r.planned.to_f64(),
+                        r.actual.to_f64(),
+                        r.cumulative_variance.to_f64(),
+                    )
+                })
+                .unwrap()
+        };
+        assert_eq!(row("Housing", "2020-01"), (-1500.0, -1500.0, 0.0));
+        assert_eq!(row("Housing", "2020-02"), (-1500.0, -1550.0, -50.0));
+        // the tax on pay is planned with it, so the net deposit matches
+        assert_eq!(row("Payroll", "2020-01"), (2400.0, 2400.0, 0.0));
+        assert_eq!(row("Payroll", "2020-02"), (2400.0, 0.0, -2400.0));
+        assert_eq!(report.rows.len(), 4);
+        assert_eq!(report.unmatched.len(), 1);
+        assert_eq!(report.unmatched[0].payee, "Cafe");
+    }
+
+    #[test]
+    fn test_example() {
+        let dir = Path::new("./scenarios/examples/bank");
+        let mapping: CsvMapping = serde_yaml::from_str(
+            &std::fs::read_to_string(dir.join("checking_mapping.yaml")).unwrap(),
+        )
+        .unwrap();
+        let transactions = import_file(
+            &dir.join("history.csv"),
+            None,
+            Some(&mapping),
+            Currency::USD,
+        )
+        .unwrap();
+        let mut account: Account =
+            serde_yaml::from_str(&std::fs::read_to_string(dir.join("budget.yaml")).unwrap())
+                .unwrap();
+        let payments = account
+            .payments(date(2020, 1, 1), date(2020, 12, 31))
+            .unwrap()
+            .into_iter()
+            .collect::<PaymentTable>();
+        let report = budget_vs_actual(&payments, &transactions, Period::Quarter).unwrap();
+        let last = report
+            .rows
+            .iter()
+            .rfind(|r| r.category == "Housing")
+            .unwrap();
+        assert_eq!(last.period, "2020-Q4");
+        assert_eq!(last.cumulative_variance, Money::try_from(0.0).unwrap());
+        assert!(report
+            .unmatched
+            .iter()
+            .any(|t| t.payee == "CAR INSURANCE CO"));
+
+        let file = std::env::temp_dir().join("budget_variance_test.csv");
+        report.write_csv(file.to_str().unwrap()).unwrap();
+        let csv = std::fs::read_to_string(&file).unwrap();
+        assert!(csv.starts_with("Category,Period,Planned,Actual,Variance,Cumulative Variance\n"));
+        assert!(csv.contains("\nHousing,2020-Q1,-4500.00,-4500.00,0.00,0.00\n"));
+    }
+}